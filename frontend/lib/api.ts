@@ -460,7 +460,7 @@ class CedarAgentAPI {
   async patchEntityAttributes(
     entityType: string,
     entityId: string,
-    attributes: Record<string, string>,
+    attributes: Record<string, unknown>,
     parents?: Array<{ type: string; id: string }>
   ): Promise<Entity> {
     const body: any = {
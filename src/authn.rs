@@ -9,12 +9,39 @@ use rocket_okapi::request::{OpenApiFromRequest, RequestHeaderInput};
 use crate::config::Config;
 
 const AUTHENTICATION_HEADER: &'static str = "Authorization";
+/// Header naming the caller of a request, recorded as the claimed author of the changes it makes.
+const CALLER_HEADER: &str = "X-Caller";
+const MAX_CALLER_LENGTH: usize = 128;
 
-pub struct ApiKey(Option<String>);
+/// The API key of a request, along with the caller it claims to come from.
+pub struct ApiKey {
+    token: Option<String>,
+    caller: Option<String>,
+}
 
 impl ApiKey {
+    /// The caller the request claims to come from, if it was authenticated with the API key.
+    ///
+    /// The agent has a single API key, so callers sharing it name themselves with the
+    /// `X-Caller` header. Nothing ties the name to the key, so it is only advisory.
+    /// Without authentication configured the header is ignored.
+    pub fn claimed_caller(&self) -> Option<String> {
+        self.caller.clone()
+    }
+
+    fn caller_from_header(value: Option<&str>) -> Option<String> {
+        value
+            .map(str::trim)
+            .filter(|caller| {
+                !caller.is_empty()
+                    && caller.len() <= MAX_CALLER_LENGTH
+                    && caller.chars().all(|c| c.is_ascii_graphic() || c == ' ')
+            })
+            .map(str::to_owned)
+    }
+
     fn validate_matching_header(&self, request: &rocket::Request) -> bool {
-        let required_token = self.0.clone();
+        let required_token = self.token.clone();
         if required_token.is_none() {
             return true;
         }
@@ -31,19 +58,26 @@ impl<'r> FromRequest<'r> for ApiKey {
     type Error = ();
 
     async fn from_request(request: &'r rocket::Request<'_>) -> Outcome<Self, Self::Error> {
-        let token = request
-            .rocket()
-            .state::<Config>()
-            .map(|my_config| ApiKey(my_config.authentication.clone()));
+        let token = request.rocket().state::<Config>().map(|my_config| ApiKey {
+            token: my_config.authentication.clone(),
+            caller: None,
+        });
         match token {
-            Some(token) => {
+            Some(mut token) => {
                 if token.validate_matching_header(request) {
+                    if token.token.is_some() {
+                        token.caller =
+                            ApiKey::caller_from_header(request.headers().get_one(CALLER_HEADER));
+                    }
                     Outcome::Success(token)
                 } else {
                     Outcome::Error((rocket::http::Status::Unauthorized, ()))
                 }
             }
-            None => Outcome::Success(ApiKey(None)),
+            None => Outcome::Success(ApiKey {
+                token: None,
+                caller: None,
+            }),
         }
     }
 }
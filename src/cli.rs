@@ -0,0 +1,361 @@
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+
+use cedar_policy::Authorizer;
+use cedar_policy_formatter::policies_str_to_pretty;
+use rocket::serde::json::serde_json::{self, json, Value};
+
+use crate::config::{Command, Config};
+use crate::schemas::authorization::{AuthorizationAnswer, AuthorizationCall, AuthorizationRequest};
+use crate::schemas::replay::ReplayCandidate;
+use crate::services::authorization::replay::{self, ReplayTarget};
+use crate::services::data::load_from_file::load_entities_from_file;
+use crate::services::data::memory::MemoryDataStore;
+use crate::services::data::DataStore;
+use crate::services::policies::load_from_file::load_policies_from_file;
+use crate::services::policies::memory::MemoryPolicyStore;
+use crate::services::policies::PolicyStore;
+use crate::services::schema::load_from_file::load_schema_from_file;
+use crate::services::schema::memory::MemorySchemaStore;
+use crate::services::schema::SchemaStore;
+
+/// Stores loaded from the schema, data and policies files of the configuration, with the
+/// same rules as the loaders of the server.
+pub(crate) struct FileStores {
+    pub schema_store: MemorySchemaStore,
+    pub data_store: MemoryDataStore,
+    pub policy_store: MemoryPolicyStore,
+}
+
+impl FileStores {
+    /// Load every configured file, returning the errors of all the files that failed.
+    pub(crate) async fn load(config: &Config) -> Result<Self, Vec<String>> {
+        let stores = FileStores {
+            schema_store: MemorySchemaStore::new(),
+            data_store: match config.data_integrity {
+                Some(integrity) => MemoryDataStore::with_integrity(integrity),
+                None => MemoryDataStore::new(),
+            },
+            policy_store: MemoryPolicyStore::new(),
+        };
+        let mut errors = Vec::new();
+
+        if let Some(path) = &config.schema {
+            let loaded = load_schema_from_file(path.clone())
+                .await
+                .map_err(|err| err.to_string());
+            let updated = match loaded {
+                Ok(schema) => stores
+                    .schema_store
+                    .update_schema(schema)
+                    .await
+                    .map(|_| ())
+                    .map_err(|err| err.to_string()),
+                Err(err) => Err(err),
+            };
+            if let Err(err) = updated {
+                errors.push(format!("Schema {}: {}", path.display(), err));
+            }
+        }
+        let schema = stores.schema_store.get_cedar_schema().await;
+
+        if let Some(path) = &config.data {
+            let loaded = load_entities_from_file(path.clone())
+                .await
+                .map_err(|err| err.to_string());
+            let updated = match loaded {
+                Ok(entities) => stores
+                    .data_store
+                    .update_entities(entities, schema.clone())
+                    .await
+                    .map(|_| ())
+                    .map_err(|err| err.to_string()),
+                Err(err) => Err(err),
+            };
+            if let Err(err) = updated {
+                errors.push(format!("Data {}: {}", path.display(), err));
+            }
+        }
+
+        if let Some(path) = &config.policies {
+            let loaded = load_policies_from_file(path.clone())
+                .await
+                .map_err(|err| err.to_string());
+            let updated = match loaded {
+                Ok(policies) => stores
+                    .policy_store
+                    .update_policies(policies.into_inner(), schema)
+                    .await
+                    .map(|_| ())
+                    .map_err(|err| err.to_string()),
+                Err(err) => Err(err),
+            };
+            if let Err(err) = updated {
+                errors.push(format!("Policies {}: {}", path.display(), err));
+            }
+        }
+
+        match errors.is_empty() {
+            true => Ok(stores),
+            false => Err(errors),
+        }
+    }
+}
+
+/// Read authorization requests from a JSONL file, or from a JSON file of a request or an
+/// array of requests.
+pub(crate) fn read_requests(path: &Path) -> Result<Vec<AuthorizationCall>, Box<dyn Error>> {
+    let contents = fs::read_to_string(path)
+        .map_err(|err| format!("Failed to read {}: {}", path.display(), err))?;
+    if path.extension().is_some_and(|ext| ext == "jsonl") {
+        return contents
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| !line.trim().is_empty())
+            .map(|(number, line)| {
+                serde_json::from_str(line).map_err(|err| {
+                    format!("Invalid request on line {}: {}", number + 1, err).into()
+                })
+            })
+            .collect();
+    }
+    match serde_json::from_str(&contents)? {
+        Value::Array(requests) => Ok(requests
+            .into_iter()
+            .map(serde_json::from_value)
+            .collect::<Result<_, _>>()?),
+        request => Ok(vec![serde_json::from_value(request)?]),
+    }
+}
+
+fn report_errors(errors: &[String]) -> ExitCode {
+    for error in errors {
+        eprintln!("{}", error);
+    }
+    ExitCode::FAILURE
+}
+
+async fn validate(config: &Config) -> ExitCode {
+    let files = [
+        ("Schema", &config.schema),
+        ("Data", &config.data),
+        ("Policies", &config.policies),
+    ];
+    if files.iter().all(|(_, path)| path.is_none()) {
+        eprintln!("Nothing to validate, pass --schema, --data or --policies");
+        return ExitCode::FAILURE;
+    }
+    match FileStores::load(config).await {
+        Ok(_) => {
+            for (kind, path) in files {
+                if let Some(path) = path {
+                    println!("{} {} is valid", kind, path.display());
+                }
+            }
+            ExitCode::SUCCESS
+        }
+        Err(errors) => report_errors(&errors),
+    }
+}
+
+async fn authorize(config: &Config, requests: &Path) -> ExitCode {
+    let calls = match read_requests(requests) {
+        Ok(calls) => calls,
+        Err(err) => return report_errors(&[err.to_string()]),
+    };
+    let stores = match FileStores::load(config).await {
+        Ok(stores) => stores,
+        Err(errors) => return report_errors(&errors),
+    };
+    let authorizer = Authorizer::new();
+    let policies = stores.policy_store.policy_set().await;
+    let entities = stores.data_store.entities().await;
+
+    // One answer per line, in the order of the requests
+    let mut result = ExitCode::SUCCESS;
+    for call in calls {
+        let decided = TryInto::<AuthorizationRequest>::try_into(call).and_then(|request| {
+            Ok(request
+                .get_request_entities(entities.clone())
+                .map(|(request, entities)| {
+                    authorizer.is_authorized(&request, &policies, &entities)
+                })?)
+        });
+        let line = match decided {
+            Ok(response) => json!(AuthorizationAnswer::from(response)),
+            Err(err) => {
+                result = ExitCode::FAILURE;
+                json!({ "error": err.to_string() })
+            }
+        };
+        println!("{}", line);
+    }
+    result
+}
+
+/// Read the candidate files, which are validated when applied to the baseline.
+async fn read_candidate(
+    schema: &Option<PathBuf>,
+    data: &Option<PathBuf>,
+    policies: &Option<PathBuf>,
+) -> Result<ReplayCandidate, String> {
+    let mut candidate = ReplayCandidate::default();
+    if let Some(path) = schema {
+        let loaded = load_schema_from_file(path.clone()).await;
+        candidate.schema =
+            Some(loaded.map_err(|err| format!("Schema {}: {}", path.display(), err))?);
+    }
+    if let Some(path) = data {
+        let loaded = load_entities_from_file(path.clone()).await;
+        candidate.entities =
+            Some(loaded.map_err(|err| format!("Data {}: {}", path.display(), err))?);
+    }
+    if let Some(path) = policies {
+        let loaded = load_policies_from_file(path.clone()).await;
+        candidate.policies = Some(
+            loaded
+                .map_err(|err| format!("Policies {}: {}", path.display(), err))?
+                .into_inner(),
+        );
+    }
+    Ok(candidate)
+}
+
+async fn replay_requests(
+    config: &Config,
+    requests: &Path,
+    candidate_files: [&Option<PathBuf>; 3],
+    fail_on_diff: bool,
+) -> ExitCode {
+    let calls = match read_requests(requests) {
+        Ok(calls) => calls,
+        Err(err) => return report_errors(&[err.to_string()]),
+    };
+    let stores = match FileStores::load(config).await {
+        Ok(stores) => stores,
+        Err(errors) => return report_errors(&errors),
+    };
+    let baseline = ReplayTarget {
+        policies: stores.policy_store.policy_set().await,
+        entities: stores.data_store.entities().await,
+    };
+    let [schema, data, policies] = candidate_files;
+    let candidate = if candidate_files.iter().any(|path| path.is_some()) {
+        let applied = match read_candidate(schema, data, policies).await {
+            Ok(candidate) => {
+                baseline
+                    .with_candidate(
+                        candidate,
+                        stores.schema_store.get_cedar_schema().await,
+                        config.data_integrity,
+                    )
+                    .await
+            }
+            Err(err) => Err(err),
+        };
+        match applied {
+            Ok(candidate) => Some(candidate),
+            Err(err) => return report_errors(&[err]),
+        }
+    } else {
+        None
+    };
+
+    let report = replay::replay(&Authorizer::new(), &calls, &baseline, candidate.as_ref());
+    match serde_json::to_string_pretty(&report) {
+        Ok(printed) => println!("{}", printed),
+        Err(err) => return report_errors(&[err.to_string()]),
+    }
+    if fail_on_diff && !report.diffs.is_empty() {
+        return report_errors(&[format!(
+            "{} of {} decisions differ with the candidate",
+            report.diffs.len(),
+            report.requests
+        )]);
+    }
+    ExitCode::SUCCESS
+}
+
+/// Format the content of the policies in a file, leaving their other fields as they are,
+/// along with the IDs of the policies that were not in the canonical format.
+///
+/// The policies are kept as JSON rather than parsed, as the fields that only appear in
+/// responses would be added to the output and the ones ignored in requests dropped.
+fn format_policies(path: &PathBuf) -> Result<(Vec<Value>, Vec<String>), Box<dyn Error>> {
+    let contents = fs::read_to_string(path)
+        .map_err(|err| format!("Failed to read {}: {}", path.display(), err))?;
+    let mut policies: Vec<Value> = serde_json::from_str(&contents)
+        .map_err(|err| format!("Failed to deserialize JSON: {}", err))?;
+    let config = cedar_policy_formatter::Config::default();
+    let mut unformatted = Vec::new();
+    for (index, policy) in policies.iter_mut().enumerate() {
+        let id = match policy.get("id").and_then(Value::as_str) {
+            Some(id) => id.to_owned(),
+            None => format!("at index {}", index),
+        };
+        let Some(content) = policy.get_mut("content") else {
+            return Err(format!("Policy {} has no content", id).into());
+        };
+        let Some(stored) = content.as_str() else {
+            return Err(format!("The content of policy {} is not a string", id).into());
+        };
+        let formatted = policies_str_to_pretty(stored, &config)
+            .map_err(|err| format!("Failed to format policy {}: {}", id, err))?
+            .trim_end()
+            .to_owned();
+        if formatted != stored {
+            unformatted.push(id);
+            *content = Value::String(formatted);
+        }
+    }
+    Ok((policies, unformatted))
+}
+
+fn format(config: &Config, check: bool) -> ExitCode {
+    let Some(path) = &config.policies else {
+        eprintln!("Nothing to format, pass --policies");
+        return ExitCode::FAILURE;
+    };
+    match format_policies(path) {
+        Ok((_, unformatted)) if check && !unformatted.is_empty() => report_errors(&[format!(
+            "Policies not in the canonical format: {}",
+            unformatted.join(", ")
+        )]),
+        Ok(_) if check => ExitCode::SUCCESS,
+        Ok((policies, _)) => match serde_json::to_string_pretty(&policies) {
+            Ok(printed) => {
+                println!("{}", printed);
+                ExitCode::SUCCESS
+            }
+            Err(err) => report_errors(&[err.to_string()]),
+        },
+        Err(err) => report_errors(&[err.to_string()]),
+    }
+}
+
+/// Run a command that works on the configured files without starting the server.
+pub async fn run(command: &Command, config: &Config) -> ExitCode {
+    match command {
+        Command::Serve => ExitCode::SUCCESS,
+        Command::Validate => validate(config).await,
+        Command::Authorize { requests } => authorize(config, requests).await,
+        Command::Replay {
+            requests,
+            candidate_schema,
+            candidate_data,
+            candidate_policies,
+            fail_on_diff,
+        } => {
+            replay_requests(
+                config,
+                requests,
+                [candidate_schema, candidate_data, candidate_policies],
+                *fail_on_diff,
+            )
+            .await
+        }
+        Command::Format { check } => format(config, *check),
+    }
+}
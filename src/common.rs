@@ -17,7 +17,7 @@ impl Display for EmptyError {
     }
 }
 
-pub(crate) struct DefaultContentType(ContentType);
+pub struct DefaultContentType(ContentType);
 
 impl DefaultContentType {
     /// Set a default content type for incoming messages
@@ -1,16 +1,91 @@
 use fmt::Debug;
 use std::borrow::Borrow;
 use std::fmt;
-use std::path::PathBuf;
+use std::fs;
+use std::path::{Path, PathBuf};
 
-use clap::Parser;
+use clap::{Parser, Subcommand, ValueEnum};
 use log::LevelFilter;
+use thiserror::Error;
 
 use serde::{Deserialize, Serialize};
 
+use crate::schemas::data::IntegrityMode;
+
+const REDACTED: &str = "<redacted>";
+/// Environment variables only read with the `CEDAR_AGENT_` prefix
+const PREFIXED_ONLY: [&str; 2] = ["CONFIG", "PRINT_CONFIG"];
+
+#[derive(ValueEnum, Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    Text,
+    Json,
+}
+
+#[derive(Debug, Error)]
+pub enum ConfigError {
+    #[error("Failed to read config file {0}: {1}")]
+    Read(String, std::io::Error),
+    #[error("Failed to parse TOML config file {0}: {1}")]
+    Toml(String, toml::de::Error),
+    #[error("Failed to parse YAML config file {0}: {1}")]
+    Yaml(String, serde_yaml::Error),
+    #[error("Unsupported config file {0}, expected a .toml, .yaml or .yml file")]
+    UnsupportedFormat(String),
+}
+
+/// Commands of the binary, which serves the API unless another command is given.
+///
+/// The other commands work on the `--schema`, `--data` and `--policies` files.
+#[derive(Subcommand, Debug, Clone, PartialEq)]
+pub enum Command {
+    /// Run the agent server
+    Serve,
+    /// Validate the schema, data and policies files with the same rules as the loaders
+    Validate,
+    /// Authorize requests against the schema, data and policies files, printing one answer per line
+    Authorize {
+        /// A JSON file of a request or an array of requests, or a .jsonl file of one request per line
+        requests: PathBuf,
+    },
+    /// Replay requests against the schema, data and policies files, comparing the decisions
+    /// with the candidate files if any are given
+    Replay {
+        /// A JSON file of a request or an array of requests, or a .jsonl file of one request per line
+        requests: PathBuf,
+        /// Schema file replacing --schema for the candidate
+        #[arg(long)]
+        candidate_schema: Option<PathBuf>,
+        /// Data file replacing --data for the candidate
+        #[arg(long)]
+        candidate_data: Option<PathBuf>,
+        /// Policies file replacing --policies for the candidate
+        #[arg(long)]
+        candidate_policies: Option<PathBuf>,
+        /// Exit with a non-zero status if a decision differs with the candidate
+        #[arg(long)]
+        fail_on_diff: bool,
+    },
+    /// Print the policies file with the policies in the canonical Cedar format
+    Format {
+        /// Only check that every policy is in the canonical format
+        #[arg(long)]
+        check: bool,
+    },
+}
+
 #[derive(Parser, Serialize, Deserialize, Debug)]
 #[command(author, version, about, long_about = None)]
 pub struct Config {
+    #[command(subcommand)]
+    #[serde(skip)]
+    pub command: Option<Command>,
+    #[arg(short, long)]
+    pub config: Option<PathBuf>,
+    #[arg(long)]
+    #[serde(default, skip_serializing)]
+    pub print_config: bool,
     #[arg(short, long)]
     pub authentication: Option<String>,
     #[arg(long)]
@@ -19,12 +94,38 @@ pub struct Config {
     pub port: Option<u16>,
     #[arg(short, long, value_enum)]
     pub log_level: Option<LevelFilter>,
-    #[arg(short, long)]
-    pub data: Option<PathBuf>,
+    #[arg(long, value_enum)]
+    pub log_format: Option<LogFormat>,
     #[arg(long)]
+    pub log_file: Option<PathBuf>,
+    #[arg(long)]
+    pub log_file_max_size: Option<u64>,
+    #[arg(long)]
+    pub log_file_count: Option<u32>,
+    #[arg(short, long, global = true)]
+    pub data: Option<PathBuf>,
+    #[arg(long, global = true)]
     pub policies: Option<PathBuf>,
-    #[arg(short, long)]
+    #[arg(short, long, global = true)]
     pub schema: Option<PathBuf>,
+    #[arg(long, num_args = 0..=1, require_equals = true, default_missing_value = "true")]
+    pub cors_disabled: Option<bool>,
+    #[arg(long, value_delimiter = ',')]
+    pub cors_allowed_origins: Option<Vec<String>>,
+    #[arg(long, value_delimiter = ',')]
+    pub cors_allowed_methods: Option<Vec<String>>,
+    #[arg(long, value_delimiter = ',')]
+    pub cors_allowed_headers: Option<Vec<String>>,
+    #[arg(long, num_args = 0..=1, require_equals = true, default_missing_value = "true")]
+    pub cors_allow_credentials: Option<bool>,
+    #[arg(long, value_enum, global = true)]
+    pub data_integrity: Option<IntegrityMode>,
+    #[arg(long)]
+    pub decision_cache_size: Option<usize>,
+    #[arg(long)]
+    pub decision_cache_ttl: Option<u64>,
+    #[arg(long, num_args = 0..=1, require_equals = true, default_missing_value = "true")]
+    pub policy_tests_gate: Option<bool>,
 }
 
 impl Into<rocket::figment::Figment> for &Config {
@@ -58,26 +159,59 @@ impl Into<rocket::figment::Figment> for &Config {
 impl Config {
     fn new() -> Self {
         Config {
+            command: None,
+            config: None,
+            print_config: false,
             authentication: None,
             addr: None,
             port: None,
             log_level: None,
+            log_format: None,
+            log_file: None,
+            log_file_max_size: None,
+            log_file_count: None,
             data: None,
             policies: None,
             schema: None,
+            cors_disabled: None,
+            cors_allowed_origins: None,
+            cors_allowed_methods: None,
+            cors_allowed_headers: None,
+            cors_allow_credentials: None,
+            data_integrity: None,
+            decision_cache_size: None,
+            decision_cache_ttl: None,
+            policy_tests_gate: None,
         }
     }
 
     fn merge(configs: Vec<Config>) -> Config {
         let mut config = Config::new();
         for c in configs {
+            config.command = c.command.or(config.command);
+            config.config = c.config.or(config.config);
+            config.print_config = c.print_config || config.print_config;
             config.authentication = c.authentication.or(config.authentication);
             config.addr = c.addr.or(config.addr);
             config.port = c.port.or(config.port);
             config.log_level = c.log_level.or(config.log_level);
+            config.log_format = c.log_format.or(config.log_format);
+            config.log_file = c.log_file.or(config.log_file);
+            config.log_file_max_size = c.log_file_max_size.or(config.log_file_max_size);
+            config.log_file_count = c.log_file_count.or(config.log_file_count);
             config.data = c.data.or(config.data);
             config.policies = c.policies.or(config.policies);
             config.schema = c.schema.or(config.schema);
+            config.cors_disabled = c.cors_disabled.or(config.cors_disabled);
+            config.cors_allowed_origins = c.cors_allowed_origins.or(config.cors_allowed_origins);
+            config.cors_allowed_methods = c.cors_allowed_methods.or(config.cors_allowed_methods);
+            config.cors_allowed_headers = c.cors_allowed_headers.or(config.cors_allowed_headers);
+            config.cors_allow_credentials =
+                c.cors_allow_credentials.or(config.cors_allow_credentials);
+            config.data_integrity = c.data_integrity.or(config.data_integrity);
+            config.decision_cache_size = c.decision_cache_size.or(config.decision_cache_size);
+            config.decision_cache_ttl = c.decision_cache_ttl.or(config.decision_cache_ttl);
+            config.policy_tests_gate = c.policy_tests_gate.or(config.policy_tests_gate);
         }
 
         config
@@ -88,22 +222,214 @@ impl Config {
     }
 
     fn from_env() -> Self {
-        let old_env = match envy::from_env() {
+        Self::from_vars(std::env::vars().collect())
+    }
+
+    fn from_vars(vars: Vec<(String, String)>) -> Self {
+        // Generic names like `CONFIG` are common in container environments, so these
+        // settings are only read with the prefix
+        let unprefixed = vars
+            .iter()
+            .filter(|(key, _)| !PREFIXED_ONLY.contains(&key.to_uppercase().as_str()))
+            .cloned();
+        let old_env = match envy::from_iter(unprefixed) {
             Ok(env) => env,
             Err(_) => Self::new(),
         };
-        let env = match envy::prefixed("CEDAR_AGENT_").from_env() {
+        let env = match envy::prefixed("CEDAR_AGENT_").from_iter(vars) {
             Ok(env) => env,
             Err(_) => Self::new(),
         };
 
         Config::merge(vec![old_env, env])
     }
+
+    fn from_file(path: &Path) -> Result<Self, ConfigError> {
+        let display = path.display().to_string();
+        let contents =
+            fs::read_to_string(path).map_err(|err| ConfigError::Read(display.clone(), err))?;
+        let file: ConfigFile = match path.extension().and_then(|ext| ext.to_str()) {
+            Some("toml") => {
+                toml::from_str(&contents).map_err(|err| ConfigError::Toml(display, err))?
+            }
+            Some("yaml") | Some("yml") => {
+                serde_yaml::from_str(&contents).map_err(|err| ConfigError::Yaml(display, err))?
+            }
+            _ => return Err(ConfigError::UnsupportedFormat(display)),
+        };
+
+        Ok(file.into())
+    }
+
+    /// Render the effective configuration in the config file layout, with secrets redacted.
+    pub fn to_redacted_toml(&self) -> Result<String, toml::ser::Error> {
+        let mut file = ConfigFile::from(self);
+        if file.auth.authentication.is_some() {
+            file.auth.authentication = Some(REDACTED.to_owned());
+        }
+        toml::to_string_pretty(&file)
+    }
+}
+
+/// Layout of the configuration file, grouping the options of `Config` into sections.
+#[derive(Serialize, Deserialize, Debug, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct ConfigFile {
+    pub server: ServerSection,
+    pub auth: AuthSection,
+    pub stores: StoresSection,
+    pub decision_cache: DecisionCacheSection,
+    pub policy_tests: PolicyTestsSection,
+    pub logging: LoggingSection,
+    pub loaders: LoadersSection,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerSection {
+    pub addr: Option<String>,
+    pub port: Option<u16>,
+    pub cors: CorsSection,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct CorsSection {
+    pub disabled: Option<bool>,
+    pub allowed_origins: Option<Vec<String>>,
+    pub allowed_methods: Option<Vec<String>>,
+    pub allowed_headers: Option<Vec<String>>,
+    pub allow_credentials: Option<bool>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct AuthSection {
+    pub authentication: Option<String>,
+}
+
+/// Settings of the policy, data and schema stores.
+#[derive(Serialize, Deserialize, Debug, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct StoresSection {
+    pub integrity: Option<IntegrityMode>,
+}
+
+/// Settings of the cache of authorization decisions, disabled unless a size is set.
+#[derive(Serialize, Deserialize, Debug, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct DecisionCacheSection {
+    pub size: Option<usize>,
+    pub ttl: Option<u64>,
+}
+
+/// Settings of the stored policy test cases.
+#[derive(Serialize, Deserialize, Debug, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct PolicyTestsSection {
+    pub gate: Option<bool>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct LoggingSection {
+    pub level: Option<LevelFilter>,
+    pub format: Option<LogFormat>,
+    pub file: Option<PathBuf>,
+    pub file_max_size: Option<u64>,
+    pub file_count: Option<u32>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct LoadersSection {
+    pub schema: Option<PathBuf>,
+    pub data: Option<PathBuf>,
+    pub policies: Option<PathBuf>,
+}
+
+impl From<ConfigFile> for Config {
+    fn from(file: ConfigFile) -> Self {
+        Config {
+            authentication: file.auth.authentication,
+            addr: file.server.addr,
+            port: file.server.port,
+            log_level: file.logging.level,
+            log_format: file.logging.format,
+            log_file: file.logging.file,
+            log_file_max_size: file.logging.file_max_size,
+            log_file_count: file.logging.file_count,
+            data: file.loaders.data,
+            policies: file.loaders.policies,
+            schema: file.loaders.schema,
+            cors_disabled: file.server.cors.disabled,
+            cors_allowed_origins: file.server.cors.allowed_origins,
+            cors_allowed_methods: file.server.cors.allowed_methods,
+            cors_allowed_headers: file.server.cors.allowed_headers,
+            cors_allow_credentials: file.server.cors.allow_credentials,
+            data_integrity: file.stores.integrity,
+            decision_cache_size: file.decision_cache.size,
+            decision_cache_ttl: file.decision_cache.ttl,
+            policy_tests_gate: file.policy_tests.gate,
+            ..Config::new()
+        }
+    }
+}
+
+impl From<&Config> for ConfigFile {
+    fn from(config: &Config) -> Self {
+        ConfigFile {
+            server: ServerSection {
+                addr: config.addr.clone(),
+                port: config.port,
+                cors: CorsSection {
+                    disabled: config.cors_disabled,
+                    allowed_origins: config.cors_allowed_origins.clone(),
+                    allowed_methods: config.cors_allowed_methods.clone(),
+                    allowed_headers: config.cors_allowed_headers.clone(),
+                    allow_credentials: config.cors_allow_credentials,
+                },
+            },
+            auth: AuthSection {
+                authentication: config.authentication.clone(),
+            },
+            stores: StoresSection {
+                integrity: config.data_integrity,
+            },
+            decision_cache: DecisionCacheSection {
+                size: config.decision_cache_size,
+                ttl: config.decision_cache_ttl,
+            },
+            policy_tests: PolicyTestsSection {
+                gate: config.policy_tests_gate,
+            },
+            logging: LoggingSection {
+                level: config.log_level,
+                format: config.log_format,
+                file: config.log_file.clone(),
+                file_max_size: config.log_file_max_size,
+                file_count: config.log_file_count,
+            },
+            loaders: LoadersSection {
+                schema: config.schema.clone(),
+                data: config.data.clone(),
+                policies: config.policies.clone(),
+            },
+        }
+    }
+}
+
+/// Build the configuration, where command line arguments take precedence over
+/// environment variables, which take precedence over the configuration file.
+pub fn init() -> Result<Config, ConfigError> {
+    resolve(Config::from_args(), Config::from_env())
 }
 
-pub fn init() -> Config {
-    let args = Config::from_args();
-    let env = Config::from_env();
+fn resolve(args: Config, env: Config) -> Result<Config, ConfigError> {
+    let file = match args.config.as_ref().or(env.config.as_ref()) {
+        Some(path) => Config::from_file(path)?,
+        None => Config::new(),
+    };
 
-    Config::merge(vec![args, env])
+    Ok(Config::merge(vec![file, env, args]))
 }
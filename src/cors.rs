@@ -0,0 +1,90 @@
+use std::collections::HashSet;
+use std::str::FromStr;
+
+use log::{info, warn};
+use rocket_cors::{AllowedHeaders, AllowedMethods, AllowedOrigins, Cors, CorsOptions, Method};
+use thiserror::Error;
+
+use crate::config;
+use crate::request_id::REQUEST_ID_HEADER;
+
+const WILDCARD: &str = "*";
+const DEFAULT_METHODS: [&str; 6] = ["GET", "POST", "PUT", "PATCH", "DELETE", "OPTIONS"];
+const DEFAULT_HEADERS: [&str; 3] = ["Authorization", "Content-Type", REQUEST_ID_HEADER];
+
+#[derive(Debug, Error)]
+pub enum CorsConfigError {
+    #[error("Unknown HTTP method '{0}' in the allowed CORS methods")]
+    InvalidMethod(String),
+    #[error("CORS credentials can not be allowed together with a wildcard origin, configure explicit origins instead")]
+    CredentialsWithWildcardOrigin,
+    #[error("Invalid CORS configuration: {0}")]
+    InvalidOptions(rocket_cors::Error),
+}
+
+fn allowed_origins(origins: &Option<Vec<String>>) -> AllowedOrigins {
+    match origins {
+        Some(origins) if origins.iter().any(|o| o == WILDCARD) => AllowedOrigins::all(),
+        Some(origins) => AllowedOrigins::some_exact(origins),
+        None => AllowedOrigins::some_exact::<&str>(&[]),
+    }
+}
+
+fn allowed_methods(methods: &Option<Vec<String>>) -> Result<AllowedMethods, CorsConfigError> {
+    let methods: Vec<String> = match methods {
+        Some(methods) => methods.clone(),
+        None => DEFAULT_METHODS.iter().map(|m| m.to_string()).collect(),
+    };
+    methods
+        .iter()
+        .map(|m| {
+            Method::from_str(&m.trim().to_uppercase())
+                .map_err(|_| CorsConfigError::InvalidMethod(m.clone()))
+        })
+        .collect()
+}
+
+fn allowed_headers(headers: &Option<Vec<String>>) -> AllowedHeaders {
+    match headers {
+        Some(headers) if headers.iter().any(|h| h == WILDCARD) => AllowedHeaders::all(),
+        Some(headers) => {
+            AllowedHeaders::some(&headers.iter().map(|h| h.as_str()).collect::<Vec<&str>>())
+        }
+        None => AllowedHeaders::some(&DEFAULT_HEADERS),
+    }
+}
+
+/// Build the CORS fairing from the configuration.
+///
+/// Returns `None` when CORS is disabled, e.g. for headless deployments that are never
+/// called from a browser. When no origins are configured no cross-origin request is
+/// allowed, `*` allows every origin, and credentials are only permitted for an explicit
+/// list of origins.
+pub fn init(conf: &config::Config) -> Result<Option<Cors>, CorsConfigError> {
+    if conf.cors_disabled.unwrap_or(false) {
+        info!("CORS is disabled");
+        return Ok(None);
+    }
+
+    let origins = allowed_origins(&conf.cors_allowed_origins);
+    let allow_credentials = conf.cors_allow_credentials.unwrap_or(false);
+    if allow_credentials && origins.is_all() {
+        return Err(CorsConfigError::CredentialsWithWildcardOrigin);
+    }
+    if origins.is_all() {
+        warn!("CORS allows requests from any origin, set allowed origins to restrict it");
+    } else if conf.cors_allowed_origins.is_none() {
+        info!("CORS allows no cross-origin requests, set allowed origins to allow them");
+    }
+
+    let cors = CorsOptions::default()
+        .allowed_origins(origins)
+        .allowed_methods(allowed_methods(&conf.cors_allowed_methods)?)
+        .allowed_headers(allowed_headers(&conf.cors_allowed_headers))
+        .expose_headers(HashSet::from([REQUEST_ID_HEADER.to_owned()]))
+        .allow_credentials(allow_credentials);
+
+    cors.to_cors()
+        .map(Some)
+        .map_err(CorsConfigError::InvalidOptions)
+}
@@ -13,9 +13,13 @@ use rocket_okapi::{okapi, OpenApiError};
 use serde::Serialize;
 use thiserror::Error;
 
-use schemas::{bad_request_response, unauthorized_response};
+use schemas::{
+    bad_request_response, conflict_response, not_found_response, unauthorized_response,
+    unprocessable_entity_response,
+};
 
 use crate::errors::schemas;
+use crate::services::errors::StoreError;
 
 /// Error messages returned to user
 #[derive(Debug, Serialize, JsonSchema)]
@@ -46,20 +50,44 @@ pub enum AgentError {
     NotFound { object: &'static str, id: String },
     #[error("{} with the given id({}) already exists", object, id)]
     Duplicate { object: &'static str, id: String },
+    #[error("The request conflicts with the current state: {}", reason)]
+    Conflict { reason: String },
     #[error(
         "The content in the request does not match the specifications: {}",
         reason
     )]
     BadRequest { reason: String },
+    #[error(
+        "The content in the request does not conform to the schema: {}",
+        reason
+    )]
+    Validation { reason: String },
+}
+
+impl From<StoreError> for AgentError {
+    fn from(err: StoreError) -> Self {
+        match err {
+            StoreError::NotFound { object, id } => AgentError::NotFound { object, id },
+            StoreError::Duplicate { object, id } => AgentError::Duplicate { object, id },
+            StoreError::Conflict { reason } => AgentError::Conflict { reason },
+            StoreError::Validation { reason } => AgentError::Validation { reason },
+            err => AgentError::BadRequest {
+                reason: err.to_string(),
+            },
+        }
+    }
 }
 
 impl AgentError {
-    fn status(&self) -> Status {
+    /// The HTTP status the error is returned with.
+    pub fn status(&self) -> Status {
         use self::AgentError::*;
         match self {
             NotFound { object: _, id: _ } => Status::NotFound,
             Duplicate { object: _, id: _ } => Status::Conflict,
+            Conflict { reason: _ } => Status::Conflict,
             BadRequest { reason: _ } => Status::BadRequest,
+            Validation { reason: _ } => Status::UnprocessableEntity,
         }
     }
 
@@ -74,8 +102,12 @@ impl AgentError {
             "You are not authorized to perform this action".to_owned()
         } else if status == Status::NotFound {
             "The requested resource was not found".to_owned()
+        } else if let AgentError::Conflict { reason: _ } = self {
+            "The request conflicts with the current state of the resource".to_owned()
         } else if status == Status::Conflict {
             "The requested resource already exists".to_owned()
+        } else if status == Status::UnprocessableEntity {
+            "The request does not conform to the schema".to_owned()
         } else if status.code >= 400 && status.code < 500 {
             "An unexpected client error has occurred".to_owned()
         } else {
@@ -112,6 +144,9 @@ impl OpenApiResponderInner for AgentError {
             responses: okapi::map! {
                 "400".to_owned() => RefOr::Object(bad_request_response(gen)),
                 "401".to_owned() => RefOr::Object(unauthorized_response(gen)),
+                "404".to_owned() => RefOr::Object(not_found_response(gen)),
+                "409".to_owned() => RefOr::Object(conflict_response(gen)),
+                "422".to_owned() => RefOr::Object(unprocessable_entity_response(gen)),
             },
             ..Default::default()
         })
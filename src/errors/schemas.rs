@@ -42,3 +42,58 @@ pub fn unauthorized_response(gen: &mut OpenApiGenerator) -> okapi::openapi3::Res
         ..Default::default()
     }
 }
+
+pub fn not_found_response(gen: &mut OpenApiGenerator) -> okapi::openapi3::Response {
+    let schema = gen.json_schema::<ErrorResponse>();
+    okapi::openapi3::Response {
+        description: "\
+        # 404 Not Found\n\
+        The requested resource does not exist. \
+        "
+        .to_owned(),
+        content: okapi::map! {
+            "application/json".to_owned() => MediaType {
+                schema: Some(schema),
+                ..Default::default()
+            }
+        },
+        ..Default::default()
+    }
+}
+
+pub fn conflict_response(gen: &mut OpenApiGenerator) -> okapi::openapi3::Response {
+    let schema = gen.json_schema::<ErrorResponse>();
+    okapi::openapi3::Response {
+        description: "\
+        # 409 Conflict\n\
+        A resource with the given ID already exists, or the request conflicts with the \
+        current state of the resource. \
+        "
+        .to_owned(),
+        content: okapi::map! {
+            "application/json".to_owned() => MediaType {
+                schema: Some(schema),
+                ..Default::default()
+            }
+        },
+        ..Default::default()
+    }
+}
+
+pub fn unprocessable_entity_response(gen: &mut OpenApiGenerator) -> okapi::openapi3::Response {
+    let schema = gen.json_schema::<ErrorResponse>();
+    okapi::openapi3::Response {
+        description: "\
+        # 422 Unprocessable Entity\n\
+        The content given does not conform to the schema. \
+        "
+        .to_owned(),
+        content: okapi::map! {
+            "application/json".to_owned() => MediaType {
+                schema: Some(schema),
+                ..Default::default()
+            }
+        },
+        ..Default::default()
+    }
+}
@@ -1,11 +1,20 @@
 #![allow(dead_code)]
 
-mod authn;
-mod common;
-mod config;
-mod errors;
-mod routes;
+pub mod authn;
+pub mod cli;
+pub mod common;
+pub mod config;
+pub mod cors;
+pub mod errors;
+pub mod logger;
+pub mod request_id;
+pub mod routes;
 pub mod schemas;
 mod services;
 
+pub use errors::response::AgentError;
+pub use services::data::DataStore;
+pub use services::errors::StoreError;
+pub use services::policies::PolicyStore;
+pub use services::schema::SchemaStore;
 pub use services::*;
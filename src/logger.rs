@@ -1,17 +1,254 @@
+use std::collections::{BTreeMap, HashMap};
+use std::error::Error;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
 use crate::config;
-use log::LevelFilter;
+use crate::config::LogFormat;
+use log::{error, info, LevelFilter};
 use log4rs::append::console::{ConsoleAppender, Target};
-use log4rs::config::{Appender, Root};
-use log4rs::Config;
+use log4rs::append::rolling_file::policy::compound::roll::fixed_window::FixedWindowRoller;
+use log4rs::append::rolling_file::policy::compound::trigger::size::SizeTrigger;
+use log4rs::append::rolling_file::policy::compound::CompoundPolicy;
+use log4rs::append::rolling_file::RollingFileAppender;
+use log4rs::config::{Appender, Logger, Root};
+use log4rs::encode::json::JsonEncoder;
+use log4rs::encode::pattern::PatternEncoder;
+use log4rs::encode::Encode;
+use log4rs::{Config, Handle};
+
+// The default pattern of log4rs with the ID of the request being handled, if any.
+const TEXT_PATTERN: &str = "{d} {l} {t} [{X(request_id)(-)}] - {m}{n}";
+const DEFAULT_FILE_MAX_SIZE: u64 = 10 * 1024 * 1024;
+const DEFAULT_FILE_COUNT: u32 = 5;
+
+fn encoder(format: LogFormat) -> Box<dyn Encode> {
+    match format {
+        LogFormat::Text => Box::new(PatternEncoder::new(TEXT_PATTERN)),
+        // The JSON encoder reports the request ID in the `mdc` field
+        LogFormat::Json => Box::new(JsonEncoder::new()),
+    }
+}
+
+/// The appenders settings, kept to rebuild the logger configuration on level changes.
+struct Appenders {
+    format: LogFormat,
+    file: Option<PathBuf>,
+    file_max_size: u64,
+    file_count: u32,
+}
+
+impl Appenders {
+    fn new(conf: &config::Config) -> Self {
+        Self {
+            format: conf.log_format.unwrap_or(LogFormat::Text),
+            file: conf.log_file.clone(),
+            file_max_size: conf.log_file_max_size.unwrap_or(DEFAULT_FILE_MAX_SIZE),
+            file_count: conf.log_file_count.unwrap_or(DEFAULT_FILE_COUNT),
+        }
+    }
+
+    fn build(&self, levels: &Levels) -> Result<Config, Box<dyn Error>> {
+        let stderr = ConsoleAppender::builder()
+            .target(Target::Stderr)
+            .encoder(encoder(self.format))
+            .build();
+
+        let mut config =
+            Config::builder().appender(Appender::builder().build("stderr", Box::new(stderr)));
+        let mut root = Root::builder().appender("stderr");
+
+        if let Some(log_file) = self.file.as_ref() {
+            let roller = FixedWindowRoller::builder()
+                .build(&format!("{}.{{}}", log_file.display()), self.file_count)?;
+            let policy = CompoundPolicy::new(
+                Box::new(SizeTrigger::new(self.file_max_size)),
+                Box::new(roller),
+            );
+            let file = RollingFileAppender::builder()
+                .encoder(encoder(self.format))
+                .build(log_file, Box::new(policy))?;
+            config = config.appender(Appender::builder().build("file", Box::new(file)));
+            root = root.appender("file");
+        }
+
+        for (module, level) in &levels.modules {
+            config = config.logger(Logger::builder().build(module, *level));
+        }
+
+        Ok(config.build(root.build(levels.root))?)
+    }
+}
+
+#[derive(Clone)]
+pub struct Levels {
+    pub root: LevelFilter,
+    pub modules: BTreeMap<String, LevelFilter>,
+}
+
+impl Levels {
+    fn get(&self, module: &Option<String>) -> Option<LevelFilter> {
+        match module {
+            Some(module) => self.modules.get(module).copied(),
+            None => Some(self.root),
+        }
+    }
+
+    fn set(&mut self, module: &Option<String>, level: Option<LevelFilter>) {
+        match (module, level) {
+            (Some(module), Some(level)) => {
+                self.modules.insert(module.clone(), level);
+            }
+            (Some(module), None) => {
+                self.modules.remove(module);
+            }
+            (None, Some(level)) => self.root = level,
+            (None, None) => {}
+        }
+    }
+}
+
+/// A level set with a TTL, reverted once the TTL elapses unless the level changed again.
+struct Override {
+    // The revision of the last change, so a scheduled revert does not undo a newer change
+    revision: u64,
+    // The level from before the first of overlapping overrides, which every revert restores
+    baseline: Option<LevelFilter>,
+}
+
+struct State {
+    levels: Levels,
+    // The pending overrides by module, `None` being the root logger
+    overrides: HashMap<Option<String>, Override>,
+    revision: u64,
+}
+
+/// Controls the log levels of the running agent through the log4rs `Handle`.
+pub struct LogController {
+    handle: Handle,
+    appenders: Appenders,
+    initial: Levels,
+    state: Mutex<State>,
+}
+
+impl LogController {
+    fn new(handle: Handle, appenders: Appenders, levels: Levels) -> Self {
+        Self {
+            handle,
+            appenders,
+            initial: levels.clone(),
+            state: Mutex::new(State {
+                levels,
+                overrides: HashMap::new(),
+                revision: 0,
+            }),
+        }
+    }
+
+    /// Control the logger behind an already initialized `Handle`, applying the configured
+    /// levels and appenders to it. The global logger can only be initialized once per process.
+    pub fn with_handle(conf: &config::Config, handle: Handle) -> Result<Self, Box<dyn Error>> {
+        let levels = initial_levels(conf);
+        let appenders = Appenders::new(conf);
+        handle.set_config(appenders.build(&levels)?);
+        Ok(Self::new(handle, appenders, levels))
+    }
 
-pub(crate) fn init(conf: &config::Config) {
-    let log_level = conf.log_level.unwrap_or(LevelFilter::Info);
-    let stderr = ConsoleAppender::builder().target(Target::Stderr).build();
+    pub fn levels(&self) -> Levels {
+        self.state.lock().unwrap().levels.clone()
+    }
+
+    /// Set the level of a module, or of the root logger when no module is given.
+    /// With a `ttl`, the level from before any pending TTL is restored once it elapses.
+    pub fn set_level(
+        self: &Arc<Self>,
+        module: Option<String>,
+        level: LevelFilter,
+        ttl: Option<Duration>,
+    ) -> Result<Levels, Box<dyn Error>> {
+        let mut state = self.state.lock().unwrap();
+        let mut levels = state.levels.clone();
+        levels.set(&module, Some(level));
+        self.handle.set_config(self.appenders.build(&levels)?);
+        info!(
+            "Log level of {} set to {}",
+            module.as_deref().unwrap_or("root"),
+            level
+        );
+
+        let previous = state.levels.get(&module);
+        state.levels = levels;
+        state.revision += 1;
+        let revision = state.revision;
+
+        match ttl {
+            Some(ttl) => {
+                let baseline = state
+                    .overrides
+                    .get(&module)
+                    .map_or(previous, |pending| pending.baseline);
+                state
+                    .overrides
+                    .insert(module.clone(), Override { revision, baseline });
+                let controller = self.clone();
+                rocket::tokio::spawn(async move {
+                    rocket::tokio::time::sleep(ttl).await;
+                    controller.revert(module, revision);
+                });
+            }
+            // A level set without a TTL is kept, cancelling the pending revert
+            None => {
+                state.overrides.remove(&module);
+            }
+        }
+        Ok(state.levels.clone())
+    }
+
+    fn revert(&self, module: Option<String>, revision: u64) {
+        let mut state = self.state.lock().unwrap();
+        let baseline = match state.overrides.get(&module) {
+            Some(pending) if pending.revision == revision => pending.baseline,
+            _ => return,
+        };
+        let mut levels = state.levels.clone();
+        levels.set(&module, baseline);
+        match self.appenders.build(&levels) {
+            Ok(config) => {
+                self.handle.set_config(config);
+                state.levels = levels;
+                state.overrides.remove(&module);
+                info!(
+                    "Log level of {} reverted",
+                    module.as_deref().unwrap_or("root")
+                );
+            }
+            Err(err) => error!("Failed to revert the log level: {}", err),
+        }
+    }
+
+    /// Restore the levels the agent was started with.
+    pub fn reset(&self) -> Result<Levels, Box<dyn Error>> {
+        let mut state = self.state.lock().unwrap();
+        self.handle.set_config(self.appenders.build(&self.initial)?);
+        info!("Log levels reset");
+        state.levels = self.initial.clone();
+        state.overrides.clear();
+        Ok(state.levels.clone())
+    }
+}
+
+fn initial_levels(conf: &config::Config) -> Levels {
+    Levels {
+        root: conf.log_level.unwrap_or(LevelFilter::Info),
+        modules: BTreeMap::new(),
+    }
+}
 
-    let config = Config::builder()
-        .appender(Appender::builder().build("stderr", Box::new(stderr)))
-        .build(Root::builder().appender("stderr").build(log_level))
-        .unwrap();
+pub fn init(conf: &config::Config) -> Result<LogController, Box<dyn Error>> {
+    let levels = initial_levels(conf);
+    let appenders = Appenders::new(conf);
+    let handle = log4rs::init_config(appenders.build(&levels)?)?;
 
-    let _handle = log4rs::init_config(config).unwrap();
+    Ok(LogController::new(handle, appenders, levels))
 }
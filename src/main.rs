@@ -3,56 +3,63 @@ extern crate rocket;
 
 use std::borrow::Borrow;
 use std::process::ExitCode;
+use std::sync::Arc;
+use std::time::Duration;
 
 use log::{error, info};
 use rocket::catchers;
 use rocket::http::ContentType;
-use rocket_cors::{AllowedHeaders, AllowedOrigins, CorsOptions};
 use rocket_okapi::settings::UrlObject;
 use rocket_okapi::{openapi_get_routes, rapidoc::*, swagger_ui::*};
 
-use crate::services::data::memory::MemoryDataStore;
-use crate::services::data::DataStore;
-use crate::services::policies::memory::MemoryPolicyStore;
-use crate::services::policies::PolicyStore;
-use crate::services::schema::memory::MemorySchemaStore;
-use crate::services::schema::SchemaStore;
-
-mod authn;
-mod common;
-mod config;
-mod errors;
-mod logger;
-mod routes;
-mod schemas;
-mod services;
+use cedar_agent::authorization::cache::DecisionCache;
+use cedar_agent::authorization::shadow::ShadowMonitor;
+use cedar_agent::changes::ChangeFeed;
+use cedar_agent::config::Command;
+use cedar_agent::data::memory::MemoryDataStore;
+use cedar_agent::policies::memory::MemoryPolicyStore;
+use cedar_agent::schema::memory::MemorySchemaStore;
+use cedar_agent::test_cases::{TestCaseStore, TestGate};
+use cedar_agent::{
+    cli, common, config, cors, errors, logger, request_id, routes, DataStore, PolicyStore,
+    SchemaStore,
+};
 
 #[rocket::main]
 async fn main() -> ExitCode {
-    let config = config::init();
-    logger::init(&config);
+    let config = match config::init() {
+        Ok(config) => config,
+        Err(err) => {
+            eprintln!("{}", err);
+            return ExitCode::FAILURE;
+        }
+    };
+    if config.print_config {
+        return match config.to_redacted_toml() {
+            Ok(printed) => {
+                println!("{}", printed);
+                ExitCode::SUCCESS
+            }
+            Err(err) => {
+                eprintln!("Failed to print the configuration: {}", err);
+                ExitCode::FAILURE
+            }
+        };
+    }
+    match &config.command {
+        None | Some(Command::Serve) => {}
+        Some(command) => return cli::run(command, &config).await,
+    }
+    let log_controller = match logger::init(&config) {
+        Ok(log_controller) => Arc::new(log_controller),
+        Err(err) => {
+            eprintln!("Failed to initialize the logger: {}", err);
+            return ExitCode::FAILURE;
+        }
+    };
     let server_config: rocket::figment::Figment = config.borrow().into();
 
-    // Configure CORS
-    let cors = CorsOptions::default()
-        .allowed_origins(AllowedOrigins::all())
-        .allowed_methods(
-            vec![
-                rocket::http::Method::Get,
-                rocket::http::Method::Post,
-                rocket::http::Method::Put,
-                rocket::http::Method::Patch,
-                rocket::http::Method::Delete,
-                rocket::http::Method::Options,
-            ]
-            .into_iter()
-            .map(From::from)
-            .collect(),
-        )
-        .allowed_headers(AllowedHeaders::some(&["Authorization", "Content-Type"]))
-        .allow_credentials(true);
-
-    let cors_fairing = match cors.to_cors() {
+    let cors_fairing = match cors::init(&config) {
         Ok(fairing) => fairing,
         Err(err) => {
             error!("Failed to configure CORS: {}", err);
@@ -60,28 +67,64 @@ async fn main() -> ExitCode {
         }
     };
 
-    let launch_result = rocket::custom(server_config)
-        .attach(cors_fairing)
+    let changes = Arc::new(ChangeFeed::new());
+    let mut data_store = match config.data_integrity {
+        Some(integrity) => MemoryDataStore::with_integrity(integrity),
+        None => MemoryDataStore::new(),
+    }
+    .with_changes(changes.clone());
+    let decision_cache = Arc::new(match config.decision_cache_size {
+        Some(size) if size > 0 => {
+            info!("Caching up to {} authorization decisions", size);
+            DecisionCache::new(
+                size,
+                config.decision_cache_ttl.map(Duration::from_secs),
+                changes.clone(),
+            )
+        }
+        _ => DecisionCache::disabled(changes.clone()),
+    });
+
+    let test_cases = Arc::new(TestCaseStore::new());
+    let mut policy_store = MemoryPolicyStore::new().with_changes(changes.clone());
+    if config.policy_tests_gate.unwrap_or(false) {
+        info!("Rejecting the changes that make a policy test case fail");
+        let gate = Arc::new(TestGate::new(test_cases.clone()));
+        data_store = data_store.with_gate(gate.clone());
+        policy_store = policy_store.with_gate(gate);
+    }
+
+    let mut server = rocket::custom(server_config).attach(request_id::RequestIdFairing);
+    if let Some(cors_fairing) = cors_fairing {
+        server = server.attach(cors_fairing);
+    }
+
+    let launch_result = server
         .attach(common::DefaultContentType::new(ContentType::JSON))
-        .attach(services::schema::load_from_file::InitSchemaFairing)
-        .attach(services::data::load_from_file::InitDataFairing)
-        .attach(services::policies::load_from_file::InitPoliciesFairing)
+        .attach(cedar_agent::schema::load_from_file::InitSchemaFairing)
+        .attach(cedar_agent::data::load_from_file::InitDataFairing)
+        .attach(cedar_agent::policies::load_from_file::InitPoliciesFairing)
         .manage(config)
-        .manage(Box::new(MemoryPolicyStore::new()) as Box<dyn PolicyStore>)
-        .manage(Box::new(MemoryDataStore::new()) as Box<dyn DataStore>)
-        .manage(Box::new(MemorySchemaStore::new()) as Box<dyn SchemaStore>)
+        .manage(log_controller)
+        .manage(changes.clone())
+        .manage(decision_cache)
+        .manage(Arc::new(ShadowMonitor::new()))
+        .manage(test_cases)
+        .manage(Box::new(policy_store) as Box<dyn PolicyStore>)
+        .manage(Box::new(data_store) as Box<dyn DataStore>)
+        .manage(Box::new(MemorySchemaStore::new().with_changes(changes)) as Box<dyn SchemaStore>)
         .manage(cedar_policy::Authorizer::new())
         .register(
             "/",
-            catchers![
+            request_id::catchers_with_request_id(catchers![
                 errors::catchers::handle_500,
                 errors::catchers::handle_404,
                 errors::catchers::handle_400,
-            ],
+            ]),
         )
         .mount(
             "/v1",
-            openapi_get_routes![
+            request_id::with_request_id(openapi_get_routes![
                 routes::healthy,
                 routes::policies::get_policies,
                 routes::policies::get_policy,
@@ -89,9 +132,31 @@ async fn main() -> ExitCode {
                 routes::policies::update_policies,
                 routes::policies::update_policy,
                 routes::policies::delete_policy,
+                routes::policies::enable_policy,
+                routes::policies::disable_policy,
+                routes::policies::analyze_policies,
+                routes::shadow::get_shadow_policies,
+                routes::shadow::update_shadow_policies,
+                routes::shadow::delete_shadow_policies,
+                routes::shadow::promote_shadow_policies,
+                routes::shadow::get_shadow_disagreements,
                 routes::data::get_entities,
                 routes::data::update_entities,
                 routes::data::delete_entities,
+                routes::data::query_entities,
+                routes::data::apply_data_delta,
+                routes::data::get_sync_status,
+                routes::data::get_integrity_report,
+                routes::data::get_entity,
+                routes::data::put_entity,
+                routes::data::patch_entity,
+                routes::data::delete_entity,
+                routes::data::get_entity_parents,
+                routes::data::get_entity_ancestors,
+                routes::data::get_entity_children,
+                routes::data::get_entity_descendants,
+                routes::data::add_entity_parent,
+                routes::data::remove_entity_parent,
                 routes::data::add_single_data_entry,
                 routes::data::update_single_data_entry,
                 routes::data::delete_single_data_entry,
@@ -100,8 +165,22 @@ async fn main() -> ExitCode {
                 routes::data::patch_entity_attributes,
                 routes::data::add_new_entity,
                 routes::authorization::is_authorized,
+                routes::authorization::explain_is_authorized,
+                routes::changes::get_revision,
+                routes::changes::watch_changes,
+                routes::logging::get_log_levels,
+                routes::logging::update_log_level,
+                routes::logging::reset_log_levels,
+                routes::metrics::get_metrics,
+                routes::replay::replay_requests,
+                routes::test_cases::get_test_cases,
+                routes::test_cases::get_test_case,
+                routes::test_cases::put_test_case,
+                routes::test_cases::delete_test_case,
+                routes::test_cases::run_test_cases,
                 routes::schema::get_schema,
                 routes::schema::update_schema,
+                routes::schema::patch_schema,
                 routes::schema::delete_schema,
                 routes::schema::add_user_attribute,
                 routes::schema::add_table_attribute,
@@ -109,7 +188,7 @@ async fn main() -> ExitCode {
                 routes::schema::delete_table_attribute,
                 routes::schema::add_generic_attribute,
                 routes::schema::delete_generic_attribute,
-            ],
+            ]),
         )
         .mount(
             "/swagger-ui/",
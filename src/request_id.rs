@@ -0,0 +1,147 @@
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use async_trait::async_trait;
+use rocket::catcher::{self, Catcher};
+use rocket::fairing::{Fairing, Info, Kind};
+use rocket::futures::future::BoxFuture;
+use rocket::http::{Header, Status};
+use rocket::route::{Handler, Outcome};
+use rocket::{Data, Request, Response, Route};
+
+pub const REQUEST_ID_HEADER: &str = "X-Request-Id";
+/// Key of the request ID in the logging MDC, e.g. `{X(request_id)}` in log patterns.
+pub const REQUEST_ID_KEY: &str = "request_id";
+const MAX_REQUEST_ID_LENGTH: usize = 128;
+
+/// The ID of the request being handled, cached on the request.
+pub struct RequestId(pub String);
+
+impl RequestId {
+    fn generate() -> Self {
+        Self(format!("{:032x}", rand::random::<u128>()))
+    }
+
+    /// Use the ID supplied by the client, unless it could corrupt the logs.
+    fn from_header(value: Option<&str>) -> Self {
+        match value {
+            Some(id)
+                if !id.is_empty()
+                    && id.len() <= MAX_REQUEST_ID_LENGTH
+                    && id.chars().all(|c| c.is_ascii_graphic()) =>
+            {
+                Self(id.to_owned())
+            }
+            _ => Self::generate(),
+        }
+    }
+
+    pub fn of<'r>(req: &'r Request<'_>) -> &'r str {
+        req.local_cache(RequestId::generate).0.as_str()
+    }
+}
+
+/// Assign an ID to every request, taken from the `X-Request-Id` header or generated,
+/// and echo it back in the response.
+///
+/// The fairing does not expose the ID to the logger, as nothing would take it back off the
+/// worker thread once the request is done. The handlers wrapped by [`with_request_id`] and
+/// [`catchers_with_request_id`] log it instead. Attach it before the other fairings.
+pub struct RequestIdFairing;
+
+#[async_trait]
+impl Fairing for RequestIdFairing {
+    fn info(&self) -> Info {
+        Info {
+            name: "RequestId",
+            kind: Kind::Request | Kind::Response,
+        }
+    }
+
+    async fn on_request(&self, req: &mut Request<'_>, _data: &mut Data<'_>) {
+        let request_id = RequestId::from_header(req.headers().get_one(REQUEST_ID_HEADER));
+        req.local_cache(|| request_id);
+    }
+
+    async fn on_response<'r>(&self, req: &'r Request<'_>, res: &mut Response<'r>) {
+        res.set_header(Header::new(
+            REQUEST_ID_HEADER,
+            RequestId::of(req).to_owned(),
+        ));
+    }
+}
+
+/// Future that exposes the request ID to the logger whenever it is polled.
+///
+/// The MDC is thread local while the handler may move between worker threads,
+/// so the ID is set around every poll rather than once per request. The value
+/// found before the poll is put back afterwards.
+struct WithRequestId<'a, T> {
+    request_id: String,
+    inner: BoxFuture<'a, T>,
+}
+
+impl<T> Future for WithRequestId<'_, T> {
+    type Output = T;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let previous = log_mdc::insert(REQUEST_ID_KEY, self.request_id.clone());
+        let poll = self.inner.as_mut().poll(cx);
+        match previous {
+            Some(previous) => log_mdc::insert(REQUEST_ID_KEY, previous),
+            None => log_mdc::remove(REQUEST_ID_KEY),
+        };
+        poll
+    }
+}
+
+#[derive(Clone)]
+struct RequestIdHandler(Box<dyn Handler>);
+
+#[async_trait]
+impl Handler for RequestIdHandler {
+    async fn handle<'r>(&self, req: &'r Request<'_>, data: Data<'r>) -> Outcome<'r> {
+        WithRequestId {
+            request_id: RequestId::of(req).to_owned(),
+            inner: self.0.handle(req, data),
+        }
+        .await
+    }
+}
+
+#[derive(Clone)]
+struct RequestIdCatcher(Box<dyn catcher::Handler>);
+
+#[async_trait]
+impl catcher::Handler for RequestIdCatcher {
+    async fn handle<'r>(&self, status: Status, req: &'r Request<'_>) -> catcher::Result<'r> {
+        WithRequestId {
+            request_id: RequestId::of(req).to_owned(),
+            inner: self.0.handle(status, req),
+        }
+        .await
+    }
+}
+
+/// Wrap the handlers of the routes so every log line they emit carries the request ID.
+pub fn with_request_id(routes: Vec<Route>) -> Vec<Route> {
+    routes
+        .into_iter()
+        .map(|mut route| {
+            route.handler = Box::new(RequestIdHandler(route.handler.clone()));
+            route
+        })
+        .collect()
+}
+
+/// Wrap the handlers of the catchers so every log line they emit carries the request ID.
+pub fn catchers_with_request_id(catchers: Vec<Catcher>) -> Vec<Catcher> {
+    catchers
+        .into_iter()
+        .map(|mut catcher| {
+            catcher.handler = Box::new(RequestIdCatcher(catcher.handler.clone()));
+            catcher
+        })
+        .collect()
+}
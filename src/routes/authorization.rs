@@ -1,4 +1,6 @@
-use cedar_policy::Authorizer;
+use std::sync::Arc;
+
+use cedar_policy::{Authorizer, Request};
 
 use log::{debug, info, warn};
 
@@ -8,23 +10,20 @@ use rocket_okapi::openapi;
 
 use crate::authn::ApiKey;
 use crate::errors::response::AgentError;
-use crate::schemas::authorization::{AuthorizationAnswer, AuthorizationCall, AuthorizationRequest};
-use crate::services::data::DataStore;
+use crate::schemas::authorization::{
+    AuthorizationAnswer, AuthorizationCall, AuthorizationExplanation, AuthorizationRequest,
+    CacheStatus,
+};
+use crate::services::authorization::cache::DecisionCache;
+use crate::services::authorization::explain;
+use crate::services::authorization::shadow::ShadowMonitor;
+use crate::services::data::{DataStore, EntitySnapshot};
 use crate::services::policies::PolicyStore;
 
-#[openapi]
-#[post("/is_authorized", format = "json", data = "<authorization_call>")]
-pub async fn is_authorized(
-    _auth: ApiKey,
-    policy_store: &State<Box<dyn PolicyStore>>,
+async fn build_request(
     data_store: &State<Box<dyn DataStore>>,
-    authorizer: &State<Authorizer>,
     authorization_call: Json<AuthorizationCall>,
-) -> Result<Json<AuthorizationAnswer>, AgentError> {
-    // Print the payload to the console
-    debug!("Received authorization request: {:?}", authorization_call);
-
-    let policies = policy_store.policy_set().await;
+) -> Result<(Request, EntitySnapshot), AgentError> {
     let query: AuthorizationRequest = match authorization_call.into_inner().try_into() {
         Ok(query) => query,
         Err(err) => {
@@ -35,21 +34,99 @@ pub async fn is_authorized(
         }
     };
 
-    // Temporary solution to override fetching entities from the datastore by directly passing it to the REST body.
-    // Eventually this logic will be replaced in favor of performing live patch updates
+    // Entities passed in the request body override the stored ones, which are kept in sync
+    // incrementally through `POST /data/delta`
     let stored_entities = data_store.entities().await;
-    let (request, entities) = match query.get_request_entities(stored_entities) {
-        Ok(result) => result,
+    match query.get_request_entities(stored_entities) {
+        Ok(result) => Ok(result),
         Err(err) => {
             warn!("Failed to build request/entities: {}", err);
-            return Err(AgentError::BadRequest {
+            Err(AgentError::BadRequest {
                 reason: err.to_string(),
-            });
+            })
+        }
+    }
+}
+
+#[openapi]
+#[post("/is_authorized", format = "json", data = "<authorization_call>")]
+pub async fn is_authorized(
+    _auth: ApiKey,
+    policy_store: &State<Box<dyn PolicyStore>>,
+    data_store: &State<Box<dyn DataStore>>,
+    authorizer: &State<Authorizer>,
+    decision_cache: &State<Arc<DecisionCache>>,
+    shadow_monitor: &State<Arc<ShadowMonitor>>,
+    authorization_call: Json<AuthorizationCall>,
+) -> Result<Json<AuthorizationAnswer>, AgentError> {
+    // Print the payload to the console
+    debug!("Received authorization request: {:?}", authorization_call);
+
+    let cache_key = decision_cache.key(&authorization_call);
+    let cached = cache_key.as_ref().and_then(|key| decision_cache.get(key));
+    // The shadow policies are evaluated even when the answer is cached
+    let shadow_policies = policy_store.shadow_policy_set().await;
+    if let (Some(answer), None) = (&cached, &shadow_policies) {
+        debug!("Cached authorization answer: {:?}", answer);
+        return Ok(Json::from(
+            answer.clone().with_cache_status(CacheStatus::Hit),
+        ));
+    }
+
+    let shadow_call = shadow_policies
+        .as_ref()
+        .map(|_| authorization_call.clone().into_inner());
+    let (request, entities) = build_request(data_store, authorization_call).await?;
+
+    let answer = match cached {
+        Some(answer) => answer.with_cache_status(CacheStatus::Hit),
+        None => {
+            let policies = policy_store.policy_set().await;
+            info!("Querying cedar using {:?}", &request);
+            let answer = authorizer.is_authorized(&request, &policies, &entities);
+            debug!("Authorization answer: {:?}", answer);
+            let answer = AuthorizationAnswer::from(answer);
+            match cache_key {
+                Some(key) => {
+                    decision_cache.insert(key, answer.clone());
+                    answer.with_cache_status(CacheStatus::Miss)
+                }
+                None => answer,
+            }
         }
     };
 
-    info!("Querying cedar using {:?}", &request);
-    let answer = authorizer.is_authorized(&request, &policies, &entities);
-    debug!("Authorization answer: {:?}", answer);
-    Ok(Json::from(AuthorizationAnswer::from(answer)))
+    if let (Some(shadow_policies), Some(shadow_call)) = (shadow_policies, shadow_call) {
+        let shadow_answer = authorizer.is_authorized(&request, &shadow_policies, &entities);
+        debug!("Shadow authorization answer: {:?}", shadow_answer);
+        shadow_monitor.record(shadow_call, &answer, shadow_answer);
+    }
+    Ok(Json::from(answer))
+}
+
+#[openapi]
+#[post(
+    "/is_authorized/explain",
+    format = "json",
+    data = "<authorization_call>"
+)]
+pub async fn explain_is_authorized(
+    _auth: ApiKey,
+    policy_store: &State<Box<dyn PolicyStore>>,
+    data_store: &State<Box<dyn DataStore>>,
+    authorizer: &State<Authorizer>,
+    authorization_call: Json<AuthorizationCall>,
+) -> Result<Json<AuthorizationExplanation>, AgentError> {
+    debug!(
+        "Received authorization request to explain: {:?}",
+        authorization_call
+    );
+
+    let policies = policy_store.policy_set().await;
+    let (request, entities) = build_request(data_store, authorization_call).await?;
+
+    info!("Explaining cedar decision using {:?}", &request);
+    Ok(Json::from(explain::explain(
+        authorizer, &request, &policies, &entities,
+    )))
 }
@@ -0,0 +1,107 @@
+use std::sync::Arc;
+
+use log::{info, warn};
+use rocket::futures::stream::{BoxStream, StreamExt};
+use rocket::request::{FromRequest, Outcome};
+use rocket::response::stream::{stream, Event, EventStream};
+use rocket::serde::json::Json;
+use rocket::tokio::select;
+use rocket::tokio::sync::broadcast::error::RecvError;
+use rocket::{get, Request, Shutdown, State};
+use rocket_okapi::gen::OpenApiGenerator;
+use rocket_okapi::openapi;
+use rocket_okapi::request::{OpenApiFromRequest, RequestHeaderInput};
+
+use crate::authn::ApiKey;
+use crate::errors::response::AgentError;
+use crate::schemas::changes as schemas;
+use crate::services::changes::ChangeFeed;
+
+const LAST_EVENT_ID_HEADER: &str = "Last-Event-ID";
+
+/// The revision of the last event seen by a reconnecting event source.
+pub struct LastEventId(Option<u64>);
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for LastEventId {
+    type Error = ();
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let revision = request
+            .headers()
+            .get_one(LAST_EVENT_ID_HEADER)
+            .and_then(|id| id.parse().ok());
+        Outcome::Success(LastEventId(revision))
+    }
+}
+
+impl<'a> OpenApiFromRequest<'a> for LastEventId {
+    fn from_request_input(
+        _gen: &mut OpenApiGenerator,
+        _name: String,
+        _required: bool,
+    ) -> rocket_okapi::Result<RequestHeaderInput> {
+        Ok(RequestHeaderInput::None)
+    }
+}
+
+#[openapi]
+#[get("/changes/revision")]
+pub async fn get_revision(
+    _auth: ApiKey,
+    changes: &State<Arc<ChangeFeed>>,
+) -> Result<Json<schemas::Revision>, AgentError> {
+    Ok(Json::from(schemas::Revision {
+        revision: changes.revision(),
+    }))
+}
+
+/// Stream the changes of the stores as server-sent events, resuming after the `since`
+/// revision or the `Last-Event-ID` header when given.
+#[openapi]
+#[get("/changes?<since>")]
+pub async fn watch_changes(
+    _auth: ApiKey,
+    changes: &State<Arc<ChangeFeed>>,
+    last_event_id: LastEventId,
+    since: Option<u64>,
+    mut shutdown: Shutdown,
+) -> Result<EventStream<BoxStream<'static, Event>>, AgentError> {
+    let since = since.or(last_event_id.0);
+    info!("Watching changes since revision {:?}", since);
+    let (missed, mut receiver) = match changes.subscribe(since) {
+        Ok(subscription) => subscription,
+        Err(err) => {
+            return Err(AgentError::BadRequest {
+                reason: err.to_string(),
+            })
+        }
+    };
+
+    let event = |change: &schemas::ChangeEvent| {
+        Event::json(change)
+            .id(change.revision.to_string())
+            .event("change")
+    };
+    let events = stream! {
+        for change in &missed {
+            yield event(change);
+        }
+        loop {
+            let change = select! {
+                change = receiver.recv() => change,
+                _ = &mut shutdown => break,
+            };
+            match change {
+                Ok(change) => yield event(&change),
+                // The client resumes from the last revision it has seen when it reconnects
+                Err(RecvError::Lagged(skipped)) => {
+                    warn!("Closing a change stream that fell {} events behind", skipped);
+                    break;
+                }
+                Err(RecvError::Closed) => break,
+            }
+        }
+    };
+    Ok(EventStream::from(events.boxed()))
+}
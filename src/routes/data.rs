@@ -1,8 +1,8 @@
 use rocket::response::status;
 
-use cedar_policy::EntityUid;
+use cedar_policy::{EntityId, EntityTypeName, EntityUid};
 use rocket::serde::json::{Json, Value, *};
-use rocket::{delete, get, patch, put, State};
+use rocket::{delete, get, patch, post, put, State};
 use rocket_okapi::openapi;
 use serde_json::Map;
 use std::collections::HashSet;
@@ -11,10 +11,38 @@ use std::str::FromStr;
 use crate::authn::ApiKey;
 use crate::errors::response::AgentError;
 use crate::schemas::data as schemas;
-use crate::services::data::DataStore;
+use crate::schemas::patch::{Patch, PatchError};
+use crate::services::data::{DataStore, Relation};
+use crate::services::errors::StoreError;
 use crate::services::schema::SchemaStore;
 use log::{debug, info, warn};
 
+fn full_type_name(namespace: &str, entity_type: &str) -> String {
+    if namespace.is_empty() {
+        entity_type.to_string()
+    } else {
+        format!("{}::{}", namespace, entity_type)
+    }
+}
+
+fn entity_uid(full_type: &str, entity_id: &str) -> Result<EntityUid, AgentError> {
+    let type_name = EntityTypeName::from_str(full_type).map_err(|err| AgentError::BadRequest {
+        reason: format!("Invalid entity type '{}': {}", full_type, err),
+    })?;
+    Ok(EntityUid::from_type_name_and_id(
+        type_name,
+        EntityId::new(entity_id),
+    ))
+}
+
+/// Read the UID of an entity from its JSON `uid` field
+fn payload_uid(entity: &schemas::Entity) -> Option<EntityUid> {
+    entity
+        .get()
+        .get("uid")
+        .and_then(|uid| EntityUid::from_json(uid.clone()).ok())
+}
+
 #[openapi]
 #[get("/data")]
 pub async fn get_entities(
@@ -58,9 +86,7 @@ pub async fn update_entities(
 
     match data_store.update_entities(incoming, schema).await {
         Ok(entities) => Ok(Json::from(entities)),
-        Err(err) => Err(AgentError::BadRequest {
-            reason: err.to_string(),
-        }),
+        Err(err) => Err(err.into()),
     }
 }
 
@@ -71,10 +97,314 @@ pub async fn delete_entities(
     data_store: &State<Box<dyn DataStore>>,
 ) -> Result<status::NoContent, AgentError> {
     info!("Deleting all entities");
-    data_store.delete_entities().await;
+    data_store.delete_entities().await?;
     Ok(status::NoContent)
 }
 
+#[openapi]
+#[post("/data/query", format = "json", data = "<query>")]
+pub async fn query_entities(
+    _auth: ApiKey,
+    data_store: &State<Box<dyn DataStore>>,
+    query: Json<schemas::EntityQuery>,
+) -> Result<Json<schemas::EntityPage>, AgentError> {
+    match data_store.query_entities(&query).await {
+        Ok(page) => Ok(Json::from(page)),
+        Err(err) => Err(err.into()),
+    }
+}
+
+#[openapi]
+#[post("/data/delta", format = "json", data = "<batch>")]
+pub async fn apply_data_delta(
+    _auth: ApiKey,
+    data_store: &State<Box<dyn DataStore>>,
+    schema_store: &State<Box<dyn SchemaStore>>,
+    batch: Json<schemas::DeltaBatch>,
+) -> Result<Json<schemas::DeltaResult>, AgentError> {
+    let schema = schema_store.get_cedar_schema().await;
+    match data_store.apply_delta(batch.into_inner(), schema).await {
+        Ok(result) => Ok(Json::from(result)),
+        Err(err) => Err(err.into()),
+    }
+}
+
+#[openapi]
+#[get("/data/delta")]
+pub async fn get_sync_status(
+    _auth: ApiKey,
+    data_store: &State<Box<dyn DataStore>>,
+) -> Result<Json<schemas::SyncStatus>, AgentError> {
+    Ok(Json::from(data_store.sync_status().await))
+}
+
+#[openapi]
+#[get("/data/integrity")]
+pub async fn get_integrity_report(
+    _auth: ApiKey,
+    data_store: &State<Box<dyn DataStore>>,
+) -> Result<Json<schemas::IntegrityReport>, AgentError> {
+    Ok(Json::from(data_store.integrity_report().await))
+}
+
+#[openapi]
+#[get("/data/entities/<entity_type>/<entity_id>")]
+pub async fn get_entity(
+    _auth: ApiKey,
+    data_store: &State<Box<dyn DataStore>>,
+    entity_type: String,
+    entity_id: String,
+) -> Result<Json<schemas::Entity>, AgentError> {
+    let uid = entity_uid(&entity_type, &entity_id)?;
+    match data_store.get_entity(&uid).await {
+        Some(entity) => Ok(Json::from(entity)),
+        None => Err(AgentError::NotFound {
+            object: "Entity",
+            id: uid.to_string(),
+        }),
+    }
+}
+
+#[openapi]
+#[put(
+    "/data/entities/<entity_type>/<entity_id>",
+    format = "json",
+    data = "<entity>"
+)]
+pub async fn put_entity(
+    _auth: ApiKey,
+    data_store: &State<Box<dyn DataStore>>,
+    schema_store: &State<Box<dyn SchemaStore>>,
+    entity_type: String,
+    entity_id: String,
+    entity: Json<schemas::Entity>,
+) -> Result<Json<schemas::Entity>, AgentError> {
+    let uid = entity_uid(&entity_type, &entity_id)?;
+    info!("Upserting entity {}", uid);
+    let mut entity = entity.into_inner();
+    let body = match entity.get_mut().as_object_mut() {
+        Some(body) => body,
+        None => {
+            return Err(AgentError::BadRequest {
+                reason: "The entity must be a JSON object".to_string(),
+            })
+        }
+    };
+
+    // The UID in the body is optional, but must match the path when given
+    match body.get("uid") {
+        Some(body_uid) => {
+            if EntityUid::from_json(body_uid.clone()).ok().as_ref() != Some(&uid) {
+                return Err(AgentError::BadRequest {
+                    reason: format!("Entity UID in payload does not match path UID {}", uid),
+                });
+            }
+        }
+        None => {
+            body.insert(
+                "uid".to_string(),
+                json!({ "type": entity_type, "id": entity_id }),
+            );
+        }
+    }
+    body.entry("attrs").or_insert_with(|| json!({}));
+    body.entry("parents").or_insert_with(|| json!([]));
+
+    let schema = schema_store.get_cedar_schema().await;
+    match data_store.upsert_entity(entity, schema).await {
+        Ok(entity) => Ok(Json::from(entity)),
+        Err(err) => Err(err.into()),
+    }
+}
+
+#[openapi]
+#[patch("/data/entities/<entity_type>/<entity_id>", data = "<patch>")]
+pub async fn patch_entity(
+    _auth: ApiKey,
+    data_store: &State<Box<dyn DataStore>>,
+    schema_store: &State<Box<dyn SchemaStore>>,
+    entity_type: String,
+    entity_id: String,
+    patch: Result<Patch, PatchError>,
+) -> Result<Json<schemas::Entity>, AgentError> {
+    let uid = entity_uid(&entity_type, &entity_id)?;
+    info!("Patching entity {}", uid);
+    let patch = match patch {
+        Ok(patch) => patch,
+        Err(err) => {
+            return Err(AgentError::BadRequest {
+                reason: err.to_string(),
+            })
+        }
+    };
+    let schema = schema_store.get_cedar_schema().await;
+    match data_store.patch_entity(&uid, &patch, schema).await {
+        Ok(Some(entity)) => Ok(Json::from(entity)),
+        Ok(None) => Err(AgentError::NotFound {
+            object: "Entity",
+            id: uid.to_string(),
+        }),
+        Err(err) => Err(err.into()),
+    }
+}
+
+#[openapi]
+#[delete("/data/entities/<entity_type>/<entity_id>")]
+pub async fn delete_entity(
+    _auth: ApiKey,
+    data_store: &State<Box<dyn DataStore>>,
+    entity_type: String,
+    entity_id: String,
+) -> Result<status::NoContent, AgentError> {
+    let uid = entity_uid(&entity_type, &entity_id)?;
+    info!("Deleting entity {}", uid);
+    match data_store.remove_entity(&uid).await {
+        Ok(Some(_)) => Ok(status::NoContent),
+        Ok(None) => Err(AgentError::NotFound {
+            object: "Entity",
+            id: uid.to_string(),
+        }),
+        Err(err) => Err(err.into()),
+    }
+}
+
+async fn related_entities(
+    data_store: &dyn DataStore,
+    entity_type: &str,
+    entity_id: &str,
+    relation: Relation,
+) -> Result<Json<Vec<schemas::EntityReference>>, AgentError> {
+    let uid = entity_uid(entity_type, entity_id)?;
+    match data_store.related_entities(&uid, relation).await {
+        Some(related) => Ok(Json::from(
+            related
+                .into_iter()
+                .map(schemas::EntityReference::from)
+                .collect::<Vec<_>>(),
+        )),
+        None => Err(AgentError::NotFound {
+            object: "Entity",
+            id: uid.to_string(),
+        }),
+    }
+}
+
+#[openapi]
+#[get("/data/entities/<entity_type>/<entity_id>/parents")]
+pub async fn get_entity_parents(
+    _auth: ApiKey,
+    data_store: &State<Box<dyn DataStore>>,
+    entity_type: String,
+    entity_id: String,
+) -> Result<Json<Vec<schemas::EntityReference>>, AgentError> {
+    related_entities(
+        data_store.as_ref(),
+        &entity_type,
+        &entity_id,
+        Relation::Parents,
+    )
+    .await
+}
+
+#[openapi]
+#[get("/data/entities/<entity_type>/<entity_id>/ancestors")]
+pub async fn get_entity_ancestors(
+    _auth: ApiKey,
+    data_store: &State<Box<dyn DataStore>>,
+    entity_type: String,
+    entity_id: String,
+) -> Result<Json<Vec<schemas::EntityReference>>, AgentError> {
+    related_entities(
+        data_store.as_ref(),
+        &entity_type,
+        &entity_id,
+        Relation::Ancestors,
+    )
+    .await
+}
+
+#[openapi]
+#[get("/data/entities/<entity_type>/<entity_id>/children")]
+pub async fn get_entity_children(
+    _auth: ApiKey,
+    data_store: &State<Box<dyn DataStore>>,
+    entity_type: String,
+    entity_id: String,
+) -> Result<Json<Vec<schemas::EntityReference>>, AgentError> {
+    related_entities(
+        data_store.as_ref(),
+        &entity_type,
+        &entity_id,
+        Relation::Children,
+    )
+    .await
+}
+
+#[openapi]
+#[get("/data/entities/<entity_type>/<entity_id>/descendants")]
+pub async fn get_entity_descendants(
+    _auth: ApiKey,
+    data_store: &State<Box<dyn DataStore>>,
+    entity_type: String,
+    entity_id: String,
+) -> Result<Json<Vec<schemas::EntityReference>>, AgentError> {
+    related_entities(
+        data_store.as_ref(),
+        &entity_type,
+        &entity_id,
+        Relation::Descendants,
+    )
+    .await
+}
+
+#[openapi]
+#[put("/data/entities/<entity_type>/<entity_id>/parents/<parent_type>/<parent_id>")]
+pub async fn add_entity_parent(
+    _auth: ApiKey,
+    data_store: &State<Box<dyn DataStore>>,
+    schema_store: &State<Box<dyn SchemaStore>>,
+    entity_type: String,
+    entity_id: String,
+    parent_type: String,
+    parent_id: String,
+) -> Result<Json<schemas::Entity>, AgentError> {
+    let uid = entity_uid(&entity_type, &entity_id)?;
+    let parent = entity_uid(&parent_type, &parent_id)?;
+    let schema = schema_store.get_cedar_schema().await;
+    match data_store.add_parent(&uid, &parent, schema).await {
+        Ok(Some(entity)) => Ok(Json::from(entity)),
+        Ok(None) => Err(AgentError::NotFound {
+            object: "Entity",
+            id: uid.to_string(),
+        }),
+        Err(err) => Err(err.into()),
+    }
+}
+
+#[openapi]
+#[delete("/data/entities/<entity_type>/<entity_id>/parents/<parent_type>/<parent_id>")]
+pub async fn remove_entity_parent(
+    _auth: ApiKey,
+    data_store: &State<Box<dyn DataStore>>,
+    schema_store: &State<Box<dyn SchemaStore>>,
+    entity_type: String,
+    entity_id: String,
+    parent_type: String,
+    parent_id: String,
+) -> Result<Json<schemas::Entity>, AgentError> {
+    let uid = entity_uid(&entity_type, &entity_id)?;
+    let parent = entity_uid(&parent_type, &parent_id)?;
+    let schema = schema_store.get_cedar_schema().await;
+    match data_store.remove_parent(&uid, &parent, schema).await {
+        Ok(Some(entity)) => Ok(Json::from(entity)),
+        Ok(None) => Err(AgentError::NotFound {
+            object: "Entity",
+            id: uid.to_string(),
+        }),
+        Err(err) => Err(err.into()),
+    }
+}
+
 #[openapi]
 #[put("/data/entity", format = "json", data = "<entity>")]
 pub async fn add_new_entity(
@@ -84,11 +414,7 @@ pub async fn add_new_entity(
     entity: Json<schemas::NewEntity>,
 ) -> Result<Json<schemas::Entities>, AgentError> {
     let schema = schema_store.get_cedar_schema().await;
-    let full_type = if entity.namespace.is_empty() {
-        entity.entity_type.clone()
-    } else {
-        format!("{}::{}", entity.namespace, entity.entity_type)
-    };
+    let full_type = full_type_name(&entity.namespace, &entity.entity_type);
     info!(
         "Adding new entity: type='{}', id='{}'",
         full_type, entity.entity_id
@@ -104,16 +430,10 @@ pub async fn add_new_entity(
         "parents": []
     }));
     let new_entity = vec![new_entity];
-    let existing_entities = data_store.get_entities().await;
 
     // check if the entity already exists
-    if existing_entities.clone().into_iter().any(|e| {
-        if let Some(uid) = e.get().get("uid") {
-            return uid.get("id").unwrap() == &Value::String(entity.entity_id.clone())
-                && uid.get("type").unwrap() == &Value::String(full_type.clone());
-        }
-        false
-    }) {
+    let uid = entity_uid(&full_type, &entity.entity_id)?;
+    if data_store.get_entity(&uid).await.is_some() {
         return Err(AgentError::Duplicate {
             object: "Entity",
             id: entity.entity_id.clone(),
@@ -121,12 +441,22 @@ pub async fn add_new_entity(
     }
 
     // add new entity to existing entities atomically
-    match data_store.add_entities(new_entity.into_iter().collect(), schema).await {
-        Ok(entities) => Ok(Json::from(entities)),
-        Err(err) => Err(AgentError::BadRequest {
-            reason: err.to_string(),
-        }),
+    if let Err(err) = data_store
+        .add_entities(new_entity.into_iter().collect(), schema)
+        .await
+    {
+        return Err(err.into());
     }
+    Ok(Json::from(data_store.get_entities().await))
+}
+
+/// Make sure the entity has an `attrs` object and return it
+fn entity_attrs(entity: &mut schemas::Entity) -> Option<&mut Map<String, Value>> {
+    let entity = entity.get_mut().as_object_mut()?;
+    entity
+        .entry("attrs")
+        .or_insert_with(|| Value::Object(Map::new()))
+        .as_object_mut()
 }
 
 #[openapi]
@@ -137,94 +467,40 @@ pub async fn update_entity_attribute(
     schema_store: &State<Box<dyn SchemaStore>>,
     entity_attribute: Json<schemas::EntityAttributeWithValue>,
 ) -> Result<Json<schemas::Entity>, AgentError> {
-    let full_type = if entity_attribute.namespace.is_empty() {
-        entity_attribute.entity_type.clone()
-    } else {
-        format!(
-            "{}::{}",
-            entity_attribute.namespace, entity_attribute.entity_type
-        )
-    };
+    let entity_attribute = entity_attribute.into_inner();
+    let full_type = full_type_name(&entity_attribute.namespace, &entity_attribute.entity_type);
     info!(
         "Updating attribute '{}' on entity type='{}' id='{}'",
         entity_attribute.attribute_name, full_type, entity_attribute.entity_id
     );
-    let entity = data_store
-        .inner()
-        .get_entities()
-        .await
-        .into_iter()
-        .find(|e| {
-            let uid = e.get().get("uid");
-            let id_match = uid
-                .and_then(|u| u.get("id"))
-                .map(|v| v == &Value::String(entity_attribute.entity_id.clone()))
-                .unwrap_or(false);
-            let type_match = uid
-                .and_then(|u| u.get("type"))
-                .map(|v| v == &Value::String(full_type.clone()))
-                .unwrap_or(false);
-            id_match && type_match
-        });
-    if entity.is_none() {
-        return Err(AgentError::NotFound {
+    let uid = entity_uid(&full_type, &entity_attribute.entity_id)?;
+    let value = schema_store.get_internal_schema().await.coerce_attribute(
+        &full_type,
+        &entity_attribute.attribute_name,
+        entity_attribute.attribute_value,
+    );
+    let attribute_name = entity_attribute.attribute_name;
+
+    // Edit the stored entity under the store's write lock, with schema validation
+    let updated = data_store
+        .update_entity(
+            &uid,
+            Box::new(move |entity| {
+                if let Some(attrs) = entity_attrs(entity) {
+                    attrs.insert(attribute_name, value);
+                }
+                Ok(())
+            }),
+            schema_store.get_cedar_schema().await,
+        )
+        .await?;
+    match updated {
+        Some(entity) => Ok(Json::from(entity)),
+        None => Err(AgentError::NotFound {
             object: "Entity",
             id: format!("{}::{}", full_type, entity_attribute.entity_id),
-        });
-    }
-
-    let mut entity = entity.unwrap().clone();
-
-    // Ensure the entity has an "attrs" object
-    if entity.get().get("attrs").is_none() {
-        entity
-            .get_mut()
-            .as_object_mut()
-            .and_then(|obj| obj.insert("attrs".to_string(), Value::Object(Map::new())));
-    }
-
-    // Update the attribute value
-    if let Some(attrs) = entity
-        .get_mut()
-        .get_mut("attrs")
-        .and_then(|attr| attr.as_object_mut())
-    {
-        attrs.insert(
-            entity_attribute.attribute_name.clone(),
-            Value::String(entity_attribute.attribute_value.clone()),
-        );
-    }
-
-    // Get all entities and update the specific one
-    let mut entities = data_store.inner().get_entities().await;
-
-    // Find and replace the entity with the updated one (match by id and type)
-    for e in entities.iter_mut() {
-        let uid = e.get().get("uid");
-        let id_match = uid
-            .and_then(|u| u.get("id"))
-            .map(|v| v == &Value::String(entity_attribute.entity_id.clone()))
-            .unwrap_or(false);
-        let type_match = uid
-            .and_then(|u| u.get("type"))
-            .map(|v| v == &Value::String(full_type.clone()))
-            .unwrap_or(false);
-        if id_match && type_match {
-            *e = entity.clone();
-            break;
-        }
+        }),
     }
-
-    // Update entities with schema validation
-    data_store
-        .inner()
-        .update_entities(entities, schema_store.get_cedar_schema().await)
-        .await
-        .map_err(|err| AgentError::BadRequest {
-            reason: err.to_string(),
-        })?;
-
-    Ok(Json::from(entity.clone()))
 }
 
 #[openapi]
@@ -235,84 +511,45 @@ pub async fn delete_entity_attribute(
     schema_store: &State<Box<dyn SchemaStore>>,
     entity_attribute: Json<schemas::EntityAttribute>,
 ) -> Result<Json<schemas::Entity>, AgentError> {
-    let full_type = if entity_attribute.namespace.is_empty() {
-        entity_attribute.entity_type.clone()
-    } else {
-        format!(
-            "{}::{}",
-            entity_attribute.namespace, entity_attribute.entity_type
-        )
-    };
+    let full_type = full_type_name(&entity_attribute.namespace, &entity_attribute.entity_type);
     info!(
         "Deleting attribute '{}' on entity type='{}' id='{}'",
         entity_attribute.attribute_name, full_type, entity_attribute.entity_id
     );
-    let entity = data_store
-        .inner()
-        .get_entities()
-        .await
-        .into_iter()
-        .find(|e| {
-            let uid = e.get().get("uid");
-            let id_match = uid
-                .and_then(|u| u.get("id"))
-                .map(|v| v == &Value::String(entity_attribute.entity_id.clone()))
-                .unwrap_or(false);
-            let type_match = uid
-                .and_then(|u| u.get("type"))
-                .map(|v| v == &Value::String(full_type.clone()))
-                .unwrap_or(false);
-            id_match && type_match
-        });
-    if entity.is_none() {
-        return Err(AgentError::NotFound {
+    let uid = entity_uid(&full_type, &entity_attribute.entity_id)?;
+    let attribute_name = entity_attribute.attribute_name.clone();
+    let attribute_id = format!(
+        "{}::{}#{}",
+        entity_attribute.entity_type, entity_attribute.entity_id, entity_attribute.attribute_name
+    );
+
+    let updated = data_store
+        .update_entity(
+            &uid,
+            Box::new(move |entity| {
+                let removed = entity
+                    .get_mut()
+                    .get_mut("attrs")
+                    .and_then(|attr| attr.as_object_mut())
+                    .and_then(|attr| attr.remove(&attribute_name));
+                match removed {
+                    Some(_) => Ok(()),
+                    None => Err(StoreError::NotFound {
+                        object: "Attribute",
+                        id: attribute_id,
+                    }),
+                }
+            }),
+            schema_store.get_cedar_schema().await,
+        )
+        .await?;
+    match updated {
+        Some(entity) => Ok(Json::from(entity)),
+        None => Err(AgentError::NotFound {
             object: "Entity",
             id: format!("{}::{}", full_type, entity_attribute.entity_id),
-        });
-    }
-
-    let mut entity = entity.unwrap().clone();
-    let removed = entity
-        .get_mut()
-        .get_mut("attrs")
-        .and_then(|attr| attr.as_object_mut())
-        .and_then(|attr| attr.remove(&entity_attribute.attribute_name));
-    if removed.is_none() {
-        return Err(AgentError::NotFound {
-            object: "Attribute",
-            id: format!(
-                "{}::{}#{}",
-                entity_attribute.entity_type,
-                entity_attribute.entity_id,
-                entity_attribute.attribute_name
-            ),
-        });
+        }),
     }
-
-    let entities = data_store.inner().get_entities().await;
-    let mut entities = entities.clone();
-    entities.retain(|e| {
-        let uid = e.get().get("uid");
-        let id_match = uid
-            .and_then(|u| u.get("id"))
-            .map(|v| v == &Value::String(entity_attribute.entity_id.clone()))
-            .unwrap_or(false);
-        let type_match = uid
-            .and_then(|u| u.get("type"))
-            .map(|v| v == &Value::String(full_type.clone()))
-            .unwrap_or(false);
-        !(id_match && type_match)
-    });
-    entities.extend(vec![entity.clone()].into_iter());
-    data_store
-        .inner()
-        .update_entities(entities, schema_store.get_cedar_schema().await)
-        .await
-        .map_err(|err| AgentError::BadRequest {
-            reason: err.to_string(),
-        })?;
-
-    Ok(Json::from(entity.clone()))
 }
 
 #[openapi]
@@ -323,114 +560,51 @@ pub async fn patch_entity_attributes(
     schema_store: &State<Box<dyn SchemaStore>>,
     update_request: Json<schemas::UpdateEntityAttributes>,
 ) -> Result<Json<schemas::Entity>, AgentError> {
-    let full_type = if update_request.namespace.is_empty() {
-        update_request.entity_type.clone()
-    } else {
-        format!(
-            "{}::{}",
-            update_request.namespace, update_request.entity_type
-        )
-    };
+    let update_request = update_request.into_inner();
+    let full_type = full_type_name(&update_request.namespace, &update_request.entity_type);
     info!(
         "Patching attributes on entity type='{}' id='{}'",
         full_type, update_request.entity_id
     );
-
-    // Find the entity
-    let entity = data_store
-        .inner()
-        .get_entities()
-        .await
+    let uid = entity_uid(&full_type, &update_request.entity_id)?;
+    let schema = schema_store.get_internal_schema().await;
+    let attributes: Vec<(String, Value)> = update_request
+        .attributes
         .into_iter()
-        .find(|e| {
-            let uid = e.get().get("uid");
-            let id_match = uid
-                .and_then(|u| u.get("id"))
-                .map(|v| v == &Value::String(update_request.entity_id.clone()))
-                .unwrap_or(false);
-            let type_match = uid
-                .and_then(|u| u.get("type"))
-                .map(|v| v == &Value::String(full_type.clone()))
-                .unwrap_or(false);
-            id_match && type_match
-        });
-
-    if entity.is_none() {
-        return Err(AgentError::NotFound {
+        .map(|(attr_name, attr_value)| {
+            let value = schema.coerce_attribute(&full_type, &attr_name, attr_value);
+            (attr_name, value)
+        })
+        .collect();
+    let parents: Option<Vec<Value>> = update_request
+        .parents
+        .map(|parents| parents.into_iter().map(Value::Object).collect());
+
+    // Edit the stored entity under the store's write lock, with schema validation
+    let updated = data_store
+        .update_entity(
+            &uid,
+            Box::new(move |entity| {
+                if let Some(attrs) = entity_attrs(entity) {
+                    attrs.extend(attributes);
+                }
+                if let Some(parents) = parents {
+                    if let Some(entity) = entity.get_mut().as_object_mut() {
+                        entity.insert("parents".to_string(), Value::Array(parents));
+                    }
+                }
+                Ok(())
+            }),
+            schema_store.get_cedar_schema().await,
+        )
+        .await?;
+    match updated {
+        Some(entity) => Ok(Json::from(entity)),
+        None => Err(AgentError::NotFound {
             object: "Entity",
             id: format!("{}::{}", full_type, update_request.entity_id),
-        });
-    }
-
-    let mut entity = entity.unwrap().clone();
-
-    // Ensure the entity has an "attrs" object
-    if entity.get().get("attrs").is_none() {
-        entity
-            .get_mut()
-            .as_object_mut()
-            .and_then(|obj| obj.insert("attrs".to_string(), Value::Object(Map::new())));
-    }
-
-    // Update all attributes from the request
-    if let Some(attrs) = entity
-        .get_mut()
-        .get_mut("attrs")
-        .and_then(|attr| attr.as_object_mut())
-    {
-        for (attr_name, attr_value) in &update_request.attributes {
-            attrs.insert(attr_name.clone(), Value::String(attr_value.clone()));
-        }
-    }
-
-    // Update parents if provided
-    if let Some(new_parents) = &update_request.parents {
-        let parents_array: Vec<Value> = new_parents
-            .iter()
-            .map(|parent_map| {
-                let mut parent_obj = Map::new();
-                for (k, v) in parent_map {
-                    parent_obj.insert(k.clone(), v.clone());
-                }
-                Value::Object(parent_obj)
-            })
-            .collect();
-        entity
-            .get_mut()
-            .as_object_mut()
-            .and_then(|obj| obj.insert("parents".to_string(), Value::Array(parents_array)));
-    }
-
-    // Get all entities and update the specific one
-    let mut entities = data_store.inner().get_entities().await;
-
-    // Find and replace the entity with the updated one (match by id and type)
-    for e in entities.iter_mut() {
-        let uid = e.get().get("uid");
-        let id_match = uid
-            .and_then(|u| u.get("id"))
-            .map(|v| v == &Value::String(update_request.entity_id.clone()))
-            .unwrap_or(false);
-        let type_match = uid
-            .and_then(|u| u.get("type"))
-            .map(|v| v == &Value::String(full_type.clone()))
-            .unwrap_or(false);
-        if id_match && type_match {
-            *e = entity.clone();
-            break;
-        }
+        }),
     }
-
-    // Update entities with schema validation (no duplicate check - this is for updating existing entities)
-    data_store
-        .inner()
-        .update_entities(entities, schema_store.get_cedar_schema().await)
-        .await
-        .map_err(|err| AgentError::BadRequest {
-            reason: err.to_string(),
-        })?;
-
-    Ok(Json::from(entity.clone()))
 }
 
 /*
@@ -452,39 +626,29 @@ pub async fn add_single_data_entry(
         });
     }
     let new_entity = entities.into_inner().into_iter().last().unwrap();
-    let existing_entities = data_store.get_entities().await;
 
     // check duplicate
-    if let Some(uid) = new_entity.get().get("uid") {
-        if let (Some(id), Some(typ)) = (uid.get("id"), uid.get("type")) {
-            if let (Some(id_str), Some(typ_str)) = (id.as_str(), typ.as_str()) {
-                if existing_entities.clone().into_iter().any(|e| {
-                    if let Some(euid) = e.get().get("uid") {
-                        return euid.get("id") == Some(&Value::String(id_str.to_string()))
-                            && euid.get("type") == Some(&Value::String(typ_str.to_string()));
-                    }
-                    false
-                }) {
-                    warn!(
-                        "Duplicate entity detected when adding single entry: {}::{}",
-                        typ_str, id_str
-                    );
-                    return Err(AgentError::Duplicate {
-                        object: "Entity",
-                        id: format!("{}::{}", typ_str, id_str),
-                    });
-                }
-            }
+    if let Some(uid) = payload_uid(&new_entity) {
+        if data_store.get_entity(&uid).await.is_some() {
+            warn!(
+                "Duplicate entity detected when adding single entry: {}",
+                uid
+            );
+            return Err(AgentError::Duplicate {
+                object: "Entity",
+                id: format!("{}::{}", uid.type_name(), uid.id().unescaped()),
+            });
         }
     }
 
     // add new entities to existing entities atomically
-    match data_store.add_entities(vec![new_entity].into_iter().collect(), schema).await {
-        Ok(entities) => Ok(Json::from(entities)),
-        Err(err) => Err(AgentError::BadRequest {
-            reason: err.to_string(),
-        }),
+    if let Err(err) = data_store
+        .add_entities(vec![new_entity].into_iter().collect(), schema)
+        .await
+    {
+        return Err(err.into());
     }
+    Ok(Json::from(data_store.get_entities().await))
 }
 
 #[openapi]
@@ -500,7 +664,10 @@ pub async fn update_single_data_entry(
     let new_entity = if entities.len() == 1 {
         entities.into_inner().into_iter().next().unwrap()
     } else {
-        warn!("Validation failed: Exactly one entity is required, received {}", entities.len());
+        warn!(
+            "Validation failed: Exactly one entity is required, received {}",
+            entities.len()
+        );
         return Err(AgentError::BadRequest {
             reason: "Exactly one entity is required".to_string(),
         });
@@ -525,7 +692,10 @@ pub async fn update_single_data_entry(
         }
 
         if !matched {
-            warn!("Validation failed: Entity id mismatch. Path: {}, Payload: {} (or {})", entity_id, payload_id, full_payload_uid);
+            warn!(
+                "Validation failed: Entity id mismatch. Path: {}, Payload: {} (or {})",
+                entity_id, payload_id, full_payload_uid
+            );
             return Err(AgentError::BadRequest {
                 reason: format!(
                     "Entity id/UID in payload ('{}' / '{}') does not match path id ('{}')",
@@ -534,26 +704,15 @@ pub async fn update_single_data_entry(
             });
         }
     }
-    let existing_entities = data_store.get_entities().await;
 
     // Check if entity already exists - if so, just return success (idempotent)
-    if let Some(uid) = new_entity.get().get("uid") {
-        if let (Some(id), Some(typ)) = (uid.get("id"), uid.get("type")) {
-            if let (Some(id_str), Some(typ_str)) = (id.as_str(), typ.as_str()) {
-                if existing_entities.clone().into_iter().any(|e| {
-                    if let Some(euid) = e.get().get("uid") {
-                        return euid.get("id") == Some(&Value::String(id_str.to_string()))
-                            && euid.get("type") == Some(&Value::String(typ_str.to_string()));
-                    }
-                    false
-                }) {
-                    info!(
-                        "Entity already exists, returning success (idempotent): {}::{}",
-                        typ_str, id_str
-                    );
-                    return Ok(Json::from(new_entity));
-                }
-            }
+    if let Some(uid) = payload_uid(&new_entity) {
+        if data_store.get_entity(&uid).await.is_some() {
+            info!(
+                "Entity already exists, returning success (idempotent): {}",
+                uid
+            );
+            return Ok(Json::from(new_entity));
         }
     }
 
@@ -561,17 +720,21 @@ pub async fn update_single_data_entry(
     info!("Creating new entity: {:#?}", new_entity);
 
     // Persist the new entity to the data store atomically
-    match data_store.add_entities(vec![new_entity.clone()].into_iter().collect(), schema).await {
+    match data_store
+        .add_entities(vec![new_entity.clone()].into_iter().collect(), schema)
+        .await
+    {
         Ok(_) => {
-            info!("Successfully added entity: {:?}", new_entity.get().get("uid"));
+            info!(
+                "Successfully added entity: {:?}",
+                new_entity.get().get("uid")
+            );
             Ok(Json::from(new_entity))
-        },
+        }
         Err(err) => {
             warn!("Failed to add entity: {}", err);
-            Err(AgentError::BadRequest {
-                reason: err.to_string(),
-            })
-        },
+            Err(err.into())
+        }
     }
 }
 
@@ -580,44 +743,38 @@ pub async fn update_single_data_entry(
 pub async fn delete_single_data_entry(
     _auth: ApiKey,
     data_store: &State<Box<dyn DataStore>>,
-    schema_store: &State<Box<dyn SchemaStore>>,
     entity_id: String,
 ) -> Result<status::NoContent, AgentError> {
-    let schema = schema_store.get_cedar_schema().await;
-    let existing_entities = data_store.get_entities().await;
     info!("Deleting single entity with id: {}", entity_id);
-    let original_len = existing_entities.len();
-    let mut entities = existing_entities.clone();
-    entities.retain(|e| {
-        if let Some(uid) = e.get().get("uid") {
-            let payload_id = uid.get("id").and_then(|v| v.as_str()).unwrap_or("");
-            let payload_type = uid.get("type").and_then(|v| v.as_str()).unwrap_or("");
-            let full_payload_uid = format!("{}::\"{}\"", payload_type, payload_id);
 
-            if entity_id == payload_id || entity_id == full_payload_uid {
-                return false;
-            }
-
-            // Try robust comparison by parsing path entity_id as a Cedar UID
-            if let Ok(path_uid) = EntityUid::from_str(&entity_id) {
-                if path_uid.to_string() == full_payload_uid {
-                    return false;
-                }
-            }
-        }
-        true
-    });
-    if entities.len() == original_len {
-        return Err(AgentError::NotFound {
-            object: "Entity",
-            id: entity_id,
+    // The id is either a full Cedar UID or the id of a single entity of any type
+    let uids: Vec<EntityUid> = match EntityUid::from_str(&entity_id) {
+        Ok(uid) => vec![uid],
+        Err(_) => data_store
+            .entities()
+            .await
+            .iter()
+            .map(|e| e.uid())
+            .filter(|uid| uid.id().unescaped() == entity_id)
+            .collect(),
+    };
+    if uids.len() > 1 {
+        let candidates: Vec<String> = uids.iter().map(|uid| uid.to_string()).collect();
+        return Err(AgentError::Conflict {
+            reason: format!(
+                "Entity id {} matches more than one entity, delete one of {} by its full UID",
+                entity_id,
+                candidates.join(", ")
+            ),
         });
     }
 
-    match data_store.update_entities(entities, schema).await {
-        Ok(_) => Ok(status::NoContent),
-        Err(err) => Err(AgentError::BadRequest {
-            reason: err.to_string(),
+    match data_store.remove_entities(uids).await {
+        Ok(removed) if removed.is_empty() => Err(AgentError::NotFound {
+            object: "Entity",
+            id: entity_id,
         }),
+        Ok(_) => Ok(status::NoContent),
+        Err(err) => Err(err.into()),
     }
 }
@@ -0,0 +1,82 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use rocket::serde::json::Json;
+use rocket::{delete, get, put, State};
+use rocket_okapi::openapi;
+
+use crate::authn::ApiKey;
+use crate::errors::response::AgentError;
+use crate::logger::{Levels, LogController};
+use crate::schemas::logging as schemas;
+use log::info;
+
+impl From<Levels> for schemas::LogLevels {
+    fn from(levels: Levels) -> Self {
+        schemas::LogLevels {
+            level: levels.root.into(),
+            modules: levels
+                .modules
+                .into_iter()
+                .map(|(module, level)| (module, level.into()))
+                .collect(),
+        }
+    }
+}
+
+#[openapi]
+#[get("/admin/logging")]
+pub async fn get_log_levels(
+    _auth: ApiKey,
+    log_controller: &State<Arc<LogController>>,
+) -> Result<Json<schemas::LogLevels>, AgentError> {
+    Ok(Json::from(schemas::LogLevels::from(
+        log_controller.levels(),
+    )))
+}
+
+#[openapi]
+#[put("/admin/logging", format = "json", data = "<update>")]
+pub async fn update_log_level(
+    _auth: ApiKey,
+    log_controller: &State<Arc<LogController>>,
+    update: Json<schemas::LogLevelUpdate>,
+) -> Result<Json<schemas::LogLevels>, AgentError> {
+    let update = update.into_inner();
+    info!(
+        "Setting log level of {} to {:?}",
+        update.module.as_deref().unwrap_or("root"),
+        update.level
+    );
+    if update.module.as_ref().is_some_and(|m| m.trim().is_empty()) {
+        return Err(AgentError::BadRequest {
+            reason: "The module name can not be empty".to_string(),
+        });
+    }
+
+    match log_controller.set_level(
+        update.module,
+        update.level.into(),
+        update.ttl_seconds.map(Duration::from_secs),
+    ) {
+        Ok(levels) => Ok(Json::from(schemas::LogLevels::from(levels))),
+        Err(err) => Err(AgentError::BadRequest {
+            reason: err.to_string(),
+        }),
+    }
+}
+
+#[openapi]
+#[delete("/admin/logging")]
+pub async fn reset_log_levels(
+    _auth: ApiKey,
+    log_controller: &State<Arc<LogController>>,
+) -> Result<Json<schemas::LogLevels>, AgentError> {
+    info!("Resetting log levels");
+    match log_controller.reset() {
+        Ok(levels) => Ok(Json::from(schemas::LogLevels::from(levels))),
+        Err(err) => Err(AgentError::BadRequest {
+            reason: err.to_string(),
+        }),
+    }
+}
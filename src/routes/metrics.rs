@@ -0,0 +1,29 @@
+use std::sync::Arc;
+
+use rocket::serde::json::Json;
+use rocket::{get, State};
+use rocket_okapi::openapi;
+
+use crate::authn::ApiKey;
+use crate::errors::response::AgentError;
+use crate::schemas::metrics::Metrics;
+use crate::services::authorization::cache::DecisionCache;
+use crate::services::authorization::shadow::ShadowMonitor;
+use crate::services::policies::PolicyStore;
+
+#[openapi]
+#[get("/admin/metrics")]
+pub async fn get_metrics(
+    _auth: ApiKey,
+    decision_cache: &State<Arc<DecisionCache>>,
+    shadow_monitor: &State<Arc<ShadowMonitor>>,
+    policy_store: &State<Box<dyn PolicyStore>>,
+) -> Result<Json<Metrics>, AgentError> {
+    let shadow = policy_store.shadow_policy_set().await;
+    Ok(Json::from(Metrics {
+        decision_cache: decision_cache
+            .is_enabled()
+            .then(|| decision_cache.metrics()),
+        shadow: shadow.map(|_| shadow_monitor.metrics()),
+    }))
+}
@@ -3,9 +3,15 @@ use rocket::response::status;
 use rocket_okapi::openapi;
 
 pub mod authorization;
+pub mod changes;
 pub mod data;
+pub mod logging;
+pub mod metrics;
 pub mod policies;
+pub mod replay;
 pub mod schema;
+pub mod shadow;
+pub mod test_cases;
 
 #[openapi]
 #[get("/")]
@@ -1,4 +1,5 @@
 use std::borrow::Borrow;
+use std::time::Duration;
 
 use rocket::response::status;
 use rocket::serde::json::Json;
@@ -7,20 +8,26 @@ use rocket_okapi::openapi;
 
 use crate::authn::ApiKey;
 use crate::errors::response::AgentError;
+use crate::schemas::analysis::AnalysisReport;
 use crate::schemas::policies as schemas;
-use crate::services::policies::errors::PolicyStoreError;
+use crate::services::data::DataStore;
+use crate::services::policies::analysis;
 use crate::services::policies::PolicyStore;
 use crate::services::schema::SchemaStore;
 use log::{info, warn};
 
 #[openapi]
-#[get("/policies")]
+#[get("/policies?<query..>")]
 pub async fn get_policies(
     _auth: ApiKey,
     policy_store: &State<Box<dyn PolicyStore>>,
+    data_store: &State<Box<dyn DataStore>>,
+    query: schemas::PolicyQuery,
 ) -> Result<Json<Vec<schemas::Policy>>, AgentError> {
-    info!("Fetching all policies");
-    Ok(Json::from(policy_store.get_policies().await))
+    info!("Fetching policies matching {:?}", query);
+    let entities = data_store.entities().await;
+    let policies = policy_store.query_policies(&query, &entities).await?;
+    Ok(Json::from(policies))
 }
 
 #[openapi]
@@ -31,52 +38,28 @@ pub async fn get_policy(
     policy_store: &State<Box<dyn PolicyStore>>,
 ) -> Result<Json<schemas::Policy>, AgentError> {
     info!("Fetching policy with id='{}'", id);
-    match policy_store.get_policy(id.borrow()).await {
-        Ok(policy) => Ok(Json::from(policy)),
-        Err(_) => Err(AgentError::NotFound {
-            id,
-            object: "policy",
-        }),
-    }
+    let policy = policy_store.get_policy(id.borrow()).await?;
+    Ok(Json::from(policy))
 }
 
 #[openapi]
 #[post("/policies", format = "json", data = "<policy>")]
 pub async fn create_policy(
-    _auth: ApiKey,
+    auth: ApiKey,
     policy: Json<schemas::Policy>,
     policy_store: &State<Box<dyn PolicyStore>>,
     schema_store: &State<Box<dyn SchemaStore>>,
 ) -> Result<Json<schemas::Policy>, AgentError> {
-    let policy = policy.into_inner();
+    let mut policy = policy.into_inner();
+    policy.claimed_author = auth.claimed_caller();
     let schema = schema_store.get_cedar_schema().await;
     info!("Creating policy with id='{}'", policy.id);
 
-    let added_policy = policy_store.create_policy(policy.borrow(), schema).await;
-    match added_policy {
-        Ok(p) => Ok(Json::from(p)),
-        Err(e) => {
-            if let Some(policy_store_error) = e.downcast_ref::<PolicyStoreError>() {
-                match policy_store_error {
-                    PolicyStoreError::PolicyInvalid(_, reason) => Err(AgentError::BadRequest {
-                        reason: reason.clone(),
-                    }),
-                    PolicyStoreError::PolicyParseError(parse_errors) => {
-                        Err(AgentError::BadRequest {
-                            reason: format!("Policy parsing failed: {}", parse_errors),
-                        })
-                    }
-                    _ => Err(AgentError::BadRequest {
-                        reason: format!("Policy error: {}", policy_store_error),
-                    }),
-                }
-            } else {
-                warn!("Duplicate policy detected while creating");
-                Err(AgentError::Duplicate {
-                    id: policy.id,
-                    object: "policy",
-                })
-            }
+    match policy_store.create_policy(policy.borrow(), schema).await {
+        Ok(policy) => Ok(Json::from(policy)),
+        Err(err) => {
+            warn!("Failed to create policy with id='{}': {}", policy.id, err);
+            Err(err.into())
         }
     }
 }
@@ -84,63 +67,34 @@ pub async fn create_policy(
 #[openapi]
 #[put("/policies", format = "json", data = "<policy>")]
 pub async fn update_policies(
-    _auth: ApiKey,
+    auth: ApiKey,
     policy: Json<Vec<schemas::Policy>>,
     policy_store: &State<Box<dyn PolicyStore>>,
     schema_store: &State<Box<dyn SchemaStore>>,
 ) -> Result<Json<Vec<schemas::Policy>>, AgentError> {
+    let mut policies = policy.into_inner();
+    for policy in policies.iter_mut() {
+        policy.claimed_author = auth.claimed_caller();
+    }
     let schema = schema_store.get_cedar_schema().await;
     info!("Updating policies in bulk");
 
-    let updated_policy = policy_store
-        .update_policies(policy.into_inner(), schema)
-        .await;
-    match updated_policy {
-        Ok(p) => Ok(Json::from(p)),
-        Err(e) => {
-            if let Some(policy_store_error) = e.downcast_ref::<PolicyStoreError>() {
-                match policy_store_error {
-                    PolicyStoreError::PolicyInvalid(_, reason) => {
-                        return Err(AgentError::BadRequest {
-                            reason: reason.clone(),
-                        });
-                    }
-                    PolicyStoreError::PolicyParseError(parse_errors) => {
-                        return Err(AgentError::BadRequest {
-                            reason: format!("Policy parsing failed: {}", parse_errors),
-                        });
-                    }
-                    _ => {}
-                }
-            }
-            if let Some(io_err) = e.downcast_ref::<std::io::Error>() {
-                if io_err.kind() == std::io::ErrorKind::AlreadyExists {
-                    warn!("Duplicate policy id found in bulk update payload");
-                    // Try to extract the id from the error message: "Policy with id <id> already exists"
-                    let msg = io_err.to_string();
-                    let dup_id = msg
-                        .strip_prefix("Policy with id ")
-                        .and_then(|s| s.strip_suffix(" already exists"))
-                        .unwrap_or("")
-                        .to_string();
-                    return Err(AgentError::Duplicate {
-                        object: "policy",
-                        id: dup_id,
-                    });
-                }
-            }
-            Err(AgentError::BadRequest {
-                reason: e.to_string(),
-            })
+    match policy_store.update_policies(policies, schema).await {
+        Ok(policies) => Ok(Json::from(policies)),
+        Err(err) => {
+            warn!("Failed to update policies: {}", err);
+            Err(err.into())
         }
     }
 }
 
+/// Replace a policy, or create it if missing unless `mode` is `create`.
 #[openapi]
-#[put("/policies/<id>", format = "json", data = "<policy>")]
+#[put("/policies/<id>?<mode>", format = "json", data = "<policy>")]
 pub async fn update_policy(
-    _auth: ApiKey,
+    auth: ApiKey,
     id: String,
+    mode: Option<schemas::PolicyWriteMode>,
     policy: Json<schemas::PolicyUpdate>,
     policy_store: &State<Box<dyn PolicyStore>>,
     schema_store: &State<Box<dyn SchemaStore>>,
@@ -148,33 +102,17 @@ pub async fn update_policy(
     let schema = schema_store.get_cedar_schema().await;
     info!("Updating policy with id='{}'", id);
 
-    let updated_policy = policy_store
-        .update_policy(id, policy.into_inner(), schema)
-        .await;
-
-    match updated_policy {
-        Ok(p) => Ok(Json::from(p)),
-        Err(e) => {
-            if let Some(policy_store_error) = e.downcast_ref::<PolicyStoreError>() {
-                match policy_store_error {
-                    PolicyStoreError::PolicyInvalid(_, reason) => {
-                        return Err(AgentError::BadRequest {
-                            reason: reason.clone(),
-                        });
-                    }
-                    PolicyStoreError::PolicyParseError(parse_errors) => {
-                        return Err(AgentError::BadRequest {
-                            reason: format!("Policy parsing failed: {}", parse_errors),
-                        });
-                    }
-                    _ => {}
-                }
-            }
-            Err(AgentError::BadRequest {
-                reason: e.to_string(),
-            })
+    let mut policy = policy.into_inner();
+    policy.claimed_author = auth.claimed_caller();
+    let mode = mode.unwrap_or_default();
+    let updated_policy = match mode {
+        schemas::PolicyWriteMode::Upsert => policy_store.update_policy(id, policy, schema).await?,
+        schemas::PolicyWriteMode::Create => {
+            let policy = schemas::Policy::from_policy_update(id, policy);
+            policy_store.create_policy(&policy, schema).await?
         }
-    }
+    };
+    Ok(Json::from(updated_policy))
 }
 
 #[openapi]
@@ -185,11 +123,53 @@ pub async fn delete_policy(
     policy_store: &State<Box<dyn PolicyStore>>,
 ) -> Result<status::NoContent, AgentError> {
     info!("Deleting policy with id='{}'", id);
-    match policy_store.delete_policy(id.borrow()).await {
-        Ok(_p) => Ok(status::NoContent),
-        Err(_err) => Err(AgentError::NotFound {
-            id,
-            object: "Policy",
-        }),
-    }
+    policy_store.delete_policy(id.borrow()).await?;
+    Ok(status::NoContent)
+}
+
+#[openapi]
+#[post("/policies/<id>/enable")]
+pub async fn enable_policy(
+    _auth: ApiKey,
+    id: String,
+    policy_store: &State<Box<dyn PolicyStore>>,
+) -> Result<Json<schemas::Policy>, AgentError> {
+    info!("Enabling policy with id='{}'", id);
+    let policy = policy_store.enable_policy(id.borrow()).await?;
+    Ok(Json::from(policy))
+}
+
+/// Disable a policy, for `ttl_seconds` seconds if given.
+#[openapi]
+#[post("/policies/<id>/disable?<ttl_seconds>")]
+pub async fn disable_policy(
+    _auth: ApiKey,
+    id: String,
+    ttl_seconds: Option<u64>,
+    policy_store: &State<Box<dyn PolicyStore>>,
+) -> Result<Json<schemas::Policy>, AgentError> {
+    info!("Disabling policy with id='{}'", id);
+    let policy = policy_store
+        .disable_policy(id.borrow(), ttl_seconds.map(Duration::from_secs))
+        .await?;
+    Ok(Json::from(policy))
+}
+
+#[openapi]
+#[get("/analysis/policies")]
+pub async fn analyze_policies(
+    _auth: ApiKey,
+    policy_store: &State<Box<dyn PolicyStore>>,
+    data_store: &State<Box<dyn DataStore>>,
+    schema_store: &State<Box<dyn SchemaStore>>,
+) -> Result<Json<AnalysisReport>, AgentError> {
+    info!("Analyzing policies");
+    let policy_set = policy_store.policy_set().await;
+    let schema = schema_store.get_cedar_schema().await;
+    let entities = data_store.entities().await;
+    Ok(Json::from(analysis::analyze(
+        &policy_set,
+        schema.as_ref(),
+        &entities,
+    )))
 }
@@ -0,0 +1,56 @@
+use cedar_policy::Authorizer;
+use log::{info, warn};
+use rocket::serde::json::Json;
+use rocket::{post, State};
+use rocket_okapi::openapi;
+
+use crate::authn::ApiKey;
+use crate::config::Config;
+use crate::errors::response::AgentError;
+use crate::schemas::replay::{ReplayReport, ReplayRequest};
+use crate::services::authorization::replay::{self, ReplayTarget};
+use crate::services::data::DataStore;
+use crate::services::policies::PolicyStore;
+use crate::services::schema::SchemaStore;
+
+#[openapi]
+#[post("/admin/replay", format = "json", data = "<replay_request>")]
+pub async fn replay_requests(
+    _auth: ApiKey,
+    config: &State<Config>,
+    policy_store: &State<Box<dyn PolicyStore>>,
+    data_store: &State<Box<dyn DataStore>>,
+    schema_store: &State<Box<dyn SchemaStore>>,
+    authorizer: &State<Authorizer>,
+    replay_request: Json<ReplayRequest>,
+) -> Result<Json<ReplayReport>, AgentError> {
+    let replay_request = replay_request.into_inner();
+    info!("Replaying {} requests", replay_request.requests.len());
+
+    let baseline = ReplayTarget {
+        policies: policy_store.policy_set().await,
+        entities: data_store.entities().await,
+    };
+    let candidate = match replay_request.candidate {
+        Some(candidate) => {
+            let schema = schema_store.get_cedar_schema().await;
+            match baseline
+                .with_candidate(candidate, schema, config.data_integrity)
+                .await
+            {
+                Ok(candidate) => Some(candidate),
+                Err(reason) => {
+                    warn!("{}", reason);
+                    return Err(AgentError::BadRequest { reason });
+                }
+            }
+        }
+        None => None,
+    };
+    Ok(Json::from(replay::replay(
+        authorizer,
+        &replay_request.requests,
+        &baseline,
+        candidate.as_ref(),
+    )))
+}
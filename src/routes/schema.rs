@@ -1,10 +1,11 @@
 use rocket::response::status;
 use rocket::serde::json::{json, Json};
-use rocket::{delete, get, post, put, State};
+use rocket::{delete, get, patch, post, put, State};
 use rocket_okapi::openapi;
 
 use crate::authn::ApiKey;
 use crate::errors::response::AgentError;
+use crate::schemas::patch::{Patch, PatchError};
 use crate::schemas::schema::AttributeSchema;
 use crate::schemas::schema::Schema as InternalSchema;
 use crate::schemas::schema::{DeleteAttributeSchema, GenericAttributeSchema};
@@ -24,17 +25,17 @@ pub async fn get_schema(
     Ok(Json::from(schema_store.get_internal_schema().await))
 }
 
-#[openapi]
-#[put("/schema", format = "json", data = "<schema>")]
-pub async fn update_schema(
-    _auth: ApiKey,
-    schema_store: &State<Box<dyn SchemaStore>>,
-    policy_store: &State<Box<dyn PolicyStore>>,
-    data_store: &State<Box<dyn DataStore>>,
-    schema: Json<InternalSchema>,
-) -> Result<Json<InternalSchema>, AgentError> {
-    info!("Updating schema");
-    let cedar_schema: CedarSchema = match schema.clone().into_inner().try_into() {
+/// Validate the existing policies and entities against a schema before storing it.
+///
+/// A schema derived from the stored one is only stored if that one wasn't replaced meanwhile.
+async fn replace_schema(
+    schema_store: &dyn SchemaStore,
+    policy_store: &dyn PolicyStore,
+    data_store: &dyn DataStore,
+    current: Option<&InternalSchema>,
+    schema: InternalSchema,
+) -> Result<InternalSchema, AgentError> {
+    let cedar_schema: CedarSchema = match schema.clone().try_into() {
         Ok(schema) => schema,
         Err(err) => {
             return Err(AgentError::BadRequest {
@@ -69,12 +70,62 @@ pub async fn update_schema(
         }
     }
 
-    match schema_store.update_schema(schema.into_inner()).await {
-        Ok(schema) => Ok(Json::from(schema)),
-        Err(err) => Err(AgentError::BadRequest {
-            reason: err.to_string(),
-        }),
-    }
+    let stored = match current {
+        Some(current) => schema_store.swap_schema(current, schema).await,
+        None => schema_store.update_schema(schema).await,
+    };
+    stored.map_err(AgentError::from)
+}
+
+#[openapi]
+#[put("/schema", format = "json", data = "<schema>")]
+pub async fn update_schema(
+    _auth: ApiKey,
+    schema_store: &State<Box<dyn SchemaStore>>,
+    policy_store: &State<Box<dyn PolicyStore>>,
+    data_store: &State<Box<dyn DataStore>>,
+    schema: Json<InternalSchema>,
+) -> Result<Json<InternalSchema>, AgentError> {
+    info!("Updating schema");
+    replace_schema(
+        schema_store.inner().as_ref(),
+        policy_store.inner().as_ref(),
+        data_store.inner().as_ref(),
+        None,
+        schema.into_inner(),
+    )
+    .await
+    .map(Json::from)
+}
+
+#[openapi]
+#[patch("/schema", data = "<patch>")]
+pub async fn patch_schema(
+    _auth: ApiKey,
+    schema_store: &State<Box<dyn SchemaStore>>,
+    policy_store: &State<Box<dyn PolicyStore>>,
+    data_store: &State<Box<dyn DataStore>>,
+    patch: Result<Patch, PatchError>,
+) -> Result<Json<InternalSchema>, AgentError> {
+    info!("Patching schema");
+    let current = schema_store.get_internal_schema().await;
+    let patched = match patch.and_then(|patch| patch.apply(current.get())) {
+        Ok(patched) => patched,
+        Err(err) => {
+            return Err(AgentError::BadRequest {
+                reason: err.to_string(),
+            })
+        }
+    };
+    replace_schema(
+        schema_store.inner().as_ref(),
+        policy_store.inner().as_ref(),
+        data_store.inner().as_ref(),
+        Some(&current),
+        InternalSchema::from(patched),
+    )
+    .await
+    .map(Json::from)
 }
 
 #[openapi]
@@ -0,0 +1,102 @@
+use std::sync::Arc;
+
+use log::info;
+use rocket::response::status;
+use rocket::serde::json::Json;
+use rocket::{delete, get, post, put, State};
+use rocket_okapi::openapi;
+
+use crate::authn::ApiKey;
+use crate::errors::response::AgentError;
+use crate::schemas::policies::Policy;
+use crate::schemas::shadow::ShadowDisagreement;
+use crate::services::authorization::shadow::ShadowMonitor;
+use crate::services::policies::PolicyStore;
+use crate::services::schema::SchemaStore;
+
+fn shadow_not_found() -> AgentError {
+    AgentError::NotFound {
+        id: "shadow".to_owned(),
+        object: "policy set",
+    }
+}
+
+#[openapi]
+#[get("/shadow/policies")]
+pub async fn get_shadow_policies(
+    _auth: ApiKey,
+    policy_store: &State<Box<dyn PolicyStore>>,
+) -> Result<Json<Vec<Policy>>, AgentError> {
+    info!("Fetching shadow policies");
+    match policy_store.get_shadow_policies().await {
+        Some(policies) => Ok(Json::from(policies)),
+        None => Err(shadow_not_found()),
+    }
+}
+
+#[openapi]
+#[put("/shadow/policies", format = "json", data = "<policies>")]
+pub async fn update_shadow_policies(
+    auth: ApiKey,
+    policies: Json<Vec<Policy>>,
+    policy_store: &State<Box<dyn PolicyStore>>,
+    schema_store: &State<Box<dyn SchemaStore>>,
+    shadow_monitor: &State<Arc<ShadowMonitor>>,
+) -> Result<Json<Vec<Policy>>, AgentError> {
+    let mut policies = policies.into_inner();
+    for policy in policies.iter_mut() {
+        policy.claimed_author = auth.claimed_caller();
+    }
+    let schema = schema_store.get_cedar_schema().await;
+    info!("Updating shadow policies");
+    match policy_store.update_shadow_policies(policies, schema).await {
+        Ok(policies) => {
+            shadow_monitor.reset();
+            Ok(Json::from(policies))
+        }
+        Err(err) => Err(err.into()),
+    }
+}
+
+#[openapi]
+#[delete("/shadow/policies")]
+pub async fn delete_shadow_policies(
+    _auth: ApiKey,
+    policy_store: &State<Box<dyn PolicyStore>>,
+    shadow_monitor: &State<Arc<ShadowMonitor>>,
+) -> Result<status::NoContent, AgentError> {
+    info!("Deleting shadow policies");
+    match policy_store.delete_shadow_policies().await {
+        Some(_) => {
+            shadow_monitor.reset();
+            Ok(status::NoContent)
+        }
+        None => Err(shadow_not_found()),
+    }
+}
+
+#[openapi]
+#[post("/shadow/promote")]
+pub async fn promote_shadow_policies(
+    _auth: ApiKey,
+    policy_store: &State<Box<dyn PolicyStore>>,
+    shadow_monitor: &State<Arc<ShadowMonitor>>,
+) -> Result<Json<Vec<Policy>>, AgentError> {
+    info!("Promoting shadow policies");
+    match policy_store.promote_shadow_policies().await? {
+        Some(policies) => {
+            shadow_monitor.reset();
+            Ok(Json::from(policies))
+        }
+        None => Err(shadow_not_found()),
+    }
+}
+
+#[openapi]
+#[get("/shadow/disagreements")]
+pub async fn get_shadow_disagreements(
+    _auth: ApiKey,
+    shadow_monitor: &State<Arc<ShadowMonitor>>,
+) -> Result<Json<Vec<ShadowDisagreement>>, AgentError> {
+    Ok(Json::from(shadow_monitor.samples()))
+}
@@ -0,0 +1,93 @@
+use std::sync::Arc;
+
+use cedar_policy::Authorizer;
+use log::info;
+use rocket::response::status;
+use rocket::serde::json::Json;
+use rocket::{delete, get, post, put, State};
+use rocket_okapi::openapi;
+
+use crate::authn::ApiKey;
+use crate::errors::response::AgentError;
+use crate::schemas::test_cases::{TestCase, TestRunReport};
+use crate::services::data::DataStore;
+use crate::services::policies::PolicyStore;
+use crate::services::test_cases::TestCaseStore;
+
+#[openapi]
+#[get("/policy-tests")]
+pub async fn get_test_cases(
+    _auth: ApiKey,
+    test_cases: &State<Arc<TestCaseStore>>,
+) -> Result<Json<Vec<TestCase>>, AgentError> {
+    info!("Fetching policy test cases");
+    Ok(Json::from(test_cases.get_test_cases().await))
+}
+
+#[openapi]
+#[get("/policy-tests/<id>")]
+pub async fn get_test_case(
+    _auth: ApiKey,
+    id: String,
+    test_cases: &State<Arc<TestCaseStore>>,
+) -> Result<Json<TestCase>, AgentError> {
+    info!("Fetching policy test case with id='{}'", id);
+    match test_cases.get_test_case(&id).await {
+        Some(case) => Ok(Json::from(case)),
+        None => Err(AgentError::NotFound {
+            id,
+            object: "policy test case",
+        }),
+    }
+}
+
+#[openapi]
+#[put("/policy-tests/<id>", format = "json", data = "<case>")]
+pub async fn put_test_case(
+    _auth: ApiKey,
+    id: String,
+    case: Json<TestCase>,
+    test_cases: &State<Arc<TestCaseStore>>,
+) -> Result<Json<TestCase>, AgentError> {
+    info!("Storing policy test case with id='{}'", id);
+    match test_cases.put_test_case(id, case.into_inner()).await {
+        Ok(case) => Ok(Json::from(case)),
+        Err(err) => Err(AgentError::BadRequest {
+            reason: err.to_string(),
+        }),
+    }
+}
+
+#[openapi]
+#[delete("/policy-tests/<id>")]
+pub async fn delete_test_case(
+    _auth: ApiKey,
+    id: String,
+    test_cases: &State<Arc<TestCaseStore>>,
+) -> Result<status::NoContent, AgentError> {
+    info!("Deleting policy test case with id='{}'", id);
+    match test_cases.delete_test_case(&id).await {
+        Some(_) => Ok(status::NoContent),
+        None => Err(AgentError::NotFound {
+            id,
+            object: "policy test case",
+        }),
+    }
+}
+
+#[openapi]
+#[post("/policy-tests/run")]
+pub async fn run_test_cases(
+    _auth: ApiKey,
+    test_cases: &State<Arc<TestCaseStore>>,
+    policy_store: &State<Box<dyn PolicyStore>>,
+    data_store: &State<Box<dyn DataStore>>,
+    authorizer: &State<Authorizer>,
+) -> Result<Json<TestRunReport>, AgentError> {
+    info!("Running policy test cases");
+    let policies = policy_store.policy_set().await;
+    let entities = data_store.entities().await;
+    Ok(Json::from(
+        test_cases.run(authorizer, &policies, entities).await,
+    ))
+}
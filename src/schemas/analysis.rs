@@ -0,0 +1,44 @@
+use rocket_okapi::okapi::schemars;
+use rocket_okapi::okapi::schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, JsonSchema, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum FindingKind {
+    /// A permit that applies to a subset of the requests of another permit
+    Redundant,
+    /// A permit that a forbid overrides on every request it applies to
+    Overridden,
+    /// A policy whose conditions always evaluate to false under the schema
+    Unsatisfiable,
+    /// A policy that does not validate against the schema
+    Invalid,
+    /// An action of the schema that no permit applies to
+    UnusedAction,
+    /// An entity type of the schema that no policy refers to
+    UnusedEntityType,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, PartialEq)]
+pub struct Finding {
+    pub kind: FindingKind,
+    /// The policy the finding is about
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub policy: Option<String>,
+    /// The policy that subsumes or overrides it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub related_policy: Option<String>,
+    /// The unused action or entity type
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub subject: Option<String>,
+    pub explanation: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, PartialEq)]
+pub struct AnalysisReport {
+    /// Number of analyzed policies
+    pub policies: usize,
+    /// Whether the policies were checked against a schema
+    pub schema: bool,
+    pub findings: Vec<Finding>,
+}
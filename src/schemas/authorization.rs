@@ -7,11 +7,14 @@ use cedar_policy_core::authorizer::Decision;
 use cedar_policy_core::entities::err::EntitiesError;
 
 use rocket::serde::json::serde_json;
+use rocket::FromFormField;
 use rocket_okapi::okapi::schemars;
 use rocket_okapi::okapi::schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+use crate::services::data::EntitySnapshot;
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct AuthorizationCall {
     principal: Option<String>,
     action: Option<String>,
@@ -52,13 +55,15 @@ impl AuthorizationRequest {
         self.entities
     }
 
+    /// Get the request and its entities, sharing the stored entities unless the request
+    /// overrides or extends them.
     pub fn get_request_entities(
         self,
-        stored_entities: Entities,
-    ) -> Result<(Request, Entities), EntitiesError> {
+        stored_entities: EntitySnapshot,
+    ) -> Result<(Request, EntitySnapshot), EntitiesError> {
         let request_entities = match self.entities {
             None => stored_entities,
-            Some(ents) => ents.clone(),
+            Some(ents) => EntitySnapshot::from(ents),
         };
         let patched_entities = match self.additional_entities {
             None => request_entities,
@@ -67,7 +72,7 @@ impl AuthorizationRequest {
                     request_entities.iter().chain(ents.iter()).cloned(),
                     None,
                 ) {
-                    Ok(entities) => entities,
+                    Ok(entities) => EntitySnapshot::from(entities),
                     Err(err) => return Err(err),
                 }
             }
@@ -163,7 +168,7 @@ impl TryInto<AuthorizationRequest> for AuthorizationCall {
     }
 }
 
-#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, JsonSchema, PartialEq)]
 pub enum DecisionRef {
     Allow,
     /// The `Authorizer` determined that the query should be denied.
@@ -173,21 +178,56 @@ pub enum DecisionRef {
     Deny,
 }
 
-#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+impl From<Decision> for DecisionRef {
+    fn from(value: Decision) -> Self {
+        match value {
+            Decision::Allow => DecisionRef::Allow,
+            Decision::Deny => DecisionRef::Deny,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, JsonSchema, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum CacheStatus {
+    Hit,
+    Miss,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct DiagnosticsRef {
     /// `PolicyId`s of the policies that contributed to the decision.
     /// If no policies applied to the query, this set will be empty.
     reason: HashSet<String>,
     /// list of error messages which occurred
     errors: HashSet<String>,
+    /// Whether the decision was served from the decision cache, missing when it is disabled
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    cache: Option<CacheStatus>,
 }
 
-#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct AuthorizationAnswer {
     decision: DecisionRef,
     diagnostics: DiagnosticsRef,
 }
 
+impl AuthorizationAnswer {
+    pub fn with_cache_status(mut self, status: CacheStatus) -> Self {
+        self.diagnostics.cache = Some(status);
+        self
+    }
+
+    pub fn decision(&self) -> DecisionRef {
+        self.decision
+    }
+
+    /// IDs of the policies that determined the decision.
+    pub fn reasons(&self) -> &HashSet<String> {
+        &self.diagnostics.reason
+    }
+}
+
 impl Into<Response> for AuthorizationAnswer {
     fn into(self) -> Response {
         Response::new(
@@ -209,14 +249,61 @@ impl Into<Response> for AuthorizationAnswer {
 impl From<Response> for AuthorizationAnswer {
     fn from(value: Response) -> Self {
         AuthorizationAnswer {
-            decision: match value.decision() {
-                Decision::Allow => DecisionRef::Allow,
-                Decision::Deny => DecisionRef::Deny,
-            },
+            decision: DecisionRef::from(value.decision()),
             diagnostics: DiagnosticsRef {
                 reason: HashSet::from_iter(value.diagnostics().reason().map(|r| r.to_string())),
                 errors: HashSet::from_iter(value.diagnostics().errors().map(|e| e.to_string())),
+                cache: None,
             },
         }
     }
 }
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, JsonSchema, FromFormField, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum EffectRef {
+    Permit,
+    Forbid,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, JsonSchema, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum PolicyOutcome {
+    /// The policy applied to the request
+    Satisfied,
+    /// The principal, action or resource of the request is outside the policy scope
+    ScopeMismatch,
+    /// The request is in the policy scope but its `when`/`unless` conditions did not hold
+    ConditionFalse,
+    /// Evaluating the policy failed, so it was ignored
+    Error,
+}
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct PolicyEvaluation {
+    pub id: String,
+    pub effect: EffectRef,
+    pub outcome: PolicyOutcome,
+    /// Why the policy did not apply, if it did not
+    pub reason: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct EntityExplanation {
+    pub uid: String,
+    /// Whether the entity exists in the entities used for the request
+    pub found: bool,
+    pub attrs: serde_json::Value,
+    /// The transitive ancestors of the entity
+    pub ancestors: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct AuthorizationExplanation {
+    #[serde(flatten)]
+    pub answer: AuthorizationAnswer,
+    /// The outcome of every policy for the request
+    pub policies: Vec<PolicyEvaluation>,
+    /// The principal, action and resource of the request
+    pub entities: Vec<EntityExplanation>,
+}
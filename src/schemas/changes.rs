@@ -0,0 +1,36 @@
+use rocket_okapi::okapi::schemars;
+use rocket_okapi::okapi::schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, JsonSchema, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ObjectType {
+    Policy,
+    Entity,
+    Schema,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, JsonSchema, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Operation {
+    Create,
+    Update,
+    Delete,
+}
+
+/// A single change of one of the stores
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, PartialEq)]
+pub struct ChangeEvent {
+    /// Revision of the stores after the change, increasing with every change
+    pub revision: u64,
+    pub object: ObjectType,
+    /// Policy ID, entity UID, or `schema` for the schema
+    pub id: String,
+    pub operation: Operation,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, PartialEq)]
+pub struct Revision {
+    /// Revision of the last change of the stores, 0 before the first change
+    pub revision: u64,
+}
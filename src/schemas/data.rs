@@ -1,4 +1,5 @@
 use std::error::Error;
+use std::str::FromStr;
 
 use cedar_policy::Schema;
 use cedar_policy_core::entities::{
@@ -10,6 +11,7 @@ use log::debug;
 use rocket::serde::json::serde_json::{from_str, json, to_string, Map};
 use rocket::serde::json::Value;
 
+use clap::ValueEnum;
 use rocket_okapi::okapi::schemars;
 use rocket_okapi::okapi::schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
@@ -27,10 +29,34 @@ impl Entity {
     pub fn get_mut(&mut self) -> &mut Value {
         &mut self.0
     }
+
+    /// Add a parent to the entity, or remove it.
+    pub fn set_parent(&mut self, parent: &cedar_policy::EntityUid, add: bool) {
+        if let Some(parents) = self
+            .0
+            .get_mut("parents")
+            .and_then(|parents| parents.as_array_mut())
+        {
+            parents.retain(|existing| {
+                cedar_policy::EntityUid::from_json(existing.clone())
+                    .ok()
+                    .as_ref()
+                    != Some(parent)
+            });
+            if add {
+                parents.push(json!({
+                    "type": parent.type_name().to_string(),
+                    "id": parent.id().unescaped(),
+                }));
+            }
+        }
+    }
 }
 
 impl From<ast::Entity> for Entity {
-    fn from(value: ast::Entity) -> Self {
+    fn from(mut value: ast::Entity) -> Self {
+        // Cedar writes every ancestor as a parent, keep only the direct parents
+        value.remove_all_indirect_ancestors();
         let entity_json = EntityJson::from_entity(&value).unwrap();
         let json_string = to_string(&entity_json).unwrap();
         Self(from_str(&json_string).unwrap())
@@ -173,7 +199,8 @@ pub struct EntityAttributeWithValue {
     pub namespace: String,
     pub entity_id: String,
     pub attribute_name: String,
-    pub attribute_value: String,
+    /// The attribute value in the Cedar entity JSON format, coerced to the schema type if one exists
+    pub attribute_value: Value,
 }
 
 #[derive(Debug, Serialize, Deserialize, JsonSchema, Clone)]
@@ -199,7 +226,250 @@ pub struct UpdateEntityAttributes {
     #[serde(default)]
     pub namespace: String,
     pub entity_id: String,
-    pub attributes: std::collections::HashMap<String, String>,
+    /// Attribute values in the Cedar entity JSON format, coerced to the schema types if one exists
+    pub attributes: std::collections::HashMap<String, Value>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub parents: Option<Vec<Map<String, Value>>>,
 }
+
+/// Reference to an entity by its full type name and id
+#[derive(Debug, Serialize, Deserialize, JsonSchema, Clone)]
+pub struct EntityReference {
+    #[serde(rename = "type")]
+    pub entity_type: String,
+    pub id: String,
+}
+
+impl From<cedar_policy::EntityUid> for EntityReference {
+    fn from(uid: cedar_policy::EntityUid) -> Self {
+        Self {
+            entity_type: uid.type_name().to_string(),
+            id: uid.id().unescaped().to_string(),
+        }
+    }
+}
+
+impl TryFrom<&EntityReference> for cedar_policy::EntityUid {
+    type Error = cedar_policy::ParseErrors;
+
+    fn try_from(reference: &EntityReference) -> Result<Self, Self::Error> {
+        Ok(cedar_policy::EntityUid::from_type_name_and_id(
+            cedar_policy::EntityTypeName::from_str(&reference.entity_type)?,
+            cedar_policy::EntityId::new(&reference.id),
+        ))
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum FilterOperator {
+    Eq,
+    Ne,
+    Gt,
+    Gte,
+    Lt,
+    Lte,
+    /// The attribute is a set containing the value, or a string containing it
+    Contains,
+    StartsWith,
+    /// The attribute equals one of the values of an array
+    In,
+    /// The attribute is present, the value is ignored
+    Exists,
+}
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema, Clone)]
+pub struct AttributeFilter {
+    /// Attribute name, nested record attributes are separated by dots, e.g. `address.city`
+    pub attribute: String,
+    #[serde(default = "AttributeFilter::default_operator")]
+    pub op: FilterOperator,
+    #[serde(default)]
+    pub value: Value,
+}
+
+/// Unwrap `__entity` escapes so entity references compare equal in both JSON forms
+fn comparable(value: &Value) -> &Value {
+    value.get("__entity").unwrap_or(value)
+}
+
+fn compare(left: &Value, right: &Value) -> Option<std::cmp::Ordering> {
+    match (left, right) {
+        (Value::Number(left), Value::Number(right)) => left.as_f64()?.partial_cmp(&right.as_f64()?),
+        (Value::String(left), Value::String(right)) => Some(left.cmp(right)),
+        _ => None,
+    }
+}
+
+impl AttributeFilter {
+    fn default_operator() -> FilterOperator {
+        FilterOperator::Eq
+    }
+
+    /// Check the filter against the `attrs` of an entity in the Cedar JSON format.
+    pub fn matches(&self, attrs: &Value) -> bool {
+        let attribute = self
+            .attribute
+            .split('.')
+            .try_fold(attrs, |value, name| value.get(name));
+        let attribute = match attribute {
+            Some(attribute) => comparable(attribute),
+            None => return self.op == FilterOperator::Ne,
+        };
+        let value = comparable(&self.value);
+        match self.op {
+            FilterOperator::Exists => true,
+            FilterOperator::Eq => attribute == value,
+            FilterOperator::Ne => attribute != value,
+            FilterOperator::Gt => compare(attribute, value).is_some_and(|o| o.is_gt()),
+            FilterOperator::Gte => compare(attribute, value).is_some_and(|o| o.is_ge()),
+            FilterOperator::Lt => compare(attribute, value).is_some_and(|o| o.is_lt()),
+            FilterOperator::Lte => compare(attribute, value).is_some_and(|o| o.is_le()),
+            FilterOperator::Contains => match (attribute, value) {
+                (Value::Array(items), value) => items.iter().any(|item| comparable(item) == value),
+                (Value::String(attribute), Value::String(value)) => {
+                    attribute.contains(value.as_str())
+                }
+                _ => false,
+            },
+            FilterOperator::StartsWith => match (attribute, value) {
+                (Value::String(attribute), Value::String(value)) => {
+                    attribute.starts_with(value.as_str())
+                }
+                _ => false,
+            },
+            FilterOperator::In => match value {
+                Value::Array(values) => values.iter().any(|item| comparable(item) == attribute),
+                _ => false,
+            },
+        }
+    }
+}
+
+/// Filters, pagination and projection of an entity query. All filters must match.
+#[derive(Debug, Serialize, Deserialize, JsonSchema, Clone, Default)]
+pub struct EntityQuery {
+    /// Entity type name without its namespace
+    pub entity_type: Option<String>,
+    /// Namespace of the entity type, empty for the default namespace
+    pub namespace: Option<String>,
+    pub id_prefix: Option<String>,
+    #[serde(default)]
+    pub attributes: Vec<AttributeFilter>,
+    /// Only return members of this entity
+    pub parent: Option<EntityReference>,
+    /// Also return indirect members of the parent, through the entity hierarchy
+    #[serde(default)]
+    pub transitive: bool,
+    pub has_children: Option<bool>,
+    #[serde(default)]
+    pub offset: usize,
+    pub limit: Option<usize>,
+    /// Fields to return besides the `uid`: `attrs`, `parents` or single attributes as `attrs.<name>`
+    pub fields: Option<Vec<String>>,
+}
+
+impl EntityQuery {
+    /// Keep only the requested fields of an entity
+    pub fn project(&self, entity: Entity) -> Entity {
+        let fields = match &self.fields {
+            Some(fields) => fields,
+            None => return entity,
+        };
+        let mut projected = Map::new();
+        if let Some(uid) = entity.get().get("uid") {
+            projected.insert("uid".to_string(), uid.clone());
+        }
+        for field in fields {
+            match field.split_once('.') {
+                Some(("attrs", attribute)) => {
+                    if let Some(value) = entity.get().get("attrs").and_then(|a| a.get(attribute)) {
+                        projected
+                            .entry("attrs")
+                            .or_insert_with(|| json!({}))
+                            .as_object_mut()
+                            .and_then(|attrs| attrs.insert(attribute.to_string(), value.clone()));
+                    }
+                }
+                _ => {
+                    if let Some(value) = entity.get().get(field) {
+                        projected.insert(field.clone(), value.clone());
+                    }
+                }
+            }
+        }
+        Entity(Value::Object(projected))
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema, Clone)]
+pub struct EntityPage {
+    /// Number of entities matching the filters
+    pub total: usize,
+    pub offset: usize,
+    pub entities: Entities,
+}
+
+/// How the data store handles parents that reference missing entities
+#[derive(
+    ValueEnum, Serialize, Deserialize, JsonSchema, Debug, Clone, Copy, PartialEq, Eq, Default,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum IntegrityMode {
+    /// Reject writes that reference missing parents and deletions of referenced entities
+    Reject,
+    /// Remove deleted entities from the parents of their children
+    Cascade,
+    /// Keep dangling references and log a warning
+    #[default]
+    Warn,
+}
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema, Clone)]
+pub struct DanglingReference {
+    pub entity: EntityReference,
+    /// The missing parent
+    pub parent: EntityReference,
+}
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema, Clone)]
+pub struct IntegrityReport {
+    pub mode: IntegrityMode,
+    pub dangling: Vec<DanglingReference>,
+    /// Entities forming a cycle in the hierarchy, each parent of the previous one
+    pub cycles: Vec<Vec<EntityReference>>,
+}
+
+/// A single change of an incremental data sync batch
+#[derive(Debug, Serialize, Deserialize, JsonSchema, Clone)]
+#[serde(tag = "op", rename_all = "lowercase")]
+pub enum DeltaOperation {
+    /// Add or replace an entity
+    Upsert { entity: Entity },
+    /// Remove an entity, removing a missing entity is a no-op
+    Delete { uid: EntityReference },
+}
+
+/// An ordered batch of changes tagged with the sequence number of its source
+#[derive(Debug, Serialize, Deserialize, JsonSchema, Clone)]
+pub struct DeltaBatch {
+    /// Sequence number of the batch, batches at or below the last applied one are skipped
+    pub sequence: u64,
+    pub operations: Vec<DeltaOperation>,
+}
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema, Clone, PartialEq)]
+pub struct DeltaResult {
+    /// Whether the batch was applied, `false` if it was already applied before
+    pub applied: bool,
+    /// Sequence number of the last applied batch
+    pub sequence: u64,
+    pub upserted: usize,
+    pub deleted: usize,
+}
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema, Clone, PartialEq)]
+pub struct SyncStatus {
+    /// Sequence number of the last applied batch, if any
+    pub sequence: Option<u64>,
+}
@@ -0,0 +1,37 @@
+use rocket_okapi::okapi::schemars;
+use rocket_okapi::okapi::schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, PartialEq)]
+pub struct DecisionCacheMetrics {
+    /// Maximum number of cached decisions
+    pub capacity: usize,
+    /// How long a decision is cached, unbounded if missing
+    pub ttl_seconds: Option<u64>,
+    /// Number of currently cached decisions
+    pub entries: usize,
+    pub hits: u64,
+    pub misses: u64,
+    /// Decisions dropped to make room for newer ones
+    pub evictions: u64,
+    /// Times the cache was cleared because the stores changed
+    pub invalidations: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, PartialEq)]
+pub struct ShadowMetrics {
+    /// Requests evaluated against the shadow policy set
+    pub evaluations: u64,
+    /// Requests the shadow policy set decided differently
+    pub disagreements: u64,
+    /// Number of kept disagreeing requests
+    pub samples: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, PartialEq)]
+pub struct Metrics {
+    /// Statistics of the decision cache, missing when it is disabled
+    pub decision_cache: Option<DecisionCacheMetrics>,
+    /// Statistics of the shadow policy set since it was last set, missing when there is none
+    pub shadow: Option<ShadowMetrics>,
+}
@@ -1,4 +1,12 @@
+pub mod analysis;
 pub mod authorization;
+pub mod changes;
 pub mod data;
+pub mod logging;
+pub mod metrics;
+pub mod patch;
 pub mod policies;
+pub mod replay;
 pub mod schema;
+pub mod shadow;
+pub mod test_cases;
@@ -0,0 +1,268 @@
+use rocket::data::{self, Data, FromData, Limits};
+use rocket::http::Status;
+use rocket::serde::json::serde_json::{self, Map};
+use rocket::serde::json::Value;
+use rocket::Request;
+use rocket_okapi::gen::OpenApiGenerator;
+use rocket_okapi::okapi;
+use rocket_okapi::okapi::openapi3::{MediaType, RequestBody};
+use rocket_okapi::okapi::schemars;
+use rocket_okapi::okapi::schemars::JsonSchema;
+use rocket_okapi::request::OpenApiFromData;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+pub const JSON_PATCH: &str = "application/json-patch+json";
+pub const MERGE_PATCH: &str = "application/merge-patch+json";
+
+#[derive(Debug, Error)]
+pub enum PatchError {
+    #[error("Unsupported patch content type {0}, expected {JSON_PATCH} or {MERGE_PATCH}")]
+    UnsupportedMediaType(String),
+    #[error("The patch exceeds the size limit")]
+    TooLarge,
+    #[error("Failed to read the patch: {0}")]
+    Read(std::io::Error),
+    #[error("Invalid patch: {0}")]
+    Parse(serde_json::Error),
+    #[error("Invalid JSON pointer '{0}'")]
+    InvalidPointer(String),
+    #[error("Path '{0}' does not exist")]
+    PathNotFound(String),
+    #[error("Can not move '{0}' into one of its children")]
+    MoveIntoChild(String),
+    #[error("Test failed, the value at '{0}' differs")]
+    TestFailed(String),
+}
+
+/// A single RFC 6902 JSON Patch operation
+#[derive(Debug, Serialize, Deserialize, JsonSchema, Clone, PartialEq)]
+#[serde(tag = "op", rename_all = "lowercase")]
+pub enum PatchOperation {
+    Add { path: String, value: Value },
+    Remove { path: String },
+    Replace { path: String, value: Value },
+    Move { from: String, path: String },
+    Copy { from: String, path: String },
+    Test { path: String, value: Value },
+}
+
+/// A patch of a JSON document, chosen by the content type of the request
+#[derive(Debug, Clone, PartialEq)]
+pub enum Patch {
+    /// RFC 6902 JSON Patch, sent as `application/json-patch+json`
+    Json(Vec<PatchOperation>),
+    /// RFC 7396 JSON Merge Patch, sent as `application/merge-patch+json`
+    Merge(Value),
+}
+
+impl Patch {
+    /// Apply the patch to a copy of the document, so a failing operation leaves it unchanged.
+    pub fn apply(&self, document: &Value) -> Result<Value, PatchError> {
+        let mut patched = document.clone();
+        match self {
+            Patch::Json(operations) => {
+                for operation in operations {
+                    apply_operation(&mut patched, operation)?;
+                }
+            }
+            Patch::Merge(patch) => merge(&mut patched, patch),
+        }
+        Ok(patched)
+    }
+}
+
+/// Split a JSON pointer into its unescaped reference tokens.
+fn tokens(pointer: &str) -> Result<Vec<String>, PatchError> {
+    if pointer.is_empty() {
+        return Ok(Vec::new());
+    }
+    match pointer.strip_prefix('/') {
+        Some(pointer) => Ok(pointer
+            .split('/')
+            .map(|token| token.replace("~1", "/").replace("~0", "~"))
+            .collect()),
+        None => Err(PatchError::InvalidPointer(pointer.to_owned())),
+    }
+}
+
+/// Parse an array index, which must not have leading zeros.
+fn index(token: &str, pointer: &str) -> Result<usize, PatchError> {
+    if token.len() > 1 && token.starts_with('0') {
+        return Err(PatchError::InvalidPointer(pointer.to_owned()));
+    }
+    token
+        .parse()
+        .map_err(|_| PatchError::InvalidPointer(pointer.to_owned()))
+}
+
+fn resolve<'a>(
+    document: &'a mut Value,
+    tokens: &[String],
+    pointer: &str,
+) -> Result<&'a mut Value, PatchError> {
+    let mut current = document;
+    for token in tokens {
+        current = match current {
+            Value::Object(map) => map.get_mut(token),
+            Value::Array(items) => items.get_mut(index(token, pointer)?),
+            _ => None,
+        }
+        .ok_or_else(|| PatchError::PathNotFound(pointer.to_owned()))?;
+    }
+    Ok(current)
+}
+
+fn add(document: &mut Value, pointer: &str, value: Value) -> Result<(), PatchError> {
+    let tokens = tokens(pointer)?;
+    let Some((last, parent)) = tokens.split_last() else {
+        *document = value;
+        return Ok(());
+    };
+    match resolve(document, parent, pointer)? {
+        Value::Object(map) => {
+            map.insert(last.clone(), value);
+        }
+        Value::Array(items) if last == "-" => items.push(value),
+        Value::Array(items) => {
+            let position = index(last, pointer)?;
+            if position > items.len() {
+                return Err(PatchError::PathNotFound(pointer.to_owned()));
+            }
+            items.insert(position, value);
+        }
+        _ => return Err(PatchError::PathNotFound(pointer.to_owned())),
+    }
+    Ok(())
+}
+
+fn remove(document: &mut Value, pointer: &str) -> Result<Value, PatchError> {
+    let tokens = tokens(pointer)?;
+    let Some((last, parent)) = tokens.split_last() else {
+        return Err(PatchError::InvalidPointer(pointer.to_owned()));
+    };
+    match resolve(document, parent, pointer)? {
+        Value::Object(map) => map.remove(last),
+        Value::Array(items) => {
+            let position = index(last, pointer)?;
+            (position < items.len()).then(|| items.remove(position))
+        }
+        _ => None,
+    }
+    .ok_or_else(|| PatchError::PathNotFound(pointer.to_owned()))
+}
+
+fn apply_operation(document: &mut Value, operation: &PatchOperation) -> Result<(), PatchError> {
+    match operation {
+        PatchOperation::Add { path, value } => add(document, path, value.clone()),
+        PatchOperation::Remove { path } => remove(document, path).map(|_| ()),
+        PatchOperation::Replace { path, value } => {
+            *resolve(document, &tokens(path)?, path)? = value.clone();
+            Ok(())
+        }
+        PatchOperation::Move { from, path } => {
+            if path.starts_with(&format!("{}/", from)) {
+                return Err(PatchError::MoveIntoChild(from.clone()));
+            }
+            let value = remove(document, from)?;
+            add(document, path, value)
+        }
+        PatchOperation::Copy { from, path } => {
+            let value = resolve(document, &tokens(from)?, from)?.clone();
+            add(document, path, value)
+        }
+        PatchOperation::Test { path, value } => {
+            if resolve(document, &tokens(path)?, path)? == value {
+                Ok(())
+            } else {
+                Err(PatchError::TestFailed(path.clone()))
+            }
+        }
+    }
+}
+
+/// Merge a patch into the document, where `null` members remove the member.
+fn merge(document: &mut Value, patch: &Value) {
+    let Value::Object(patch) = patch else {
+        *document = patch.clone();
+        return;
+    };
+    if !document.is_object() {
+        *document = Value::Object(Map::new());
+    }
+    if let Value::Object(map) = document {
+        for (key, value) in patch {
+            if value.is_null() {
+                map.remove(key);
+            } else {
+                merge(map.entry(key.clone()).or_insert(Value::Null), value);
+            }
+        }
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromData<'r> for Patch {
+    type Error = PatchError;
+
+    async fn from_data(req: &'r Request<'_>, data: Data<'r>) -> data::Outcome<'r, Self> {
+        let content_type = req.content_type().map(|content_type| {
+            (
+                content_type.to_string(),
+                format!("{}/{}", content_type.top(), content_type.sub()),
+            )
+        });
+        let is_merge = match &content_type {
+            Some((_, media_type)) if media_type == JSON_PATCH => false,
+            Some((_, media_type)) if media_type == MERGE_PATCH => true,
+            _ => {
+                let content_type = content_type.map(|(full, _)| full).unwrap_or_default();
+                return data::Outcome::Error((
+                    Status::UnsupportedMediaType,
+                    PatchError::UnsupportedMediaType(content_type),
+                ));
+            }
+        };
+
+        let limit = req.limits().get("json").unwrap_or(Limits::JSON);
+        let body = match data.open(limit).into_string().await {
+            Ok(body) if body.is_complete() => body.into_inner(),
+            Ok(_) => return data::Outcome::Error((Status::PayloadTooLarge, PatchError::TooLarge)),
+            Err(err) => return data::Outcome::Error((Status::BadRequest, PatchError::Read(err))),
+        };
+        let patch = if is_merge {
+            serde_json::from_str(&body).map(Patch::Merge)
+        } else {
+            serde_json::from_str(&body).map(Patch::Json)
+        };
+        match patch {
+            Ok(patch) => data::Outcome::Success(patch),
+            Err(err) => data::Outcome::Error((Status::BadRequest, PatchError::Parse(err))),
+        }
+    }
+}
+
+impl<'r> OpenApiFromData<'r> for Patch {
+    fn request_body(gen: &mut OpenApiGenerator) -> rocket_okapi::Result<RequestBody> {
+        let mut content = okapi::Map::new();
+        content.insert(
+            JSON_PATCH.to_owned(),
+            MediaType {
+                schema: Some(gen.json_schema::<Vec<PatchOperation>>()),
+                ..MediaType::default()
+            },
+        );
+        content.insert(
+            MERGE_PATCH.to_owned(),
+            MediaType {
+                schema: Some(gen.json_schema::<Value>()),
+                ..MediaType::default()
+            },
+        );
+        Ok(RequestBody {
+            content,
+            required: true,
+            ..RequestBody::default()
+        })
+    }
+}
@@ -1,12 +1,168 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use cedar_policy::{ActionConstraint, Effect, PrincipalConstraint, ResourceConstraint};
 use log::debug;
+use rocket::{FromForm, FromFormField};
 use rocket_okapi::okapi::schemars;
 use rocket_okapi::okapi::schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use std::str::FromStr;
+
+use crate::schemas::authorization::EffectRef;
+
 #[derive(Serialize, Deserialize, JsonSchema, Clone, Debug)]
 pub struct Policy {
     pub id: String,
     pub content: String,
+    /// Parsed from the content, ignored in requests
+    #[serde(default, skip_deserializing, skip_serializing_if = "Option::is_none")]
+    pub effect: Option<EffectRef>,
+    /// Parsed from the content, ignored in requests
+    #[serde(default, skip_deserializing, skip_serializing_if = "Option::is_none")]
+    pub scope: Option<PolicyScope>,
+    /// The `@annotations` of the policy, parsed from the content and ignored in requests
+    #[serde(default, skip_deserializing)]
+    pub annotations: BTreeMap<String, String>,
+    /// Whether the policy is used for authorization, ignored in requests
+    #[serde(default = "default_enabled", skip_deserializing)]
+    pub enabled: bool,
+    /// When a disabled policy is enabled again, as an RFC 3339 timestamp, ignored in requests
+    #[serde(default, skip_deserializing, skip_serializing_if = "Option::is_none")]
+    pub disabled_until: Option<String>,
+    /// What the policy is for
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    /// Who is responsible for the policy
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub owner: Option<String>,
+    /// Labels to search the policies by
+    #[serde(default, skip_serializing_if = "BTreeSet::is_empty")]
+    pub tags: BTreeSet<String>,
+    /// When the policy was created, as an RFC 3339 timestamp, ignored in requests
+    #[serde(default, skip_deserializing, skip_serializing_if = "Option::is_none")]
+    pub created_at: Option<String>,
+    /// When the content or details of the policy last changed, as an RFC 3339 timestamp,
+    /// ignored in requests
+    #[serde(default, skip_deserializing, skip_serializing_if = "Option::is_none")]
+    pub updated_at: Option<String>,
+    /// The caller who last changed the policy as it named itself with the `X-Caller` header
+    /// of authenticated requests, ignored in the body. Any holder of the API key can claim
+    /// any name, so it is advisory and not an audit trail
+    #[serde(default, skip_deserializing, skip_serializing_if = "Option::is_none")]
+    pub claimed_author: Option<String>,
+}
+
+fn default_enabled() -> bool {
+    true
+}
+
+impl Default for Policy {
+    fn default() -> Self {
+        Policy {
+            id: String::new(),
+            content: String::new(),
+            effect: None,
+            scope: None,
+            annotations: BTreeMap::new(),
+            enabled: true,
+            disabled_until: None,
+            description: None,
+            owner: None,
+            tags: BTreeSet::new(),
+            created_at: None,
+            updated_at: None,
+            claimed_author: None,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ScopeOperator {
+    Any,
+    Eq,
+    In,
+    Is,
+    IsIn,
+}
+
+/// Constraint of the policy scope on the principal, action or resource
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug, PartialEq)]
+pub struct ScopeConstraint {
+    pub operator: ScopeOperator,
+    /// Entity type of `is` constraints
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub entity_type: Option<String>,
+    /// Entities of `==` and `in` constraints, several for a list of actions
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub entities: Vec<String>,
+}
+
+impl ScopeConstraint {
+    fn new(
+        operator: ScopeOperator,
+        entity_type: Option<String>,
+        entities: Vec<cedar_policy::EntityUid>,
+    ) -> Self {
+        ScopeConstraint {
+            operator,
+            entity_type,
+            entities: entities.iter().map(|uid| uid.to_string()).collect(),
+        }
+    }
+}
+
+impl From<PrincipalConstraint> for ScopeConstraint {
+    fn from(constraint: PrincipalConstraint) -> Self {
+        match constraint {
+            PrincipalConstraint::Any => ScopeConstraint::new(ScopeOperator::Any, None, vec![]),
+            PrincipalConstraint::Eq(uid) => {
+                ScopeConstraint::new(ScopeOperator::Eq, None, vec![uid])
+            }
+            PrincipalConstraint::In(uid) => {
+                ScopeConstraint::new(ScopeOperator::In, None, vec![uid])
+            }
+            PrincipalConstraint::Is(type_name) => {
+                ScopeConstraint::new(ScopeOperator::Is, Some(type_name.to_string()), vec![])
+            }
+            PrincipalConstraint::IsIn(type_name, uid) => {
+                ScopeConstraint::new(ScopeOperator::IsIn, Some(type_name.to_string()), vec![uid])
+            }
+        }
+    }
+}
+
+impl From<ActionConstraint> for ScopeConstraint {
+    fn from(constraint: ActionConstraint) -> Self {
+        match constraint {
+            ActionConstraint::Any => ScopeConstraint::new(ScopeOperator::Any, None, vec![]),
+            ActionConstraint::Eq(uid) => ScopeConstraint::new(ScopeOperator::Eq, None, vec![uid]),
+            ActionConstraint::In(uids) => ScopeConstraint::new(ScopeOperator::In, None, uids),
+        }
+    }
+}
+
+impl From<ResourceConstraint> for ScopeConstraint {
+    fn from(constraint: ResourceConstraint) -> Self {
+        match constraint {
+            ResourceConstraint::Any => ScopeConstraint::new(ScopeOperator::Any, None, vec![]),
+            ResourceConstraint::Eq(uid) => ScopeConstraint::new(ScopeOperator::Eq, None, vec![uid]),
+            ResourceConstraint::In(uid) => ScopeConstraint::new(ScopeOperator::In, None, vec![uid]),
+            ResourceConstraint::Is(type_name) => {
+                ScopeConstraint::new(ScopeOperator::Is, Some(type_name.to_string()), vec![])
+            }
+            ResourceConstraint::IsIn(type_name, uid) => {
+                ScopeConstraint::new(ScopeOperator::IsIn, Some(type_name.to_string()), vec![uid])
+            }
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug, PartialEq)]
+pub struct PolicyScope {
+    pub principal: ScopeConstraint,
+    pub action: ScopeConstraint,
+    pub resource: ScopeConstraint,
 }
 
 impl From<cedar_policy::Policy> for Policy {
@@ -14,10 +170,46 @@ impl From<cedar_policy::Policy> for Policy {
         Policy {
             id: policy.id().to_string(),
             content: policy.to_string(),
+            effect: Some(match policy.effect() {
+                Effect::Permit => EffectRef::Permit,
+                Effect::Forbid => EffectRef::Forbid,
+            }),
+            scope: Some(PolicyScope {
+                principal: policy.principal_constraint().into(),
+                action: policy.action_constraint().into(),
+                resource: policy.resource_constraint().into(),
+            }),
+            annotations: policy
+                .annotations()
+                .map(|(key, value)| (key.to_owned(), value.to_owned()))
+                .collect(),
+            ..Policy::default()
         }
     }
 }
 
+/// Filters of a policy query. All filters must match.
+#[derive(Serialize, Deserialize, JsonSchema, FromForm, Clone, Debug, Default)]
+pub struct PolicyQuery {
+    pub effect: Option<EffectRef>,
+    /// Only return policies whose scope may apply to this principal, e.g. `User::"alice"`
+    pub principal: Option<String>,
+    /// Only return policies whose scope may apply to this action, e.g. `Action::"SELECT"`
+    pub action: Option<String>,
+    /// Only return policies whose scope may apply to this resource
+    pub resource: Option<String>,
+    /// Only return policies with these annotations, as `key` or `key:value`
+    #[serde(default)]
+    pub annotation: Vec<String>,
+    /// Only return policies with this owner
+    pub owner: Option<String>,
+    /// Only return policies with this tag. May be repeated
+    #[serde(default)]
+    pub tag: Vec<String>,
+    /// Only return policies whose ID or description contains this text, ignoring case
+    pub search: Option<String>,
+}
+
 impl TryInto<cedar_policy::Policy> for &Policy {
     type Error = cedar_policy::ParseErrors;
 
@@ -35,11 +227,41 @@ impl Policy {
         Policy {
             id,
             content: policy_update.content,
+            description: policy_update.description,
+            owner: policy_update.owner,
+            tags: policy_update.tags.unwrap_or_default(),
+            claimed_author: policy_update.claimed_author,
+            ..Policy::default()
         }
     }
 }
 
-#[derive(Serialize, Deserialize, JsonSchema)]
+/// How `PUT /policies/<id>` treats a stored policy with the same ID
+#[derive(
+    Serialize, Deserialize, JsonSchema, FromFormField, Clone, Copy, Debug, Default, PartialEq,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum PolicyWriteMode {
+    /// Replace the stored policy, or create the policy if it is missing
+    #[default]
+    Upsert,
+    /// Only create the policy, failing if one is already stored
+    Create,
+}
+
+#[derive(Clone, Serialize, Deserialize, JsonSchema, Default)]
 pub struct PolicyUpdate {
     pub content: String,
+    /// Replaces the description of the policy, kept if missing
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    /// Replaces the owner of the policy, kept if missing
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub owner: Option<String>,
+    /// Replaces the tags of the policy, kept if missing
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tags: Option<BTreeSet<String>>,
+    /// Set from the self-asserted `X-Caller` header of the request
+    #[serde(skip)]
+    pub claimed_author: Option<String>,
 }
@@ -0,0 +1,89 @@
+use std::collections::BTreeSet;
+
+use rocket_okapi::okapi::schemars;
+use rocket_okapi::okapi::schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::schemas::authorization::{AuthorizationCall, DecisionRef};
+use crate::schemas::data::Entities;
+use crate::schemas::policies::Policy;
+use crate::schemas::schema::Schema;
+
+/// Replaces parts of the stored policies, entities and schema to replay requests against.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema)]
+pub struct ReplayCandidate {
+    /// Schema used to validate the candidate policies and entities, the stored one if missing
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub schema: Option<Schema>,
+    /// Entities replacing the stored ones, kept if missing
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub entities: Option<Entities>,
+    /// Policies replacing the stored ones, kept if missing
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub policies: Option<Vec<Policy>>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct ReplayRequest {
+    /// Recorded authorization requests, replayed in order
+    pub requests: Vec<AuthorizationCall>,
+    /// Configuration to compare the stored one with
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub candidate: Option<ReplayCandidate>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema, PartialEq)]
+pub struct DecisionCounts {
+    pub allow: usize,
+    pub deny: usize,
+    /// Requests that could not be evaluated
+    pub errors: usize,
+}
+
+/// Time taken to evaluate a request, in microseconds
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema, PartialEq)]
+pub struct LatencyPercentiles {
+    pub p50: u64,
+    pub p90: u64,
+    pub p99: u64,
+    pub max: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, PartialEq)]
+pub struct ReplayRun {
+    pub decisions: DecisionCounts,
+    pub latency_us: LatencyPercentiles,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, PartialEq)]
+pub struct ReplayOutcome {
+    /// Missing if the request could not be evaluated
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub decision: Option<DecisionRef>,
+    /// IDs of the policies that determined the decision
+    pub reasons: BTreeSet<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// A request whose decision differs between the two configurations
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct ReplayDiff {
+    /// Position of the request, starting from 0
+    pub index: usize,
+    pub request: AuthorizationCall,
+    pub baseline: ReplayOutcome,
+    pub candidate: ReplayOutcome,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct ReplayReport {
+    /// Number of replayed requests
+    pub requests: usize,
+    /// Results with the stored, or baseline, configuration
+    pub baseline: ReplayRun,
+    /// Results with the candidate configuration, missing when there is none
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub candidate: Option<ReplayRun>,
+    pub diffs: Vec<ReplayDiff>,
+}
@@ -2,7 +2,9 @@ use cedar_policy;
 use log::debug;
 use serde::{Deserialize, Serialize};
 
-use rocket::serde::json::serde_json::Map;
+use std::str::FromStr;
+
+use rocket::serde::json::serde_json::{json, Map};
 use rocket::serde::json::Value;
 use rocket_okapi::okapi::schemars;
 use rocket_okapi::okapi::schemars::JsonSchema;
@@ -27,6 +29,172 @@ impl Schema {
     }
 }
 
+// Types that are not references to common types
+const BUILTIN_TYPES: [&str; 8] = [
+    "String",
+    "Long",
+    "Boolean",
+    "Set",
+    "Record",
+    "Entity",
+    "Extension",
+    "EntityOrCommon",
+];
+// Extension types, which can be referenced by name like the builtin types
+const EXTENSION_TYPES: [&str; 4] = ["ipaddr", "decimal", "datetime", "duration"];
+const CEDAR_NAMESPACE: &str = "__cedar::";
+
+/// Split `Namespace::Type` into the namespace, empty for the default one, and the type name.
+fn split_type_name(type_name: &str) -> (&str, &str) {
+    match type_name.rsplit_once("::") {
+        Some((namespace, name)) => (namespace, name),
+        None => ("", type_name),
+    }
+}
+
+fn builtin_type(name: &str) -> Value {
+    if EXTENSION_TYPES.contains(&name) {
+        json!({ "type": "Extension", "name": name })
+    } else {
+        json!({ "type": name })
+    }
+}
+
+fn qualify(namespace: &str, name: &str) -> String {
+    if namespace.is_empty() || name.contains("::") {
+        name.to_owned()
+    } else {
+        format!("{}::{}", namespace, name)
+    }
+}
+
+impl Schema {
+    /// Get the declared type of an attribute of the given entity type, with common types resolved.
+    pub fn attribute_type(&self, entity_type: &str, attribute: &str) -> Option<Value> {
+        let (namespace, name) = split_type_name(entity_type);
+        let attr_type = self
+            .0
+            .get(namespace)?
+            .get("entityTypes")?
+            .get(name)?
+            .get("shape")?
+            .get("attributes")?
+            .get(attribute)?;
+        Some(self.resolve_type(namespace, attr_type))
+    }
+
+    fn resolve_type(&self, namespace: &str, attr_type: &Value) -> Value {
+        let type_name = match attr_type.get("type").and_then(|t| t.as_str()) {
+            Some(type_name) => type_name,
+            None => return attr_type.clone(),
+        };
+        let reference = if type_name == "EntityOrCommon" {
+            match attr_type.get("name").and_then(|n| n.as_str()) {
+                Some(name) => name,
+                None => return attr_type.clone(),
+            }
+        } else if BUILTIN_TYPES.contains(&type_name) {
+            return attr_type.clone();
+        } else {
+            type_name
+        };
+        if let Some(builtin) = reference.strip_prefix(CEDAR_NAMESPACE) {
+            return builtin_type(builtin);
+        }
+
+        let (reference_namespace, reference_name) = match reference.rsplit_once("::") {
+            Some((ns, name)) => (ns, name),
+            None => (namespace, reference),
+        };
+        match self
+            .0
+            .get(reference_namespace)
+            .and_then(|ns| ns.get("commonTypes"))
+            .and_then(|types| types.get(reference_name))
+        {
+            // Common types can not reference themselves, so this terminates
+            Some(common_type) => self.resolve_type(reference_namespace, common_type),
+            None if BUILTIN_TYPES.contains(&reference) || EXTENSION_TYPES.contains(&reference) => {
+                builtin_type(reference)
+            }
+            None => json!({ "type": "Entity", "name": qualify(namespace, reference) }),
+        }
+    }
+
+    /// Coerce an attribute value of the given entity type to the type declared in the schema.
+    ///
+    /// Values sent as strings are converted to the declared primitive type, and extension and
+    /// entity values are wrapped in their `__extn` and `__entity` escapes. Values that can't be
+    /// coerced are returned unchanged, to be rejected when validating the entity.
+    pub fn coerce_attribute(&self, entity_type: &str, attribute: &str, value: Value) -> Value {
+        match self.attribute_type(entity_type, attribute) {
+            Some(attr_type) => self.coerce(split_type_name(entity_type).0, &attr_type, value),
+            None => value,
+        }
+    }
+
+    fn coerce(&self, namespace: &str, attr_type: &Value, value: Value) -> Value {
+        let attr_type = self.resolve_type(namespace, attr_type);
+        let type_name = attr_type.get("type").and_then(|t| t.as_str()).unwrap_or("");
+        let name = attr_type.get("name").and_then(|n| n.as_str()).unwrap_or("");
+        match (type_name, value) {
+            ("Long", Value::String(s)) => match s.trim().parse::<i64>() {
+                Ok(long) => Value::from(long),
+                Err(_) => Value::String(s),
+            },
+            ("Boolean", Value::String(s)) => match s.trim() {
+                "true" => Value::Bool(true),
+                "false" => Value::Bool(false),
+                _ => Value::String(s),
+            },
+            ("String", value @ (Value::Number(_) | Value::Bool(_))) => {
+                Value::String(value.to_string())
+            }
+            ("Extension", Value::String(s)) => {
+                let function = match name {
+                    "ipaddr" => "ip",
+                    other => other,
+                };
+                json!({ "__extn": { "fn": function, "arg": s } })
+            }
+            ("Entity", Value::String(s)) => {
+                let uid = match cedar_policy::EntityUid::from_str(&s) {
+                    Ok(uid) => {
+                        json!({ "type": uid.type_name().to_string(), "id": uid.id().unescaped() })
+                    }
+                    Err(_) => json!({ "type": qualify(namespace, name), "id": s }),
+                };
+                json!({ "__entity": uid })
+            }
+            ("Set", Value::Array(items)) => match attr_type.get("element") {
+                Some(element) => Value::Array(
+                    items
+                        .into_iter()
+                        .map(|item| self.coerce(namespace, element, item))
+                        .collect(),
+                ),
+                None => Value::Array(items),
+            },
+            ("Record", Value::Object(record)) => {
+                let attributes = attr_type.get("attributes");
+                Value::Object(
+                    record
+                        .into_iter()
+                        .map(|(key, item)| {
+                            let item = match attributes.and_then(|a| a.get(&key)) {
+                                Some(item_type) => self.coerce(namespace, item_type, item),
+                                None => item,
+                            };
+                            (key, item)
+                        })
+                        .collect(),
+                )
+            }
+            (_, value) => value,
+        }
+    }
+}
+
 impl TryInto<cedar_policy::Schema> for Schema {
     type Error = cedar_policy::SchemaError;
 
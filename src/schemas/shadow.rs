@@ -0,0 +1,18 @@
+use std::collections::BTreeSet;
+
+use rocket_okapi::okapi::schemars;
+use rocket_okapi::okapi::schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::schemas::authorization::{AuthorizationCall, DecisionRef};
+
+/// A request the shadow policy set decided differently than the active one
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct ShadowDisagreement {
+    pub request: AuthorizationCall,
+    /// The decision returned to the caller
+    pub active_decision: DecisionRef,
+    pub active_reasons: BTreeSet<String>,
+    pub shadow_decision: DecisionRef,
+    pub shadow_reasons: BTreeSet<String>,
+}
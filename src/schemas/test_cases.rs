@@ -0,0 +1,43 @@
+use std::collections::BTreeSet;
+
+use rocket_okapi::okapi::schemars;
+use rocket_okapi::okapi::schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::schemas::authorization::{AuthorizationCall, DecisionRef};
+
+/// An authorization request along with the decision it is expected to get
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct TestCase {
+    /// Set from the path when the test case is stored
+    #[serde(default)]
+    pub id: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    pub request: AuthorizationCall,
+    pub expected_decision: DecisionRef,
+    /// IDs of the policies expected to determine the decision, not checked if missing
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expected_reasons: Option<BTreeSet<String>>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, PartialEq)]
+pub struct TestCaseResult {
+    pub id: String,
+    pub passed: bool,
+    /// The decision of the authorizer, missing if the request could not be evaluated
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub decision: Option<DecisionRef>,
+    /// IDs of the policies that determined the decision
+    pub reasons: BTreeSet<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, PartialEq)]
+pub struct TestRunReport {
+    pub total: usize,
+    pub passed: usize,
+    pub failed: usize,
+    pub results: Vec<TestCaseResult>,
+}
@@ -0,0 +1,201 @@
+use std::collections::{BTreeMap, HashMap};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use log::{debug, warn};
+use rocket::serde::json::serde_json;
+
+use crate::schemas::authorization::{AuthorizationAnswer, AuthorizationCall};
+use crate::schemas::metrics::DecisionCacheMetrics;
+use crate::services::changes::ChangeFeed;
+
+/// Identifies a cached decision: the authorization request at a revision of the stores.
+pub struct CacheKey {
+    revision: u64,
+    request: String,
+}
+
+struct Entry {
+    answer: AuthorizationAnswer,
+    inserted: Instant,
+    used: u64,
+}
+
+/// Least recently used decisions, all computed at the same revision of the stores.
+#[derive(Default)]
+struct Lru {
+    revision: u64,
+    tick: u64,
+    entries: HashMap<String, Entry>,
+    /// Keys of the entries by their last use, the least recently used first
+    recency: BTreeMap<u64, String>,
+}
+
+impl Lru {
+    fn touch(&mut self, request: &str) {
+        self.tick += 1;
+        if let Some(entry) = self.entries.get_mut(request) {
+            self.recency.remove(&entry.used);
+            entry.used = self.tick;
+            self.recency.insert(self.tick, request.to_owned());
+        }
+    }
+
+    fn remove(&mut self, request: &str) {
+        if let Some(entry) = self.entries.remove(request) {
+            self.recency.remove(&entry.used);
+        }
+    }
+
+    fn clear(&mut self, revision: u64) {
+        self.revision = revision;
+        self.entries.clear();
+        self.recency.clear();
+    }
+}
+
+/// Bounded cache of authorization decisions in front of the authorizer.
+///
+/// Decisions are cached for the revision of the stores they were computed at, so any
+/// change to the policies, entities or schema invalidates the whole cache.
+pub struct DecisionCache {
+    capacity: usize,
+    ttl: Option<Duration>,
+    changes: Arc<ChangeFeed>,
+    lru: Mutex<Lru>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+    evictions: AtomicU64,
+    invalidations: AtomicU64,
+}
+
+impl DecisionCache {
+    /// Create a cache of up to `capacity` decisions, which is disabled when the capacity is 0.
+    pub fn new(capacity: usize, ttl: Option<Duration>, changes: Arc<ChangeFeed>) -> Self {
+        Self {
+            capacity,
+            ttl,
+            changes,
+            lru: Mutex::new(Lru::default()),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+            evictions: AtomicU64::new(0),
+            invalidations: AtomicU64::new(0),
+        }
+    }
+
+    pub fn disabled(changes: Arc<ChangeFeed>) -> Self {
+        Self::new(0, None, changes)
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.capacity > 0
+    }
+
+    /// Key of the request at the current revision, or `None` when the cache is disabled.
+    ///
+    /// The key must be taken before reading the stores, so a decision computed while the
+    /// stores change is cached under a revision that is already outdated.
+    pub fn key(&self, authorization_call: &AuthorizationCall) -> Option<CacheKey> {
+        if !self.is_enabled() {
+            return None;
+        }
+        let revision = self.changes.revision();
+        match serde_json::to_string(authorization_call) {
+            Ok(request) => Some(CacheKey { revision, request }),
+            Err(err) => {
+                warn!("Failed to build the decision cache key: {}", err);
+                None
+            }
+        }
+    }
+
+    /// Look up the decision for the key, counting the hit or miss.
+    pub fn get(&self, key: &CacheKey) -> Option<AuthorizationAnswer> {
+        let answer = self.lookup(key);
+        match answer {
+            Some(_) => self.hits.fetch_add(1, Ordering::Relaxed),
+            None => self.misses.fetch_add(1, Ordering::Relaxed),
+        };
+        answer
+    }
+
+    fn lookup(&self, key: &CacheKey) -> Option<AuthorizationAnswer> {
+        let mut lru = self.lru.lock().unwrap();
+        if key.revision > lru.revision {
+            self.invalidate(&mut lru, key.revision);
+            return None;
+        }
+        if key.revision < lru.revision {
+            return None;
+        }
+        let inserted = lru.entries.get(&key.request)?.inserted;
+        if self.ttl.is_some_and(|ttl| inserted.elapsed() >= ttl) {
+            lru.remove(&key.request);
+            return None;
+        }
+        lru.touch(&key.request);
+        lru.entries
+            .get(&key.request)
+            .map(|entry| entry.answer.clone())
+    }
+
+    /// Cache a decision, evicting the least recently used one when the cache is full.
+    pub fn insert(&self, key: CacheKey, answer: AuthorizationAnswer) {
+        if key.revision < self.changes.revision() {
+            // The stores changed while the decision was computed
+            return;
+        }
+        let mut lru = self.lru.lock().unwrap();
+        if key.revision > lru.revision {
+            self.invalidate(&mut lru, key.revision);
+        }
+        if key.revision < lru.revision {
+            return;
+        }
+        lru.remove(&key.request);
+        while lru.entries.len() >= self.capacity {
+            let Some((_, evicted)) = lru.recency.pop_first() else {
+                break;
+            };
+            lru.entries.remove(&evicted);
+            self.evictions.fetch_add(1, Ordering::Relaxed);
+        }
+        lru.tick += 1;
+        let used = lru.tick;
+        lru.recency.insert(used, key.request.clone());
+        lru.entries.insert(
+            key.request,
+            Entry {
+                answer,
+                inserted: Instant::now(),
+                used,
+            },
+        );
+    }
+
+    fn invalidate(&self, lru: &mut Lru, revision: u64) {
+        if !lru.entries.is_empty() {
+            debug!(
+                "Invalidating {} cached decisions of revision {}",
+                lru.entries.len(),
+                lru.revision
+            );
+            self.invalidations.fetch_add(1, Ordering::Relaxed);
+        }
+        lru.clear(revision);
+    }
+
+    pub fn metrics(&self) -> DecisionCacheMetrics {
+        DecisionCacheMetrics {
+            capacity: self.capacity,
+            ttl_seconds: self.ttl.map(|ttl| ttl.as_secs()),
+            entries: self.lru.lock().unwrap().entries.len(),
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            evictions: self.evictions.load(Ordering::Relaxed),
+            invalidations: self.invalidations.load(Ordering::Relaxed),
+        }
+    }
+}
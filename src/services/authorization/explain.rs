@@ -0,0 +1,204 @@
+use cedar_policy::{
+    ActionConstraint, Authorizer, Effect, Entities, EntityUid, Policy, PolicySet,
+    PrincipalConstraint, Request, ResourceConstraint,
+};
+use log::debug;
+use rocket::serde::json::serde_json::{Map, Value};
+
+use crate::schemas::authorization::{
+    AuthorizationAnswer, AuthorizationExplanation, EffectRef, EntityExplanation, PolicyEvaluation,
+    PolicyOutcome,
+};
+
+/// Check `uid in target`, using the ancestors of the entities.
+pub(crate) fn is_in(entities: &Entities, uid: &EntityUid, target: &EntityUid) -> bool {
+    uid == target || entities.is_ancestor_of(target, uid)
+}
+
+pub(crate) fn check_principal(
+    constraint: PrincipalConstraint,
+    uid: &EntityUid,
+    entities: &Entities,
+) -> Result<(), String> {
+    let matched = match &constraint {
+        PrincipalConstraint::Any => true,
+        PrincipalConstraint::Eq(target) => uid == target,
+        PrincipalConstraint::In(target) => is_in(entities, uid, target),
+        PrincipalConstraint::Is(type_name) => uid.type_name() == type_name,
+        PrincipalConstraint::IsIn(type_name, target) => {
+            uid.type_name() == type_name && is_in(entities, uid, target)
+        }
+    };
+    if matched {
+        Ok(())
+    } else {
+        Err(format!(
+            "principal {} does not match {}",
+            uid,
+            describe_scope(&constraint)
+        ))
+    }
+}
+
+pub(crate) fn check_action(
+    constraint: ActionConstraint,
+    uid: &EntityUid,
+    entities: &Entities,
+) -> Result<(), String> {
+    let (matched, description) = match &constraint {
+        ActionConstraint::Any => (true, String::new()),
+        ActionConstraint::Eq(target) => (uid == target, format!("== {}", target)),
+        ActionConstraint::In(targets) => (
+            targets.iter().any(|target| is_in(entities, uid, target)),
+            format!(
+                "in [{}]",
+                targets
+                    .iter()
+                    .map(|t| t.to_string())
+                    .collect::<Vec<String>>()
+                    .join(", ")
+            ),
+        ),
+    };
+    if matched {
+        Ok(())
+    } else {
+        Err(format!("action {} does not match {}", uid, description))
+    }
+}
+
+pub(crate) fn check_resource(
+    constraint: ResourceConstraint,
+    uid: &EntityUid,
+    entities: &Entities,
+) -> Result<(), String> {
+    // Resource constraints have the same shape as principal constraints
+    let constraint = match constraint {
+        ResourceConstraint::Any => PrincipalConstraint::Any,
+        ResourceConstraint::Eq(target) => PrincipalConstraint::Eq(target),
+        ResourceConstraint::In(target) => PrincipalConstraint::In(target),
+        ResourceConstraint::Is(type_name) => PrincipalConstraint::Is(type_name),
+        ResourceConstraint::IsIn(type_name, target) => PrincipalConstraint::IsIn(type_name, target),
+    };
+    check_principal(constraint, uid, entities)
+        .map_err(|reason| reason.replacen("principal", "resource", 1))
+}
+
+fn describe_scope(constraint: &PrincipalConstraint) -> String {
+    match constraint {
+        PrincipalConstraint::Any => String::new(),
+        PrincipalConstraint::Eq(target) => format!("== {}", target),
+        PrincipalConstraint::In(target) => format!("in {}", target),
+        PrincipalConstraint::Is(type_name) => format!("is {}", type_name),
+        PrincipalConstraint::IsIn(type_name, target) => format!("is {} in {}", type_name, target),
+    }
+}
+
+/// Check the scope of the policy against the request, returning the first mismatch.
+fn check_scope(policy: &Policy, request: &Request, entities: &Entities) -> Result<(), String> {
+    if let Some(principal) = request.principal() {
+        check_principal(policy.principal_constraint(), principal, entities)?;
+    }
+    if let Some(action) = request.action() {
+        check_action(policy.action_constraint(), action, entities)?;
+    }
+    if let Some(resource) = request.resource() {
+        check_resource(policy.resource_constraint(), resource, entities)?;
+    }
+    Ok(())
+}
+
+fn evaluate_policy(
+    authorizer: &Authorizer,
+    policy: &Policy,
+    request: &Request,
+    entities: &Entities,
+) -> PolicyEvaluation {
+    let effect = match policy.effect() {
+        Effect::Permit => EffectRef::Permit,
+        Effect::Forbid => EffectRef::Forbid,
+    };
+    let (outcome, reason) = match check_scope(policy, request, entities) {
+        Err(reason) => (PolicyOutcome::ScopeMismatch, Some(reason)),
+        Ok(()) => {
+            // Evaluate the policy on its own to learn whether its conditions hold
+            let mut policy_set = PolicySet::new();
+            match policy_set.add(policy.clone()) {
+                Err(err) => (PolicyOutcome::Error, Some(err.to_string())),
+                Ok(()) => {
+                    let response = authorizer.is_authorized(request, &policy_set, entities);
+                    let error = response
+                        .diagnostics()
+                        .errors()
+                        .next()
+                        .map(|e| e.to_string());
+                    if error.is_some() {
+                        (PolicyOutcome::Error, error)
+                    } else if response.diagnostics().reason().any(|id| id == policy.id()) {
+                        (PolicyOutcome::Satisfied, None)
+                    } else {
+                        (
+                            PolicyOutcome::ConditionFalse,
+                            Some("the when/unless conditions are not satisfied".to_string()),
+                        )
+                    }
+                }
+            }
+        }
+    };
+    PolicyEvaluation {
+        id: policy.id().to_string(),
+        effect,
+        outcome,
+        reason,
+    }
+}
+
+fn explain_entity(uid: &EntityUid, entities: &Entities) -> EntityExplanation {
+    let entity = entities.get(uid);
+    let attrs = entity
+        .and_then(|e| e.to_json_value().ok())
+        .and_then(|json| json.get("attrs").cloned())
+        .unwrap_or(Value::Object(Map::new()));
+    let mut ancestors: Vec<String> = entities
+        .ancestors(uid)
+        .map(|ancestors| ancestors.map(|a| a.to_string()).collect())
+        .unwrap_or_default();
+    ancestors.sort();
+    EntityExplanation {
+        uid: uid.to_string(),
+        found: entity.is_some(),
+        attrs,
+        ancestors,
+    }
+}
+
+/// Authorize the request and report how every policy evaluated against it,
+/// together with the stored attributes and ancestors of the request entities.
+pub fn explain(
+    authorizer: &Authorizer,
+    request: &Request,
+    policies: &PolicySet,
+    entities: &Entities,
+) -> AuthorizationExplanation {
+    let answer = authorizer.is_authorized(request, policies, entities);
+    debug!("Explaining authorization answer: {:?}", answer);
+
+    let mut evaluations: Vec<PolicyEvaluation> = policies
+        .policies()
+        .map(|policy| evaluate_policy(authorizer, policy, request, entities))
+        .collect();
+    evaluations.sort_by(|a, b| a.id.cmp(&b.id));
+
+    let explained_entities = [request.principal(), request.action(), request.resource()]
+        .into_iter()
+        .flatten()
+        .map(|uid| explain_entity(uid, entities))
+        .collect();
+
+    AuthorizationExplanation {
+        answer: AuthorizationAnswer::from(answer),
+        policies: evaluations,
+        entities: explained_entities,
+    }
+}
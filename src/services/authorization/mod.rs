@@ -0,0 +1,4 @@
+pub mod cache;
+pub mod explain;
+pub mod replay;
+pub mod shadow;
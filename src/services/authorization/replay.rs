@@ -0,0 +1,237 @@
+use std::collections::BTreeSet;
+use std::error::Error;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use cedar_policy::{Authorizer, PolicySet, Response, Schema};
+
+use crate::schemas::authorization::{AuthorizationCall, AuthorizationRequest, DecisionRef};
+use crate::schemas::data::{Entities, Entity, IntegrityMode};
+use crate::schemas::policies::Policy;
+use crate::schemas::replay::{
+    DecisionCounts, LatencyPercentiles, ReplayCandidate, ReplayDiff, ReplayOutcome, ReplayReport,
+    ReplayRun,
+};
+use crate::services::data::memory::MemoryDataStore;
+use crate::services::data::{DataStore, EntitySnapshot};
+use crate::services::errors::StoreError;
+use crate::services::policies::memory::MemoryPolicyStore;
+use crate::services::policies::PolicyStore;
+use crate::services::schema::memory::MemorySchemaStore;
+use crate::services::schema::SchemaStore;
+
+/// Policies and entities that requests are replayed against.
+pub struct ReplayTarget {
+    pub policies: Arc<PolicySet>,
+    pub entities: EntitySnapshot,
+}
+
+impl ReplayTarget {
+    /// Replace the parts of this target given by the candidate, which are validated with
+    /// the same rules as the stores, against the candidate schema or else the given one.
+    /// The parts kept from this target are validated against a candidate schema too.
+    pub async fn with_candidate(
+        &self,
+        candidate: ReplayCandidate,
+        schema: Option<Schema>,
+        integrity: Option<IntegrityMode>,
+    ) -> Result<ReplayTarget, String> {
+        let schema_changed = candidate.schema.is_some();
+        let schema = match candidate.schema {
+            Some(candidate_schema) => {
+                let schema_store = MemorySchemaStore::new();
+                schema_store
+                    .update_schema(candidate_schema)
+                    .await
+                    .map_err(|err| format!("Invalid candidate schema: {}", err))?;
+                schema_store.get_cedar_schema().await
+            }
+            None => schema,
+        };
+        let entities = match (candidate.entities, schema_changed) {
+            (Some(candidate_entities), _) => load_entities(candidate_entities, &schema, integrity)
+                .await
+                .map_err(|err| format!("Invalid candidate entities: {}", err))?,
+            (None, true) => load_entities(self.schema_entities(), &schema, integrity)
+                .await
+                .map_err(|err| {
+                    format!(
+                        "The entities do not conform to the candidate schema: {}",
+                        err
+                    )
+                })?,
+            (None, false) => self.entities.clone(),
+        };
+        let policies = match (candidate.policies, schema_changed) {
+            (Some(candidate_policies), _) => load_policies(candidate_policies, schema)
+                .await
+                .map_err(|err| format!("Invalid candidate policies: {}", err))?,
+            (None, true) => load_policies(self.schema_policies(), schema)
+                .await
+                .map_err(|err| {
+                    format!(
+                        "The policies do not conform to the candidate schema: {}",
+                        err
+                    )
+                })?,
+            (None, false) => self.policies.clone(),
+        };
+        Ok(ReplayTarget { policies, entities })
+    }
+
+    /// The entities of this target, leaving out the actions, which are declared by the schema.
+    fn schema_entities(&self) -> Entities {
+        self.entities
+            .as_ref()
+            .iter()
+            .filter(|entity| !entity.uid().entity_type().is_action())
+            .map(|entity| Entity::from(entity.clone()))
+            .collect()
+    }
+
+    /// The policies of this target, in the format of the policy store.
+    fn schema_policies(&self) -> Vec<Policy> {
+        self.policies
+            .policies()
+            .cloned()
+            .map(Policy::from)
+            .collect()
+    }
+}
+
+/// Load entities into a data store of their own, as a write to the stored ones would.
+async fn load_entities(
+    entities: Entities,
+    schema: &Option<Schema>,
+    integrity: Option<IntegrityMode>,
+) -> Result<EntitySnapshot, StoreError> {
+    let data_store = match integrity {
+        Some(integrity) => MemoryDataStore::with_integrity(integrity),
+        None => MemoryDataStore::new(),
+    };
+    data_store.update_entities(entities, schema.clone()).await?;
+    Ok(data_store.entities().await)
+}
+
+/// Load policies into a policy store of their own, as a write to the stored ones would.
+async fn load_policies(
+    policies: Vec<Policy>,
+    schema: Option<Schema>,
+) -> Result<Arc<PolicySet>, StoreError> {
+    let policy_store = MemoryPolicyStore::new();
+    policy_store.update_policies(policies, schema).await?;
+    Ok(policy_store.policy_set().await)
+}
+
+fn decide(
+    call: &AuthorizationCall,
+    authorizer: &Authorizer,
+    target: &ReplayTarget,
+) -> Result<Response, Box<dyn Error>> {
+    let request: AuthorizationRequest = call.clone().try_into()?;
+    let (request, entities) = request.get_request_entities(target.entities.clone())?;
+    Ok(authorizer.is_authorized(&request, &target.policies, &entities))
+}
+
+fn evaluate(
+    call: &AuthorizationCall,
+    authorizer: &Authorizer,
+    target: &ReplayTarget,
+) -> (ReplayOutcome, Duration) {
+    let started = Instant::now();
+    let decided = decide(call, authorizer, target);
+    let elapsed = started.elapsed();
+    let outcome = match decided {
+        Ok(response) => ReplayOutcome {
+            decision: Some(DecisionRef::from(response.decision())),
+            reasons: response
+                .diagnostics()
+                .reason()
+                .map(|id| id.to_string())
+                .collect(),
+            error: None,
+        },
+        Err(err) => ReplayOutcome {
+            decision: None,
+            reasons: BTreeSet::new(),
+            error: Some(err.to_string()),
+        },
+    };
+    (outcome, elapsed)
+}
+
+/// Nearest-rank percentile of sorted latencies, in microseconds.
+fn percentile(sorted: &[Duration], percent: usize) -> u64 {
+    if sorted.is_empty() {
+        return 0;
+    }
+    let rank = (sorted.len() * percent).div_ceil(100).max(1);
+    sorted[rank - 1].as_micros() as u64
+}
+
+fn summarize(outcomes: &[(ReplayOutcome, Duration)]) -> ReplayRun {
+    let mut decisions = DecisionCounts::default();
+    for (outcome, _) in outcomes {
+        match outcome.decision {
+            Some(DecisionRef::Allow) => decisions.allow += 1,
+            Some(DecisionRef::Deny) => decisions.deny += 1,
+            None => decisions.errors += 1,
+        }
+    }
+    let mut latencies: Vec<Duration> = outcomes.iter().map(|(_, elapsed)| *elapsed).collect();
+    latencies.sort();
+    ReplayRun {
+        decisions,
+        latency_us: LatencyPercentiles {
+            p50: percentile(&latencies, 50),
+            p90: percentile(&latencies, 90),
+            p99: percentile(&latencies, 99),
+            max: percentile(&latencies, 100),
+        },
+    }
+}
+
+/// Replay the requests against the baseline and, if given, the candidate, reporting the
+/// requests whose decision differs between them.
+pub fn replay(
+    authorizer: &Authorizer,
+    requests: &[AuthorizationCall],
+    baseline: &ReplayTarget,
+    candidate: Option<&ReplayTarget>,
+) -> ReplayReport {
+    let baseline_outcomes: Vec<(ReplayOutcome, Duration)> = requests
+        .iter()
+        .map(|call| evaluate(call, authorizer, baseline))
+        .collect();
+    let Some(candidate) = candidate else {
+        return ReplayReport {
+            requests: requests.len(),
+            baseline: summarize(&baseline_outcomes),
+            candidate: None,
+            diffs: Vec::new(),
+        };
+    };
+    let candidate_outcomes: Vec<(ReplayOutcome, Duration)> = requests
+        .iter()
+        .map(|call| evaluate(call, authorizer, candidate))
+        .collect();
+    let mut diffs = Vec::new();
+    for (index, request) in requests.iter().enumerate() {
+        let (baseline, _) = &baseline_outcomes[index];
+        let (candidate, _) = &candidate_outcomes[index];
+        if baseline.decision != candidate.decision {
+            diffs.push(ReplayDiff {
+                index,
+                request: request.clone(),
+                baseline: baseline.clone(),
+                candidate: candidate.clone(),
+            });
+        }
+    }
+    ReplayReport {
+        requests: requests.len(),
+        baseline: summarize(&baseline_outcomes),
+        candidate: Some(summarize(&candidate_outcomes)),
+        diffs,
+    }
+}
@@ -0,0 +1,95 @@
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+
+use cedar_policy::Response;
+use log::warn;
+
+use crate::schemas::authorization::{AuthorizationAnswer, AuthorizationCall, DecisionRef};
+use crate::schemas::metrics::ShadowMetrics;
+use crate::schemas::shadow::ShadowDisagreement;
+
+/// Number of most recent disagreements kept for review
+const SAMPLE_CAPACITY: usize = 100;
+
+/// Compares the decisions of the shadow policy set with the active ones, keeping the most
+/// recent requests they disagree on.
+pub struct ShadowMonitor {
+    evaluations: AtomicU64,
+    disagreements: AtomicU64,
+    samples: Mutex<VecDeque<ShadowDisagreement>>,
+}
+
+impl ShadowMonitor {
+    pub fn new() -> Self {
+        Self {
+            evaluations: AtomicU64::new(0),
+            disagreements: AtomicU64::new(0),
+            samples: Mutex::new(VecDeque::with_capacity(SAMPLE_CAPACITY)),
+        }
+    }
+
+    /// Record the shadow decision of a request along with the active answer.
+    pub fn record(
+        &self,
+        request: AuthorizationCall,
+        active: &AuthorizationAnswer,
+        shadow: Response,
+    ) {
+        self.evaluations.fetch_add(1, Ordering::Relaxed);
+        let shadow_decision = DecisionRef::from(shadow.decision());
+        if shadow_decision == active.decision() {
+            return;
+        }
+        self.disagreements.fetch_add(1, Ordering::Relaxed);
+        warn!(
+            "Shadow policies decided {:?} instead of {:?} for {:?}",
+            shadow_decision,
+            active.decision(),
+            request
+        );
+        let disagreement = ShadowDisagreement {
+            request,
+            active_decision: active.decision(),
+            active_reasons: active.reasons().iter().cloned().collect(),
+            shadow_decision,
+            shadow_reasons: shadow
+                .diagnostics()
+                .reason()
+                .map(|id| id.to_string())
+                .collect(),
+        };
+        let mut samples = self.samples.lock().unwrap();
+        if samples.len() == SAMPLE_CAPACITY {
+            samples.pop_front();
+        }
+        samples.push_back(disagreement);
+    }
+
+    /// The kept disagreements, oldest first.
+    pub fn samples(&self) -> Vec<ShadowDisagreement> {
+        self.samples.lock().unwrap().iter().cloned().collect()
+    }
+
+    /// Forget the statistics and samples, when the shadow policy set changes.
+    pub fn reset(&self) {
+        let mut samples = self.samples.lock().unwrap();
+        samples.clear();
+        self.evaluations.store(0, Ordering::Relaxed);
+        self.disagreements.store(0, Ordering::Relaxed);
+    }
+
+    pub fn metrics(&self) -> ShadowMetrics {
+        ShadowMetrics {
+            evaluations: self.evaluations.load(Ordering::Relaxed),
+            disagreements: self.disagreements.load(Ordering::Relaxed),
+            samples: self.samples.lock().unwrap().len(),
+        }
+    }
+}
+
+impl Default for ShadowMonitor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
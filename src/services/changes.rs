@@ -0,0 +1,110 @@
+use std::collections::VecDeque;
+use std::sync::Mutex;
+
+use log::debug;
+use rocket::tokio::sync::broadcast;
+use thiserror::Error;
+
+use crate::schemas::changes::{ChangeEvent, ObjectType, Operation};
+
+/// Number of past changes kept to resume watches from
+const HISTORY_CAPACITY: usize = 1024;
+
+#[derive(Debug, Error)]
+pub enum ChangeFeedError {
+    #[error("Revision {0} is no longer available, reload the stores and watch from revision {1}")]
+    Expired(u64, u64),
+    #[error("Revision {0} is ahead of the current revision {1}, reload the stores as the agent may have restarted")]
+    Unknown(u64, u64),
+}
+
+struct History {
+    revision: u64,
+    events: VecDeque<ChangeEvent>,
+}
+
+/// Ordered feed of the changes of the policy, data and schema stores.
+///
+/// Every change gets the next revision, the most recent changes are kept so watchers
+/// can resume from the last revision they have seen.
+pub struct ChangeFeed {
+    history: Mutex<History>,
+    sender: broadcast::Sender<ChangeEvent>,
+}
+
+impl ChangeFeed {
+    pub fn new() -> Self {
+        let (sender, _) = broadcast::channel(HISTORY_CAPACITY);
+        Self {
+            history: Mutex::new(History {
+                revision: 0,
+                events: VecDeque::with_capacity(HISTORY_CAPACITY),
+            }),
+            sender,
+        }
+    }
+
+    /// Revision of the last change.
+    pub fn revision(&self) -> u64 {
+        self.history.lock().unwrap().revision
+    }
+
+    /// Record changes, which are published in the given order.
+    pub fn publish(&self, changes: impl IntoIterator<Item = (ObjectType, String, Operation)>) {
+        let mut history = self.history.lock().unwrap();
+        for (object, id, operation) in changes {
+            history.revision += 1;
+            let event = ChangeEvent {
+                revision: history.revision,
+                object,
+                id,
+                operation,
+            };
+            debug!("Publishing change {:?}", event);
+            if history.events.len() == HISTORY_CAPACITY {
+                history.events.pop_front();
+            }
+            history.events.push_back(event.clone());
+            // Sending only fails if nobody is watching
+            let _ = self.sender.send(event);
+        }
+    }
+
+    /// Watch the changes after a revision, returning the past changes to replay and a
+    /// receiver of the following ones. Without a revision only new changes are watched.
+    pub fn subscribe(
+        &self,
+        since: Option<u64>,
+    ) -> Result<(Vec<ChangeEvent>, broadcast::Receiver<ChangeEvent>), ChangeFeedError> {
+        // Subscribing under the lock ensures no change is missed or replayed twice
+        let history = self.history.lock().unwrap();
+        let receiver = self.sender.subscribe();
+        let since = match since {
+            Some(since) if since > history.revision => {
+                return Err(ChangeFeedError::Unknown(since, history.revision))
+            }
+            Some(since) => since,
+            None => return Ok((Vec::new(), receiver)),
+        };
+        let oldest = history
+            .events
+            .front()
+            .map_or(history.revision + 1, |event| event.revision);
+        if since + 1 < oldest {
+            return Err(ChangeFeedError::Expired(since, history.revision));
+        }
+        let missed = history
+            .events
+            .iter()
+            .filter(|event| event.revision > since)
+            .cloned()
+            .collect();
+        Ok((missed, receiver))
+    }
+}
+
+impl Default for ChangeFeed {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -1,106 +1,1082 @@
-use std::borrow::Borrow;
-use std::error::Error;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::sync::Arc;
 
-use async_lock::{RwLock, RwLockReadGuard, RwLockWriteGuard};
+use arc_swap::ArcSwap;
+use async_lock::{MutexGuard, RwLock, RwLockReadGuard, RwLockWriteGuard};
 use async_trait::async_trait;
-use cedar_policy::Schema;
-use cedar_policy_core::entities;
-use log::{debug, error, info};
+use cedar_policy::{EntityUid, Schema};
+use cedar_policy_core::ast::{Entity, EntityUID};
+use cedar_policy_core::entities::{self, NoEntitiesSchema, TCComputation};
+use cedar_policy_core::extensions::Extensions;
+use log::{debug, error, info, warn};
+use rocket::serde::json::serde_json;
+use rocket::serde::json::Value;
+use thiserror::Error;
 
+use crate::schemas::changes::{ObjectType, Operation};
 use crate::schemas::data as schemas;
-use crate::services::data::DataStore;
+use crate::schemas::patch::Patch;
+use crate::services::changes::ChangeFeed;
+use crate::services::data::{DataStore, EntityEdit, EntitySnapshot, Relation};
+use crate::services::errors::StoreError;
+use crate::services::test_cases::TestGate;
 
-pub struct Entities(cedar_policy::Entities, entities::Entities);
+#[derive(Debug, Error)]
+pub enum DataStoreError {
+    #[error("Entity {0} is its own ancestor, the entity hierarchy must not contain cycles")]
+    Cycle(String),
+    #[error("Entity {0} already exists with different attributes or parents")]
+    Duplicate(String),
+    #[error("Action {0} does not match its declaration in the schema")]
+    ActionMismatch(String),
+    #[error("The UID of entity {0} can not be changed")]
+    UidChanged(String),
+    #[error("Entity {0} references the missing parent {1}")]
+    DanglingParent(String, String),
+    #[error("Entity {0} can not be removed while {1} references it as a parent")]
+    Referenced(String, String),
+    #[error("Entity does not conform to the schema: {0}")]
+    Invalid(String),
+}
+
+/// Entities indexed by UID, with the transitive ancestors of every entity kept up to date.
+///
+/// Writes only parse the changed entities and recompute the ancestors of the changed
+/// entities and their descendants, instead of rebuilding the whole store. Each write is
+/// journaled, so it can be undone if it fails and only the entities it changed are copied
+/// to the next snapshot.
+struct Index {
+    entities: HashMap<EntityUID, Arc<Entity>>,
+    /// Direct children of every UID referenced as a parent, even if that entity is missing
+    children: HashMap<EntityUID, HashSet<EntityUID>>,
+    /// Action entities of the schema the entities were last validated against
+    actions: Vec<Arc<Entity>>,
+    /// Sequence number of the last applied delta batch, kept across full reloads
+    sequence: Option<u64>,
+    /// Changes made since they were last published
+    changes: Vec<(EntityUID, Operation)>,
+    /// What the current write replaced, to undo it
+    journal: Journal,
+    /// The previously published snapshot, recycled for the next one once no request reads it
+    spare: Option<Arc<entities::Entities>>,
+    /// Entities that may differ between the spare snapshot and the index
+    stale: HashSet<EntityUID>,
+}
+
+/// The state of the index before the current write.
+#[derive(Default)]
+struct Journal {
+    /// The previous version of every changed entity, `None` for added ones
+    entities: HashMap<EntityUID, Option<Arc<Entity>>>,
+    /// Links between parents and children, in order, added if `true` and removed otherwise
+    links: Vec<(EntityUID, EntityUID, bool)>,
+    actions: Option<Vec<Arc<Entity>>>,
+    sequence: Option<Option<u64>>,
+}
 
-impl Entities {
+impl Index {
     fn empty() -> Self {
         Self {
-            0: cedar_policy::Entities::empty(),
-            1: entities::Entities::new(),
+            entities: HashMap::new(),
+            children: HashMap::new(),
+            actions: Vec::new(),
+            sequence: None,
+            changes: Vec::new(),
+            journal: Journal::default(),
+            spare: None,
+            stale: HashSet::new(),
         }
     }
 
-    fn cedar_entities(&self) -> cedar_policy::Entities {
-        self.0.clone()
+    fn get(&self, uid: &EntityUID) -> Option<&Entity> {
+        self.entities.get(uid).map(Arc::as_ref)
     }
 
-    #[allow(dead_code)]
-    fn core_entities(&self) -> entities::Entities {
-        self.1.clone()
+    fn insert(&mut self, entity: Arc<Entity>) {
+        let uid = entity.uid().clone();
+        let previous = self.entities.insert(uid.clone(), entity);
+        self.journal.entities.entry(uid).or_insert(previous);
     }
 
-    fn new(cedar_entities: cedar_policy::Entities, core_entities: entities::Entities) -> Self {
-        Self {
-            0: cedar_entities,
-            1: core_entities,
+    fn delete(&mut self, uid: &EntityUID) {
+        if let Some(previous) = self.entities.remove(uid) {
+            self.journal
+                .entities
+                .entry(uid.clone())
+                .or_insert(Some(previous));
+        }
+    }
+
+    fn link<'a>(&mut self, child: &EntityUID, parents: impl IntoIterator<Item = &'a EntityUID>) {
+        for parent in parents {
+            if self
+                .children
+                .entry(parent.clone())
+                .or_default()
+                .insert(child.clone())
+            {
+                self.journal
+                    .links
+                    .push((parent.clone(), child.clone(), true));
+            }
+        }
+    }
+
+    fn unlink(&mut self, child: &EntityUID, parents: &[EntityUID]) {
+        for parent in parents {
+            if let Some(children) = self.children.get_mut(parent) {
+                if children.remove(child) {
+                    self.journal
+                        .links
+                        .push((parent.clone(), child.clone(), false));
+                }
+                if children.is_empty() {
+                    self.children.remove(parent);
+                }
+            }
+        }
+    }
+
+    fn set_actions(&mut self, actions: Vec<Arc<Entity>>) {
+        if self.actions.len() == actions.len()
+            && self
+                .actions
+                .iter()
+                .zip(&actions)
+                .all(|(current, action)| current.deep_eq(action))
+        {
+            return;
+        }
+        let previous = std::mem::replace(&mut self.actions, actions);
+        self.journal.actions.get_or_insert(previous);
+    }
+
+    fn set_sequence(&mut self, sequence: u64) {
+        let previous = self.sequence.replace(sequence);
+        self.journal.sequence.get_or_insert(previous);
+    }
+
+    /// Undo the current write.
+    fn rollback(&mut self) {
+        let journal = std::mem::take(&mut self.journal);
+        for (uid, previous) in journal.entities {
+            match previous {
+                Some(entity) => self.entities.insert(uid, entity),
+                None => self.entities.remove(&uid),
+            };
+        }
+        for (parent, child, added) in journal.links.into_iter().rev() {
+            if !added {
+                self.children.entry(parent).or_default().insert(child);
+            } else if let Some(children) = self.children.get_mut(&parent) {
+                children.remove(&child);
+                if children.is_empty() {
+                    self.children.remove(&parent);
+                }
+            }
+        }
+        if let Some(actions) = journal.actions {
+            self.actions = actions;
+        }
+        if let Some(sequence) = journal.sequence {
+            self.sequence = sequence;
+        }
+        self.changes.clear();
+    }
+
+    /// UIDs of the entities and actions changed by the current write.
+    fn touched(&self) -> HashSet<EntityUID> {
+        let mut touched: HashSet<EntityUID> = self.journal.entities.keys().cloned().collect();
+        if let Some(previous) = &self.journal.actions {
+            touched.extend(
+                previous
+                    .iter()
+                    .chain(&self.actions)
+                    .map(|action| action.uid().clone()),
+            );
+        }
+        touched
+    }
+
+    /// The entity with the given UID in a snapshot, the schema actions taking precedence.
+    fn published(&self, uid: &EntityUID) -> Option<Arc<Entity>> {
+        self.actions
+            .iter()
+            .find(|action| action.uid() == uid)
+            .or_else(|| self.entities.get(uid))
+            .cloned()
+    }
+
+    /// Build a snapshot of the entities and schema actions from scratch.
+    fn snapshot(&self) -> entities::Entities {
+        entities::Entities::new()
+            .upsert_entities(
+                self.entities.values().chain(&self.actions).cloned(),
+                None::<&NoEntitiesSchema>,
+                TCComputation::AssumeAlreadyComputed,
+                Extensions::all_available(),
+            )
+            .expect("upserting entities without a schema or closure computation can't fail")
+    }
+
+    /// Build the next snapshot, recycling the spare one if no request reads it anymore so
+    /// only the entities changed since then are copied.
+    ///
+    /// Removing entities from a snapshot still scans it, as the cedar entities find the
+    /// descendants of the removed entities that way.
+    fn next_snapshot(&mut self, touched: &HashSet<EntityUID>) -> Arc<entities::Entities> {
+        let snapshot = match self.spare.take().map(Arc::try_unwrap) {
+            Some(Ok(spare)) => {
+                let (mut updated, mut removed) = (Vec::new(), Vec::new());
+                for uid in self.stale.iter().chain(touched) {
+                    match self.published(uid) {
+                        Some(entity) => updated.push(entity),
+                        None => removed.push(uid.clone()),
+                    }
+                }
+                spare
+                    .remove_entities(removed, TCComputation::AssumeAlreadyComputed)
+                    .expect("removing entities without closure computation can't fail")
+                    .upsert_entities(
+                        updated,
+                        None::<&NoEntitiesSchema>,
+                        TCComputation::AssumeAlreadyComputed,
+                        Extensions::all_available(),
+                    )
+                    .expect("upserting entities without a schema or closure computation can't fail")
+            }
+            // A request still reads the spare snapshot, or there is none after a full reload
+            _ => self.snapshot(),
+        };
+        Arc::new(snapshot)
+    }
+
+    /// Undo the current write after building its snapshot with the touched entities.
+    fn discard(&mut self, snapshot: Arc<entities::Entities>, touched: HashSet<EntityUID>) {
+        self.rollback();
+        // The snapshot only differs from the index by the undone write, so it can be recycled
+        self.spare = Some(snapshot);
+        self.stale = touched;
+    }
+
+    /// Collect the given entities and all of their descendants.
+    fn with_descendants(&self, roots: impl IntoIterator<Item = EntityUID>) -> HashSet<EntityUID> {
+        let mut affected = HashSet::new();
+        let mut queue: VecDeque<EntityUID> = roots.into_iter().collect();
+        while let Some(uid) = queue.pop_front() {
+            if !affected.insert(uid.clone()) {
+                continue;
+            }
+            if let Some(children) = self.children.get(&uid) {
+                queue.extend(children.iter().cloned());
+            }
         }
+        affected
+    }
+
+    /// Recompute the ancestors of the staged entities and of every descendant of them,
+    /// then store them.
+    ///
+    /// Entities are visited in topological order, so the ancestors of every parent are
+    /// known before its children are computed. Entities left unvisited are part of a cycle.
+    fn apply(&mut self, mut staged: HashMap<EntityUID, Entity>) -> Result<(), DataStoreError> {
+        let affected: HashSet<EntityUID> = self
+            .with_descendants(staged.keys().cloned())
+            .into_iter()
+            .filter(|uid| staged.contains_key(uid) || self.get(uid).is_some())
+            .collect();
+
+        let parents_of = |uid: &EntityUID| -> Vec<EntityUID> {
+            match staged.get(uid) {
+                Some(entity) => entity.parents().cloned().collect(),
+                None => self
+                    .get(uid)
+                    .map(|entity| entity.parents().cloned().collect())
+                    .unwrap_or_default(),
+            }
+        };
+
+        let mut pending: HashMap<&EntityUID, usize> = HashMap::new();
+        let mut affected_children: HashMap<EntityUID, Vec<&EntityUID>> = HashMap::new();
+        for uid in &affected {
+            let parents = parents_of(uid);
+            let affected_parents: Vec<EntityUID> = parents
+                .into_iter()
+                .filter(|parent| affected.contains(parent))
+                .collect();
+            pending.insert(uid, affected_parents.len());
+            for parent in affected_parents {
+                affected_children.entry(parent).or_default().push(uid);
+            }
+        }
+
+        let mut ancestors: HashMap<&EntityUID, HashSet<EntityUID>> = HashMap::new();
+        let mut ready: VecDeque<&EntityUID> = pending
+            .iter()
+            .filter(|(_, count)| **count == 0)
+            .map(|(uid, _)| *uid)
+            .collect();
+        while let Some(uid) = ready.pop_front() {
+            let mut uid_ancestors = HashSet::new();
+            for parent in parents_of(uid) {
+                match ancestors.get(&parent) {
+                    Some(parent_ancestors) => {
+                        uid_ancestors.extend(parent_ancestors.iter().cloned())
+                    }
+                    None => {
+                        if let Some(entity) = self.get(&parent) {
+                            uid_ancestors.extend(entity.ancestors().cloned());
+                        }
+                    }
+                }
+                uid_ancestors.insert(parent);
+            }
+            ancestors.insert(uid, uid_ancestors);
+            for child in affected_children.get(uid).into_iter().flatten() {
+                if let Some(count) = pending.get_mut(*child) {
+                    *count -= 1;
+                    if *count == 0 {
+                        ready.push_back(child);
+                    }
+                }
+            }
+        }
+        if let Some(uid) = affected.iter().find(|uid| !ancestors.contains_key(uid)) {
+            return Err(DataStoreError::Cycle(uid.to_string()));
+        }
+        for (uid, entity) in &staged {
+            match self.get(uid) {
+                None => self.changes.push((uid.clone(), Operation::Create)),
+                Some(previous) if !same_content(previous, entity) => {
+                    self.changes.push((uid.clone(), Operation::Update))
+                }
+                Some(_) => {}
+            }
+        }
+
+        debug!(
+            "Storing {} entities with recomputed ancestors",
+            ancestors.len()
+        );
+        for (uid, uid_ancestors) in ancestors {
+            let mut entity = match staged.remove(uid) {
+                Some(entity) => {
+                    let previous_parents: Vec<EntityUID> = self
+                        .get(uid)
+                        .map(|previous| previous.parents().cloned().collect())
+                        .unwrap_or_default();
+                    self.unlink(uid, &previous_parents);
+                    self.link(uid, entity.parents());
+                    entity
+                }
+                None => match self.get(uid) {
+                    Some(entity) => entity.clone(),
+                    None => continue,
+                },
+            };
+            entity.remove_all_indirect_ancestors();
+            for ancestor in uid_ancestors {
+                entity.add_indirect_ancestor(ancestor);
+            }
+            self.insert(Arc::new(entity));
+        }
+        Ok(())
+    }
+
+    /// Check whether an entity is stored or is one of the given schema actions.
+    fn exists(&self, uid: &EntityUID, actions: &[Arc<Entity>]) -> bool {
+        self.get(uid).is_some() || actions.iter().any(|action| action.uid() == uid)
+    }
+
+    /// Check that the parents of the staged entities are stored or staged themselves.
+    fn check_references(
+        &self,
+        staged: &HashMap<EntityUID, Entity>,
+        actions: &[Arc<Entity>],
+        integrity: schemas::IntegrityMode,
+    ) -> Result<(), DataStoreError> {
+        for entity in staged.values() {
+            for parent in entity.parents() {
+                if staged.contains_key(parent) || self.exists(parent, actions) {
+                    continue;
+                }
+                match integrity {
+                    schemas::IntegrityMode::Warn => warn!(
+                        "Entity {} references the missing parent {}",
+                        entity.uid(),
+                        parent
+                    ),
+                    schemas::IntegrityMode::Reject | schemas::IntegrityMode::Cascade => {
+                        return Err(DataStoreError::DanglingParent(
+                            entity.uid().to_string(),
+                            parent.to_string(),
+                        ))
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Remove entities, handling the references of their children according to the mode.
+    ///
+    /// Failing to update the children leaves the index partly changed, to be rolled back.
+    fn remove(
+        &mut self,
+        uids: &[EntityUID],
+        integrity: schemas::IntegrityMode,
+    ) -> Result<Vec<Arc<Entity>>, DataStoreError> {
+        let removed: Vec<Arc<Entity>> = uids
+            .iter()
+            .filter_map(|uid| self.entities.get(uid).cloned())
+            .collect();
+        if removed.is_empty() {
+            return Ok(removed);
+        }
+        let removed_uids: HashSet<&EntityUID> = removed.iter().map(|entity| entity.uid()).collect();
+
+        let mut children: HashMap<EntityUID, Entity> = HashMap::new();
+        for entity in &removed {
+            for child in self.children.get(entity.uid()).into_iter().flatten() {
+                if removed_uids.contains(child) {
+                    continue;
+                }
+                match integrity {
+                    schemas::IntegrityMode::Reject => {
+                        return Err(DataStoreError::Referenced(
+                            entity.uid().to_string(),
+                            child.to_string(),
+                        ))
+                    }
+                    schemas::IntegrityMode::Warn => {
+                        warn!("Entity {} keeps the removed parent {}", child, entity.uid())
+                    }
+                    schemas::IntegrityMode::Cascade => {}
+                }
+                if let Some(child_entity) = self.get(child) {
+                    children
+                        .entry(child.clone())
+                        .or_insert_with(|| child_entity.clone());
+                }
+            }
+        }
+        for entity in &removed {
+            let parents: Vec<EntityUID> = entity.parents().cloned().collect();
+            self.unlink(entity.uid(), &parents);
+            self.delete(entity.uid());
+            if integrity == schemas::IntegrityMode::Cascade {
+                for child in children.values_mut() {
+                    child.remove_parent(entity.uid());
+                }
+            }
+        }
+
+        self.changes.extend(
+            removed
+                .iter()
+                .map(|entity| (entity.uid().clone(), Operation::Delete)),
+        );
+        // The children lose the ancestors of the removed entities, and with cascading the
+        // removed entities themselves as parents
+        let recorded = self.changes.len();
+        let updated: Vec<EntityUID> = match integrity {
+            schemas::IntegrityMode::Cascade => children.keys().cloned().collect(),
+            _ => Vec::new(),
+        };
+        // Ancestors can only shrink, so this should never find a cycle. If it does, the
+        // error undoes the whole removal instead of leaving the children half updated.
+        self.apply(children)?;
+        self.changes.truncate(recorded);
+        self.changes
+            .extend(updated.into_iter().map(|uid| (uid, Operation::Update)));
+        Ok(removed)
+    }
+
+    /// Record the changes from a previous version of the index to this one.
+    fn record_changes_from(&mut self, previous: &Index) {
+        let mut changes = Vec::new();
+        for entity in self.entities.values() {
+            match previous.get(entity.uid()) {
+                None => changes.push((entity.uid().clone(), Operation::Create)),
+                Some(stored) if !same_content(stored, entity) => {
+                    changes.push((entity.uid().clone(), Operation::Update))
+                }
+                Some(_) => {}
+            }
+        }
+        for entity in previous.entities.values() {
+            if self.get(entity.uid()).is_none() {
+                changes.push((entity.uid().clone(), Operation::Delete));
+            }
+        }
+        self.changes = changes;
+    }
+
+    /// Find the parents that reference missing entities, as (child, parent) pairs.
+    fn dangling_references(&self) -> Vec<(EntityUID, EntityUID)> {
+        let mut dangling: Vec<(EntityUID, EntityUID)> = self
+            .children
+            .iter()
+            .filter(|(parent, _)| !self.exists(parent, &self.actions))
+            .flat_map(|(parent, children)| {
+                children
+                    .iter()
+                    .map(move |child| (child.clone(), parent.clone()))
+            })
+            .collect();
+        dangling.sort_by_cached_key(|(child, parent)| (child.to_string(), parent.to_string()));
+        dangling
+    }
+
+    /// Find cycles in the parent graph with a depth first search.
+    ///
+    /// Writes that would create a cycle are rejected, so this only reports cycles if the
+    /// ancestors of the store got out of sync with the parents.
+    fn cycles(&self) -> Vec<Vec<EntityUID>> {
+        let parents_of = |uid: &EntityUID| -> Vec<EntityUID> {
+            self.get(uid)
+                .map(|entity| entity.parents().cloned().collect())
+                .unwrap_or_default()
+        };
+        let mut finished: HashSet<EntityUID> = HashSet::new();
+        let mut cycles = Vec::new();
+        for entity in self.entities.values() {
+            if finished.contains(entity.uid()) {
+                continue;
+            }
+            let mut path: Vec<(EntityUID, Vec<EntityUID>)> =
+                vec![(entity.uid().clone(), parents_of(entity.uid()))];
+            while let Some((uid, pending)) = path.last_mut() {
+                match pending.pop() {
+                    Some(parent) => {
+                        if let Some(position) = path.iter().position(|(uid, _)| *uid == parent) {
+                            cycles.push(
+                                path[position..]
+                                    .iter()
+                                    .map(|(uid, _)| uid.clone())
+                                    .collect(),
+                            );
+                        } else if !finished.contains(&parent) && self.get(&parent).is_some() {
+                            let parents = parents_of(&parent);
+                            path.push((parent, parents));
+                        }
+                    }
+                    None => {
+                        finished.insert(uid.clone());
+                        path.pop();
+                    }
+                }
+            }
+        }
+        cycles
     }
 }
 
 pub struct MemoryDataStore {
-    entities: RwLock<Entities>,
+    index: RwLock<Index>,
+    /// Snapshot of the entities and schema actions, read by authorization requests without locking
+    entities: ArcSwap<entities::Entities>,
+    integrity: schemas::IntegrityMode,
+    changes: Arc<ChangeFeed>,
+    gate: Option<Arc<TestGate>>,
 }
 
 impl MemoryDataStore {
     pub fn new() -> Self {
+        Self::with_integrity(schemas::IntegrityMode::default())
+    }
+
+    /// Create a store that enforces the given referential integrity mode on writes.
+    pub fn with_integrity(integrity: schemas::IntegrityMode) -> Self {
         Self {
-            entities: RwLock::new(Entities::empty()),
+            index: RwLock::new(Index::empty()),
+            entities: ArcSwap::from_pointee(entities::Entities::new()),
+            integrity,
+            changes: Arc::new(ChangeFeed::new()),
+            gate: None,
         }
     }
 
-    async fn read(&self) -> RwLockReadGuard<Entities> {
+    /// Publish the changes of the store to the given feed.
+    pub fn with_changes(mut self, changes: Arc<ChangeFeed>) -> Self {
+        self.changes = changes;
+        self
+    }
+
+    /// Check every write against the policy test cases before publishing it.
+    pub fn with_gate(mut self, gate: Arc<TestGate>) -> Self {
+        gate.publish_entities(EntitySnapshot(self.entities.load_full()));
+        self.gate = Some(gate);
+        self
+    }
+
+    async fn read(&self) -> RwLockReadGuard<Index> {
         debug!("Trying to acquire read lock on entities");
-        self.entities.read().await
+        self.index.read().await
     }
 
-    async fn write(&self) -> RwLockWriteGuard<Entities> {
+    async fn write(&self) -> RwLockWriteGuard<Index> {
         debug!("Trying to acquire write lock on entities");
-        self.entities.write().await
+        self.index.write().await
+    }
+
+    /// Lock the gate, if any, until the checked write is published.
+    async fn lock_gate(&self) -> Option<MutexGuard<'_, ()>> {
+        match &self.gate {
+            Some(gate) => Some(gate.lock().await),
+            None => None,
+        }
+    }
+
+    /// Check a snapshot about to be published against the gate, if any.
+    async fn check(&self, snapshot: &Arc<entities::Entities>) -> Result<(), StoreError> {
+        match &self.gate {
+            Some(gate) => gate.check_entities(&EntitySnapshot(snapshot.clone())).await,
+            None => Ok(()),
+        }
+    }
+
+    /// Publish a new snapshot of the entities and the changes recorded in the index,
+    /// while the index is still locked to keep their order.
+    fn publish(&self, index: &mut Index, snapshot: Arc<entities::Entities>) {
+        if let Some(gate) = &self.gate {
+            gate.publish_entities(EntitySnapshot(snapshot.clone()));
+        }
+        index.journal = Journal::default();
+        let changes = std::mem::take(&mut index.changes);
+        self.changes.publish(
+            changes
+                .into_iter()
+                .map(|(uid, operation)| (ObjectType::Entity, uid.to_string(), operation)),
+        );
+    }
+
+    /// Run a write on the locked index, publishing it if it succeeds and passes the gate,
+    /// and undoing it otherwise.
+    ///
+    /// Readers hold on to the previous snapshot, so it can't be updated in place. It is
+    /// kept as the spare of the index instead, and brought up to date by the next write.
+    async fn commit<T>(
+        &self,
+        index: &mut Index,
+        write: impl FnOnce(&mut Index) -> Result<T, StoreError>,
+    ) -> Result<T, StoreError> {
+        let result = match write(index) {
+            Ok(result) => result,
+            Err(err) => {
+                index.rollback();
+                return Err(err);
+            }
+        };
+        let _gate = self.lock_gate().await;
+        let touched = index.touched();
+        let snapshot = index.next_snapshot(&touched);
+        if let Err(err) = self.check(&snapshot).await {
+            index.discard(snapshot, touched);
+            return Err(err);
+        }
+        index.spare = Some(self.entities.swap(snapshot.clone()));
+        index.stale = touched;
+        self.publish(index, snapshot);
+        Ok(result)
+    }
+
+    /// Replace the locked index with one loaded from scratch, building its snapshot from
+    /// scratch too, if it passes the gate.
+    async fn replace(&self, lock: &mut Index, mut index: Index) -> Result<(), StoreError> {
+        index.sequence = lock.sequence;
+        index.record_changes_from(lock);
+        let snapshot = Arc::new(index.snapshot());
+        let _gate = self.lock_gate().await;
+        self.check(&snapshot).await?;
+        *lock = index;
+        self.entities.store(snapshot.clone());
+        self.publish(lock, snapshot);
+        Ok(())
+    }
+
+    /// Edit the JSON of an entity and store the result, validating it like any upsert.
+    async fn edit_entity(
+        &self,
+        uid: &EntityUid,
+        schema: Option<Schema>,
+        check_references: bool,
+        edit: impl FnOnce(&mut schemas::Entity) -> Result<(), StoreError> + Send,
+    ) -> Result<Option<schemas::Entity>, StoreError> {
+        let actions = action_entities(&schema)?;
+        let mut lock = self.write().await;
+        let mut entity = match lock.get(uid.as_ref()) {
+            Some(entity) => schemas::Entity::from(entity.clone()),
+            None => return Ok(None),
+        };
+        edit(&mut entity)?;
+        let parsed = parse_entities(std::iter::once(entity).collect(), &schema, &actions)?;
+        if !parsed.contains_key(uid.as_ref()) {
+            return Err(DataStoreError::UidChanged(uid.to_string()).into());
+        }
+        if check_references {
+            lock.check_references(&parsed, &actions, self.integrity)?;
+        }
+        let updated = to_schema_entities(parsed.values()).into_iter().next();
+        self.commit(&mut lock, |index| {
+            index.apply(parsed)?;
+            index.set_actions(actions);
+            Ok(updated)
+        })
+        .await
+    }
+
+    /// Add or remove a parent of an entity.
+    async fn update_parent(
+        &self,
+        uid: &EntityUid,
+        parent: &EntityUid,
+        add: bool,
+        schema: Option<Schema>,
+    ) -> Result<Option<schemas::Entity>, StoreError> {
+        self.edit_entity(uid, schema, add, |entity| {
+            entity.set_parent(parent, add);
+            Ok(())
+        })
+        .await
+    }
+
+    /// Apply operations in order to the given index, returning the number of upserted and
+    /// deleted entities.
+    fn apply_operations(
+        &self,
+        index: &mut Index,
+        operations: Vec<schemas::DeltaOperation>,
+        schema: &Option<Schema>,
+        actions: &[Arc<Entity>],
+    ) -> Result<(usize, usize), StoreError> {
+        let (mut upserted, mut deleted) = (0, 0);
+        let mut pending: Vec<schemas::Entity> = Vec::new();
+        let flush = |index: &mut Index, pending: &mut Vec<schemas::Entity>| {
+            if pending.is_empty() {
+                return Ok(());
+            }
+            // Later upserts of the same entity in a batch replace earlier ones
+            let mut latest: HashMap<Option<EntityUid>, schemas::Entity> = HashMap::new();
+            for entity in pending.drain(..) {
+                latest.insert(
+                    entity
+                        .get()
+                        .get("uid")
+                        .cloned()
+                        .and_then(|uid| EntityUid::from_json(uid).ok()),
+                    entity,
+                );
+            }
+            let parsed = parse_entities(latest.into_values().collect(), schema, actions)?;
+            index.check_references(&parsed, actions, self.integrity)?;
+            index.apply(parsed)?;
+            Ok::<(), StoreError>(())
+        };
+        for operation in operations {
+            match operation {
+                schemas::DeltaOperation::Upsert { entity } => {
+                    pending.push(entity);
+                    upserted += 1;
+                }
+                schemas::DeltaOperation::Delete { uid } => {
+                    flush(index, &mut pending)?;
+                    let uid: EntityUID = EntityUid::try_from(&uid)?.into();
+                    deleted += index.remove(&[uid], self.integrity)?.len();
+                }
+            }
+        }
+        flush(index, &mut pending)?;
+        Ok((upserted, deleted))
+    }
+}
+
+/// Compare the stored content of two entities, ignoring the ancestors computed from the hierarchy.
+fn same_content(left: &Entity, right: &Entity) -> bool {
+    left.uid() == right.uid()
+        && left.attrs().collect::<HashMap<_, _>>() == right.attrs().collect::<HashMap<_, _>>()
+        && left.tags().collect::<HashMap<_, _>>() == right.tags().collect::<HashMap<_, _>>()
+        && left.parents().collect::<HashSet<_>>() == right.parents().collect::<HashSet<_>>()
+}
+
+fn action_entities(schema: &Option<Schema>) -> Result<Vec<Arc<Entity>>, StoreError> {
+    match schema {
+        Some(schema) => Ok(schema
+            .action_entities()
+            .map_err(StoreError::bad_request)?
+            .into_iter()
+            .map(|action| Arc::new(action.as_ref().clone()))
+            .collect()),
+        None => Ok(Vec::new()),
+    }
+}
+
+fn is_action(entity: &schemas::Entity) -> bool {
+    entity
+        .get()
+        .get("uid")
+        .and_then(|uid| EntityUid::from_json(uid.clone()).ok())
+        .is_some_and(|uid| EntityUID::from(uid).entity_type().is_action())
+}
+
+/// Parse entities one by one, validating them against the schema if one is given.
+///
+/// Actions are declared by the schema, so stored actions must be declared there and
+/// may only have parents that are ancestors of the declared action.
+fn parse_entities(
+    entities: schemas::Entities,
+    schema: &Option<Schema>,
+    actions: &[Arc<Entity>],
+) -> Result<HashMap<EntityUID, Entity>, StoreError> {
+    let mut parsed: HashMap<EntityUID, Entity> = HashMap::new();
+    for entity in entities {
+        let action = is_action(&entity);
+        let entity_schema = if action { None } else { schema.as_ref() };
+        let entity =
+            match cedar_policy::Entity::from_json_value(entity.get().clone(), entity_schema) {
+                Ok(entity) => entity.as_ref().clone(),
+                Err(err) => {
+                    error!("Failed to parse entity");
+                    // Entities that only fail with the schema don't conform to it
+                    if entity_schema.is_some()
+                        && cedar_policy::Entity::from_json_value(entity.get().clone(), None).is_ok()
+                    {
+                        return Err(DataStoreError::Invalid(err.to_string()).into());
+                    }
+                    return Err(StoreError::bad_request(err));
+                }
+            };
+        if action && schema.is_some() {
+            let declared = actions
+                .iter()
+                .find(|declared| declared.uid() == entity.uid())
+                .ok_or_else(|| DataStoreError::ActionMismatch(entity.uid().to_string()))?;
+            if entity.attrs_len() > 0
+                || !entity
+                    .parents()
+                    .all(|parent| declared.is_descendant_of(parent))
+            {
+                return Err(DataStoreError::ActionMismatch(entity.uid().to_string()).into());
+            }
+        }
+        if let Some(previous) = parsed.get(entity.uid()) {
+            if !previous.deep_eq(&entity) {
+                return Err(DataStoreError::Duplicate(entity.uid().to_string()).into());
+            }
+        }
+        parsed.insert(entity.uid().clone(), entity);
     }
+    Ok(parsed)
+}
+
+fn to_schema_entities<'a>(entities: impl IntoIterator<Item = &'a Entity>) -> schemas::Entities {
+    entities
+        .into_iter()
+        .map(|entity| schemas::Entity::from(entity.clone()))
+        .collect()
 }
 
 #[async_trait]
 impl DataStore for MemoryDataStore {
-    async fn entities(&self) -> cedar_policy::Entities {
-        let lock = self.read().await;
-        lock.cedar_entities()
+    async fn entities(&self) -> EntitySnapshot {
+        EntitySnapshot(self.entities.load_full())
     }
 
     async fn get_entities(&self) -> schemas::Entities {
         info!("Getting stored entities");
         let lock = self.read().await;
-        schemas::Entities::from(lock.1.clone())
+        to_schema_entities(lock.entities.values().map(Arc::as_ref))
+    }
+
+    async fn get_entity(&self, uid: &EntityUid) -> Option<schemas::Entity> {
+        debug!("Getting stored entity {}", uid);
+        let lock = self.read().await;
+        lock.get(uid.as_ref()).cloned().map(schemas::Entity::from)
+    }
+
+    async fn related_entities(
+        &self,
+        uid: &EntityUid,
+        relation: Relation,
+    ) -> Option<Vec<EntityUid>> {
+        let lock = self.read().await;
+        let entity = lock.get(uid.as_ref())?;
+        let mut related: Vec<EntityUID> = match relation {
+            Relation::Parents => entity.parents().cloned().collect(),
+            Relation::Ancestors => entity.ancestors().cloned().collect(),
+            Relation::Children => lock
+                .children
+                .get(entity.uid())
+                .into_iter()
+                .flatten()
+                .cloned()
+                .collect(),
+            Relation::Descendants => lock
+                .with_descendants([entity.uid().clone()])
+                .into_iter()
+                .filter(|descendant| descendant != entity.uid())
+                .collect(),
+        };
+        related.sort_by_cached_key(|uid| uid.to_string());
+        Some(related.into_iter().map(EntityUid::from).collect())
+    }
+
+    async fn add_parent(
+        &self,
+        uid: &EntityUid,
+        parent: &EntityUid,
+        schema: Option<Schema>,
+    ) -> Result<Option<schemas::Entity>, StoreError> {
+        info!("Adding parent {} to entity {}", parent, uid);
+        self.update_parent(uid, parent, true, schema).await
     }
 
-    async fn delete_entities(&self) {
+    async fn remove_parent(
+        &self,
+        uid: &EntityUid,
+        parent: &EntityUid,
+        schema: Option<Schema>,
+    ) -> Result<Option<schemas::Entity>, StoreError> {
+        info!("Removing parent {} from entity {}", parent, uid);
+        self.update_parent(uid, parent, false, schema).await
+    }
+
+    async fn update_entity(
+        &self,
+        uid: &EntityUid,
+        edit: EntityEdit<'_>,
+        schema: Option<Schema>,
+    ) -> Result<Option<schemas::Entity>, StoreError> {
+        info!("Updating entity {}", uid);
+        self.edit_entity(uid, schema, true, edit).await
+    }
+
+    async fn patch_entity(
+        &self,
+        uid: &EntityUid,
+        patch: &Patch,
+        schema: Option<Schema>,
+    ) -> Result<Option<schemas::Entity>, StoreError> {
+        info!("Patching entity {}", uid);
+        self.edit_entity(uid, schema, true, |entity| {
+            let patched = patch.apply(entity.get()).map_err(StoreError::bad_request)?;
+            *entity = serde_json::from_value(patched).map_err(StoreError::bad_request)?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn query_entities(
+        &self,
+        query: &schemas::EntityQuery,
+    ) -> Result<schemas::EntityPage, StoreError> {
+        debug!("Querying stored entities: {:?}", query);
+        let parent: Option<EntityUID> = match &query.parent {
+            Some(parent) => Some(EntityUid::try_from(parent)?.into()),
+            None => None,
+        };
+        let lock = self.read().await;
+
+        // Narrow down the candidates with the hierarchy index before looking at each entity
+        let candidates: Vec<&Entity> = match &parent {
+            Some(parent) if query.transitive => lock
+                .with_descendants([parent.clone()])
+                .iter()
+                .filter(|uid| *uid != parent)
+                .filter_map(|uid| lock.get(uid))
+                .collect(),
+            Some(parent) => lock
+                .children
+                .get(parent)
+                .into_iter()
+                .flatten()
+                .filter_map(|uid| lock.get(uid))
+                .collect(),
+            None => lock.entities.values().map(Arc::as_ref).collect(),
+        };
+        let mut matched: Vec<(String, &Entity)> = candidates
+            .into_iter()
+            .filter(|entity| {
+                let uid = EntityUid::from(entity.uid().clone());
+                query
+                    .entity_type
+                    .as_ref()
+                    .is_none_or(|entity_type| uid.type_name().basename() == entity_type)
+                    && query
+                        .namespace
+                        .as_ref()
+                        .is_none_or(|namespace| &uid.type_name().namespace() == namespace)
+                    && query
+                        .id_prefix
+                        .as_ref()
+                        .is_none_or(|prefix| uid.id().unescaped().starts_with(prefix.as_str()))
+                    && query.has_children.is_none_or(|has_children| {
+                        lock.children.contains_key(entity.uid()) == has_children
+                    })
+            })
+            .map(|entity| (entity.uid().to_string(), entity))
+            .collect();
+        matched.sort_by(|(left, _), (right, _)| left.cmp(right));
+
+        // Attribute filters need the JSON form, so only the page is converted without them
+        let limit = query.limit.unwrap_or(usize::MAX);
+        let (total, page): (usize, Vec<schemas::Entity>) = if query.attributes.is_empty() {
+            let page = matched
+                .iter()
+                .skip(query.offset)
+                .take(limit)
+                .map(|(_, entity)| schemas::Entity::from((*entity).clone()))
+                .collect();
+            (matched.len(), page)
+        } else {
+            let filtered: Vec<schemas::Entity> = matched
+                .into_iter()
+                .map(|(_, entity)| schemas::Entity::from(entity.clone()))
+                .filter(|entity| {
+                    let attrs = entity.get().get("attrs").unwrap_or(&Value::Null);
+                    query.attributes.iter().all(|filter| filter.matches(attrs))
+                })
+                .collect();
+            let total = filtered.len();
+            let page = filtered
+                .into_iter()
+                .skip(query.offset)
+                .take(limit)
+                .collect();
+            (total, page)
+        };
+
+        Ok(schemas::EntityPage {
+            total,
+            offset: query.offset,
+            entities: page
+                .into_iter()
+                .map(|entity| query.project(entity))
+                .collect(),
+        })
+    }
+
+    async fn delete_entities(&self) -> Result<(), StoreError> {
         info!("Deleting stored entities");
         let mut lock = self.write().await;
-        *lock = Entities::empty();
+        self.replace(&mut lock, Index::empty()).await
     }
 
     async fn update_entities(
         &self,
         entities: schemas::Entities,
         schema: Option<Schema>,
-    ) -> Result<schemas::Entities, Box<dyn Error>> {
+    ) -> Result<schemas::Entities, StoreError> {
         info!("Updating stored entities");
+        let actions = action_entities(&schema)?;
+        let parsed = parse_entities(entities, &schema, &actions)?;
+        let mut index = Index::empty();
+        index.check_references(&parsed, &actions, self.integrity)?;
+        index.apply(parsed)?;
+        index.actions = actions;
+        let schema_entities = to_schema_entities(index.entities.values().map(Arc::as_ref));
         let mut lock = self.write().await;
-        let core_entities: entities::Entities = match entities.try_into() {
-            Ok(entities) => entities,
-            Err(err) => {
-                return {
-                    error!("Failed to parse entities");
-                    Err(err.into())
-                }
-            }
-        };
-        let schema_entities: schemas::Entities = core_entities.clone().into();
-        let cedar_entities: cedar_policy::Entities =
-            match schema_entities.borrow().convert_to_cedar_entities(&schema) {
-                Ok(entities) => entities,
-                Err(err) => return Err(err.into()),
-            };
-        *lock = Entities::new(cedar_entities, core_entities);
+        self.replace(&mut lock, index).await?;
         Ok(schema_entities)
     }
 
@@ -108,36 +1084,157 @@ impl DataStore for MemoryDataStore {
         &self,
         new_entities: schemas::Entities,
         schema: Option<Schema>,
-    ) -> Result<schemas::Entities, Box<dyn Error>> {
-        info!("Adding entities to store atomically");
+    ) -> Result<schemas::Entities, StoreError> {
+        info!("Adding {} entities to store", new_entities.len());
+        let actions = action_entities(&schema)?;
+        let parsed = parse_entities(new_entities, &schema, &actions)?;
         let mut lock = self.write().await;
-        
-        // 1. Get current entities
-        let existing_core = lock.1.clone();
-        let mut existing_schemas: schemas::Entities = existing_core.into();
-        
-        // 2. Merge with new entities
-        existing_schemas.extend(new_entities.into_iter());
-        
-        // 3. Re-parse everything
-        let core_entities: entities::Entities = match existing_schemas.try_into() {
-            Ok(entities) => entities,
-            Err(err) => {
-                return {
-                    error!("Failed to parse merged entities");
-                    Err(err.into())
-                }
-            }
-        };
-        let schema_entities: schemas::Entities = core_entities.clone().into();
-        let cedar_entities: cedar_policy::Entities =
-            match schema_entities.borrow().convert_to_cedar_entities(&schema) {
-                Ok(entities) => entities,
-                Err(err) => return Err(err.into()),
-            };
-            
-        // 4. Save
-        *lock = Entities::new(cedar_entities, core_entities);
-        Ok(schema_entities)
+        if let Some(existing) = parsed.values().find(|entity| {
+            lock.get(entity.uid())
+                .is_some_and(|existing| !existing.deep_eq(entity))
+        }) {
+            return Err(DataStoreError::Duplicate(existing.uid().to_string()).into());
+        }
+        lock.check_references(&parsed, &actions, self.integrity)?;
+        let added = to_schema_entities(parsed.values());
+        self.commit(&mut lock, |index| {
+            index.apply(parsed)?;
+            index.set_actions(actions);
+            Ok(added)
+        })
+        .await
+    }
+
+    async fn upsert_entities(
+        &self,
+        entities: schemas::Entities,
+        schema: Option<Schema>,
+    ) -> Result<schemas::Entities, StoreError> {
+        info!("Upserting {} entities", entities.len());
+        let actions = action_entities(&schema)?;
+        let parsed = parse_entities(entities, &schema, &actions)?;
+        let upserted = to_schema_entities(parsed.values());
+        let mut lock = self.write().await;
+        lock.check_references(&parsed, &actions, self.integrity)?;
+        self.commit(&mut lock, |index| {
+            index.apply(parsed)?;
+            index.set_actions(actions);
+            Ok(upserted)
+        })
+        .await
+    }
+
+    async fn upsert_entity(
+        &self,
+        entity: schemas::Entity,
+        schema: Option<Schema>,
+    ) -> Result<schemas::Entity, StoreError> {
+        let upserted = self
+            .upsert_entities(std::iter::once(entity).collect(), schema)
+            .await?;
+        upserted
+            .into_iter()
+            .next()
+            .ok_or_else(|| StoreError::bad_request("The entity was not stored"))
+    }
+
+    async fn remove_entity(
+        &self,
+        uid: &EntityUid,
+    ) -> Result<Option<schemas::Entity>, StoreError> {
+        info!("Removing entity {}", uid);
+        let mut lock = self.write().await;
+        let removed = self
+            .commit(&mut lock, |index| {
+                Ok(index.remove(&[uid.clone().into()], self.integrity)?)
+            })
+            .await?;
+        Ok(removed
+            .into_iter()
+            .next()
+            .map(|entity| schemas::Entity::from(entity.as_ref().clone())))
+    }
+
+    async fn remove_entities(
+        &self,
+        uids: Vec<EntityUid>,
+    ) -> Result<schemas::Entities, StoreError> {
+        info!("Removing {} entities", uids.len());
+        let uids: Vec<EntityUID> = uids.into_iter().map(EntityUID::from).collect();
+        let mut lock = self.write().await;
+        let removed = self
+            .commit(&mut lock, |index| Ok(index.remove(&uids, self.integrity)?))
+            .await?;
+        Ok(to_schema_entities(removed.iter().map(Arc::as_ref)))
+    }
+
+    async fn apply_delta(
+        &self,
+        batch: schemas::DeltaBatch,
+        schema: Option<Schema>,
+    ) -> Result<schemas::DeltaResult, StoreError> {
+        let actions = action_entities(&schema)?;
+        let mut lock = self.write().await;
+        if let Some(sequence) = lock.sequence.filter(|sequence| batch.sequence <= *sequence) {
+            info!(
+                "Skipping delta batch {}, already applied up to {}",
+                batch.sequence, sequence
+            );
+            return Ok(schemas::DeltaResult {
+                applied: false,
+                sequence,
+                upserted: 0,
+                deleted: 0,
+            });
+        }
+        info!(
+            "Applying delta batch {} with {} operations",
+            batch.sequence,
+            batch.operations.len()
+        );
+
+        // A failing operation undoes the whole batch
+        self.commit(&mut lock, |index| {
+            let (upserted, deleted) =
+                self.apply_operations(index, batch.operations, &schema, &actions)?;
+            index.set_actions(actions);
+            index.set_sequence(batch.sequence);
+            Ok(schemas::DeltaResult {
+                applied: true,
+                sequence: batch.sequence,
+                upserted,
+                deleted,
+            })
+        })
+        .await
+    }
+
+    async fn sync_status(&self) -> schemas::SyncStatus {
+        schemas::SyncStatus {
+            sequence: self.read().await.sequence,
+        }
+    }
+
+    async fn integrity_report(&self) -> schemas::IntegrityReport {
+        debug!("Checking the referential integrity of stored entities");
+        let lock = self.read().await;
+        let reference =
+            |uid: &EntityUID| schemas::EntityReference::from(EntityUid::from(uid.clone()));
+        schemas::IntegrityReport {
+            mode: self.integrity,
+            dangling: lock
+                .dangling_references()
+                .iter()
+                .map(|(entity, parent)| schemas::DanglingReference {
+                    entity: reference(entity),
+                    parent: reference(parent),
+                })
+                .collect(),
+            cycles: lock
+                .cycles()
+                .iter()
+                .map(|cycle| cycle.iter().map(reference).collect())
+                .collect(),
+        }
     }
 }
@@ -1,26 +1,136 @@
-use std::error::Error;
+use std::ops::Deref;
+use std::sync::Arc;
 
 use async_trait::async_trait;
-use cedar_policy::Schema;
+use cedar_policy::{EntityUid, Schema};
+use cedar_policy_core::entities;
+use ref_cast::RefCast;
 
 use crate::schemas::data as schemas;
+use crate::schemas::patch::Patch;
+use crate::services::errors::StoreError;
 
 pub mod load_from_file;
 pub mod memory;
 
+/// Entities related to an entity through the entity hierarchy
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Relation {
+    Parents,
+    Ancestors,
+    Children,
+    Descendants,
+}
+
+/// Edit of the JSON of an entity, run by [`DataStore::update_entity`] on the stored entity.
+pub type EntityEdit<'a> =
+    Box<dyn FnOnce(&mut schemas::Entity) -> Result<(), StoreError> + Send + 'a>;
+
+/// Snapshot of entities shared with the requests reading them, which later writes leave unchanged.
+///
+/// It dereferences to the cedar entities, so it can be passed to the authorizer as is.
+#[derive(Clone)]
+pub struct EntitySnapshot(Arc<entities::Entities>);
+
+impl EntitySnapshot {
+    pub fn empty() -> Self {
+        Self(Arc::new(entities::Entities::new()))
+    }
+}
+
+impl Deref for EntitySnapshot {
+    type Target = cedar_policy::Entities;
+
+    fn deref(&self) -> &Self::Target {
+        cedar_policy::Entities::ref_cast(&self.0)
+    }
+}
+
+/// Entities that are not stored, such as the ones given by a request, are copied once.
+impl From<cedar_policy::Entities> for EntitySnapshot {
+    fn from(entities: cedar_policy::Entities) -> Self {
+        Self(Arc::new(entities.as_ref().clone()))
+    }
+}
+
 #[async_trait]
 pub trait DataStore: Send + Sync {
-    async fn entities(&self) -> cedar_policy::Entities;
+    /// Snapshot of the entities, including the actions of the schema, which later writes leave unchanged.
+    async fn entities(&self) -> EntitySnapshot;
     async fn get_entities(&self) -> schemas::Entities;
-    async fn delete_entities(&self);
+    async fn get_entity(&self, uid: &EntityUid) -> Option<schemas::Entity>;
+    /// Get the entities related to an entity, or `None` if the entity doesn't exist.
+    async fn related_entities(&self, uid: &EntityUid, relation: Relation)
+        -> Option<Vec<EntityUid>>;
+    /// Add a parent to an entity, returning the updated entity or `None` if it doesn't exist.
+    async fn add_parent(
+        &self,
+        uid: &EntityUid,
+        parent: &EntityUid,
+        schema: Option<Schema>,
+    ) -> Result<Option<schemas::Entity>, StoreError>;
+    /// Remove a parent from an entity, returning the updated entity or `None` if it doesn't exist.
+    async fn remove_parent(
+        &self,
+        uid: &EntityUid,
+        parent: &EntityUid,
+        schema: Option<Schema>,
+    ) -> Result<Option<schemas::Entity>, StoreError>;
+    /// Edit an entity and store the result in one write, validating it like any upsert,
+    /// returning the stored entity or `None` if it doesn't exist.
+    async fn update_entity(
+        &self,
+        uid: &EntityUid,
+        edit: EntityEdit<'_>,
+        schema: Option<Schema>,
+    ) -> Result<Option<schemas::Entity>, StoreError>;
+    /// Apply a JSON or merge patch to an entity, returning the result or `None` if it doesn't exist.
+    async fn patch_entity(
+        &self,
+        uid: &EntityUid,
+        patch: &Patch,
+        schema: Option<Schema>,
+    ) -> Result<Option<schemas::Entity>, StoreError>;
+    /// Get a page of the entities matching the query, ordered by UID.
+    async fn query_entities(
+        &self,
+        query: &schemas::EntityQuery,
+    ) -> Result<schemas::EntityPage, StoreError>;
+    async fn delete_entities(&self) -> Result<(), StoreError>;
     async fn update_entities(
         &self,
         entities: schemas::Entities,
         schema: Option<Schema>,
-    ) -> Result<schemas::Entities, Box<dyn Error>>;
+    ) -> Result<schemas::Entities, StoreError>;
     async fn add_entities(
         &self,
         new_entities: schemas::Entities,
         schema: Option<Schema>,
-    ) -> Result<schemas::Entities, Box<dyn Error>>;
+    ) -> Result<schemas::Entities, StoreError>;
+    /// Add or replace the given entities, keeping all other stored entities.
+    async fn upsert_entities(
+        &self,
+        entities: schemas::Entities,
+        schema: Option<Schema>,
+    ) -> Result<schemas::Entities, StoreError>;
+    /// Add or replace a single entity.
+    async fn upsert_entity(
+        &self,
+        entity: schemas::Entity,
+        schema: Option<Schema>,
+    ) -> Result<schemas::Entity, StoreError>;
+    /// Remove a single entity, returning it if it was stored.
+    async fn remove_entity(&self, uid: &EntityUid) -> Result<Option<schemas::Entity>, StoreError>;
+    /// Remove the given entities, returning the ones that were stored.
+    async fn remove_entities(&self, uids: Vec<EntityUid>) -> Result<schemas::Entities, StoreError>;
+    /// Apply an ordered batch of changes atomically, skipping batches that were already applied.
+    async fn apply_delta(
+        &self,
+        batch: schemas::DeltaBatch,
+        schema: Option<Schema>,
+    ) -> Result<schemas::DeltaResult, StoreError>;
+    /// Get the sequence number of the last applied delta batch.
+    async fn sync_status(&self) -> schemas::SyncStatus;
+    /// Report dangling parent references and cycles in the entity hierarchy.
+    async fn integrity_report(&self) -> schemas::IntegrityReport;
 }
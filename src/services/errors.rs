@@ -0,0 +1,87 @@
+use thiserror::Error;
+
+use crate::services::data::memory::DataStoreError;
+use crate::services::policies::errors::PolicyStoreError;
+
+/// Errors of the policy, data and schema stores.
+#[derive(Error, Debug)]
+#[non_exhaustive]
+pub enum StoreError {
+    /// No object with the given id is stored.
+    #[error("Unable to find {object} with id {id}")]
+    NotFound { object: &'static str, id: String },
+    /// An object with the given id is already stored, or given more than once.
+    #[error("{object} with id {id} already exists")]
+    Duplicate { object: &'static str, id: String },
+    /// The request conflicts with the stored objects, e.g. they were changed since the
+    /// request read them.
+    #[error("{reason}")]
+    Conflict { reason: String },
+    /// The request could not be parsed or applied to the stored objects.
+    #[error("{reason}")]
+    BadRequest { reason: String },
+    /// The request does not conform to the schema.
+    #[error("{reason}")]
+    Validation { reason: String },
+}
+
+impl StoreError {
+    pub fn bad_request(err: impl ToString) -> Self {
+        StoreError::BadRequest {
+            reason: err.to_string(),
+        }
+    }
+
+    pub fn conflict(err: impl ToString) -> Self {
+        StoreError::Conflict {
+            reason: err.to_string(),
+        }
+    }
+}
+
+impl From<PolicyStoreError> for StoreError {
+    fn from(err: PolicyStoreError) -> Self {
+        match err {
+            PolicyStoreError::PolicyNotFoundError(id) => StoreError::NotFound {
+                object: "Policy",
+                id,
+            },
+            PolicyStoreError::PolicyInvalid(..) => StoreError::Validation {
+                reason: err.to_string(),
+            },
+            _ => StoreError::bad_request(err),
+        }
+    }
+}
+
+impl From<cedar_policy::ParseErrors> for StoreError {
+    fn from(err: cedar_policy::ParseErrors) -> Self {
+        PolicyStoreError::PolicyParseError(err).into()
+    }
+}
+
+impl From<DataStoreError> for StoreError {
+    fn from(err: DataStoreError) -> Self {
+        match err {
+            DataStoreError::Duplicate(id) => StoreError::Duplicate {
+                object: "Entity",
+                id,
+            },
+            DataStoreError::ActionMismatch(_) | DataStoreError::Invalid(_) => {
+                StoreError::Validation {
+                    reason: err.to_string(),
+                }
+            }
+            DataStoreError::Referenced(..) => StoreError::conflict(err),
+            _ => StoreError::bad_request(err),
+        }
+    }
+}
+
+/// An invalid schema is a malformed request, not one that fails to conform to the schema,
+/// so it is a bad request rather than a validation error.
+impl From<cedar_policy::SchemaError> for StoreError {
+    fn from(err: cedar_policy::SchemaError) -> Self {
+        StoreError::bad_request(err)
+    }
+}
@@ -1,3 +1,7 @@
+pub mod authorization;
+pub mod changes;
 pub mod data;
+pub(crate) mod errors;
 pub mod policies;
 pub mod schema;
+pub mod test_cases;
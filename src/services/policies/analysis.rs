@@ -0,0 +1,310 @@
+use std::collections::HashSet;
+
+use cedar_policy::{
+    ActionConstraint, Effect, Entities, EntityTypeName, EntityUid, Policy, PolicySet,
+    PrincipalConstraint, ResourceConstraint, Schema, ValidationMode, ValidationWarning, Validator,
+};
+use log::warn;
+use rocket::serde::json::Value;
+
+use crate::schemas::analysis::{AnalysisReport, Finding, FindingKind};
+use crate::services::authorization::explain::{check_action, is_in};
+
+/// Resource constraints have the same shape as principal constraints
+fn resource_scope(constraint: ResourceConstraint) -> PrincipalConstraint {
+    match constraint {
+        ResourceConstraint::Any => PrincipalConstraint::Any,
+        ResourceConstraint::Eq(target) => PrincipalConstraint::Eq(target),
+        ResourceConstraint::In(target) => PrincipalConstraint::In(target),
+        ResourceConstraint::Is(type_name) => PrincipalConstraint::Is(type_name),
+        ResourceConstraint::IsIn(type_name, target) => PrincipalConstraint::IsIn(type_name, target),
+    }
+}
+
+/// Check that every entity matching `inner` also matches `outer`.
+fn entity_scope_within(
+    inner: &PrincipalConstraint,
+    outer: &PrincipalConstraint,
+    entities: &Entities,
+) -> bool {
+    use PrincipalConstraint::*;
+    match outer {
+        Any => true,
+        Eq(target) => matches!(inner, Eq(uid) if uid == target),
+        In(target) => match inner {
+            Eq(uid) | In(uid) | IsIn(_, uid) => is_in(entities, uid, target),
+            Any | Is(_) => false,
+        },
+        Is(type_name) => match inner {
+            Eq(uid) => uid.type_name() == type_name,
+            Is(inner_type) | IsIn(inner_type, _) => inner_type == type_name,
+            Any | In(_) => false,
+        },
+        IsIn(type_name, target) => match inner {
+            Eq(uid) => uid.type_name() == type_name && is_in(entities, uid, target),
+            IsIn(inner_type, uid) => inner_type == type_name && is_in(entities, uid, target),
+            Any | In(_) | Is(_) => false,
+        },
+    }
+}
+
+/// Check that every action matching `inner` also matches `outer`.
+fn action_scope_within(
+    inner: &ActionConstraint,
+    outer: &ActionConstraint,
+    entities: &Entities,
+) -> bool {
+    let in_outer = |uid: &EntityUid| match outer {
+        ActionConstraint::Any => true,
+        ActionConstraint::Eq(target) => uid == target,
+        ActionConstraint::In(targets) => targets.iter().any(|target| is_in(entities, uid, target)),
+    };
+    match inner {
+        ActionConstraint::Any => matches!(outer, ActionConstraint::Any),
+        ActionConstraint::Eq(uid) => in_outer(uid),
+        // A group also covers its members, which only an `in` constraint includes
+        ActionConstraint::In(uids) => match outer {
+            ActionConstraint::Eq(_) => false,
+            _ => uids.iter().all(in_outer),
+        },
+    }
+}
+
+/// A policy along with the parts of it the analysis compares.
+struct Analyzed<'a> {
+    policy: &'a Policy,
+    id: String,
+    conditions: Value,
+}
+
+impl Analyzed<'_> {
+    fn unconditional(&self) -> bool {
+        self.conditions.as_array().is_some_and(|c| c.is_empty())
+    }
+
+    /// Check that `other` applies whenever this policy does, if it is not the same policy.
+    fn covered_by(&self, other: &Analyzed, entities: &Entities) -> bool {
+        self.id != other.id
+            && !self.conditions.is_null()
+            && !other.conditions.is_null()
+            && (other.unconditional() || self.conditions == other.conditions)
+            && entity_scope_within(
+                &self.policy.principal_constraint(),
+                &other.policy.principal_constraint(),
+                entities,
+            )
+            && action_scope_within(
+                &self.policy.action_constraint(),
+                &other.policy.action_constraint(),
+                entities,
+            )
+            && entity_scope_within(
+                &resource_scope(self.policy.resource_constraint()),
+                &resource_scope(other.policy.resource_constraint()),
+                entities,
+            )
+    }
+
+    fn condition_description(&self) -> &'static str {
+        if self.unconditional() {
+            "without conditions"
+        } else {
+            "with the same conditions"
+        }
+    }
+}
+
+fn analyze_overlaps(policies: &[Analyzed], entities: &Entities, findings: &mut Vec<Finding>) {
+    let (permits, forbids): (Vec<&Analyzed>, Vec<&Analyzed>) = policies
+        .iter()
+        .partition(|analyzed| analyzed.policy.effect() == Effect::Permit);
+    for permit in &permits {
+        if let Some(forbid) = forbids.iter().find(|f| permit.covered_by(f, entities)) {
+            findings.push(Finding {
+                kind: FindingKind::Overridden,
+                policy: Some(permit.id.clone()),
+                related_policy: Some(forbid.id.clone()),
+                subject: None,
+                explanation: format!(
+                    "forbid {} applies {} to every request permit {} applies to, so {} never allows a request",
+                    forbid.id,
+                    forbid.condition_description(),
+                    permit.id,
+                    permit.id
+                ),
+            });
+            continue;
+        }
+        // Of two equivalent permits only the one with the greater ID is reported
+        let broader = permits.iter().find(|other| {
+            permit.covered_by(other, entities)
+                && (other.id < permit.id || !other.covered_by(permit, entities))
+        });
+        if let Some(broader) = broader {
+            findings.push(Finding {
+                kind: FindingKind::Redundant,
+                policy: Some(permit.id.clone()),
+                related_policy: Some(broader.id.clone()),
+                subject: None,
+                explanation: format!(
+                    "permit {} applies {} to every request permit {} applies to, so removing {} changes no decision",
+                    broader.id,
+                    broader.condition_description(),
+                    permit.id,
+                    permit.id
+                ),
+            });
+        }
+    }
+}
+
+fn analyze_schema(
+    policy_set: &PolicySet,
+    policies: &[Analyzed],
+    schema: &Schema,
+    findings: &mut Vec<Finding>,
+) {
+    let result = Validator::new(schema.clone()).validate(policy_set, ValidationMode::default());
+    for error in result.validation_errors() {
+        findings.push(Finding {
+            kind: FindingKind::Invalid,
+            policy: Some(error.policy_id().to_string()),
+            related_policy: None,
+            subject: None,
+            explanation: error.to_string(),
+        });
+    }
+    for warning in result.validation_warnings() {
+        if let ValidationWarning::ImpossiblePolicy(_) = warning {
+            findings.push(Finding {
+                kind: FindingKind::Unsatisfiable,
+                policy: Some(warning.policy_id().to_string()),
+                related_policy: None,
+                subject: None,
+                explanation: format!(
+                    "{}, the policy can never apply to a request that is valid for the schema",
+                    warning
+                ),
+            });
+        }
+    }
+
+    let actions = match schema.action_entities() {
+        Ok(actions) => actions,
+        Err(err) => {
+            warn!(
+                "Failed to build the action hierarchy of the schema: {}",
+                err
+            );
+            Entities::empty()
+        }
+    };
+    let mut used_types: HashSet<EntityTypeName> = HashSet::new();
+    for analyzed in policies {
+        for scope in [
+            analyzed.policy.principal_constraint(),
+            resource_scope(analyzed.policy.resource_constraint()),
+        ] {
+            match scope {
+                PrincipalConstraint::Any => {}
+                PrincipalConstraint::Eq(uid) | PrincipalConstraint::In(uid) => {
+                    used_types.insert(uid.type_name().clone());
+                }
+                PrincipalConstraint::Is(type_name) => {
+                    used_types.insert(type_name);
+                }
+                PrincipalConstraint::IsIn(type_name, uid) => {
+                    used_types.insert(type_name);
+                    used_types.insert(uid.type_name().clone());
+                }
+            }
+        }
+    }
+    for action in schema.actions() {
+        // Requests for an action only forbids apply to are always denied
+        let used = policies.iter().any(|analyzed| {
+            analyzed.policy.effect() == Effect::Permit
+                && check_action(analyzed.policy.action_constraint(), action, &actions).is_ok()
+        });
+        if used {
+            used_types.extend(
+                schema
+                    .principals_for_action(action)
+                    .into_iter()
+                    .flatten()
+                    .cloned(),
+            );
+            used_types.extend(
+                schema
+                    .resources_for_action(action)
+                    .into_iter()
+                    .flatten()
+                    .cloned(),
+            );
+        } else {
+            findings.push(Finding {
+                kind: FindingKind::UnusedAction,
+                policy: None,
+                related_policy: None,
+                subject: Some(action.to_string()),
+                explanation: format!(
+                    "no permit applies to action {}, so every request for it is denied",
+                    action
+                ),
+            });
+        }
+    }
+    let mut unused_types: Vec<String> = schema
+        .entity_types()
+        .filter(|type_name| !used_types.contains(*type_name))
+        .map(|type_name| type_name.to_string())
+        .collect();
+    unused_types.sort();
+    for type_name in unused_types {
+        findings.push(Finding {
+            kind: FindingKind::UnusedEntityType,
+            policy: None,
+            related_policy: None,
+            subject: Some(type_name.clone()),
+            explanation: format!(
+                "entity type {} is not in the scope of any policy, nor a principal or resource of an action a permit applies to",
+                type_name
+            ),
+        });
+    }
+}
+
+/// Analyze the policy set for redundant and overridden permits and, given a schema,
+/// for policies that can never apply and for unused actions and entity types.
+///
+/// `in` constraints are compared through the hierarchy of the given entities.
+pub fn analyze(
+    policy_set: &PolicySet,
+    schema: Option<&Schema>,
+    entities: &Entities,
+) -> AnalysisReport {
+    let mut policies: Vec<Analyzed> = policy_set
+        .policies()
+        .map(|policy| Analyzed {
+            policy,
+            id: policy.id().to_string(),
+            conditions: policy
+                .to_json()
+                .ok()
+                .and_then(|json| json.get("conditions").cloned())
+                .unwrap_or(Value::Null),
+        })
+        .collect();
+    policies.sort_by(|left, right| left.id.cmp(&right.id));
+
+    let mut findings = Vec::new();
+    analyze_overlaps(&policies, entities, &mut findings);
+    if let Some(schema) = schema {
+        analyze_schema(policy_set, &policies, schema, &mut findings);
+    }
+    AnalysisReport {
+        policies: policies.len(),
+        schema: schema.is_some(),
+        findings,
+    }
+}
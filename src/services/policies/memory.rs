@@ -1,42 +1,223 @@
 use std::borrow::Borrow;
-use std::collections::HashMap;
-use std::error::Error;
+use std::collections::{BTreeSet, HashMap};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
 
-use async_lock::{RwLock, RwLockReadGuard, RwLockWriteGuard};
+use arc_swap::{ArcSwap, ArcSwapOption};
+use async_lock::{MutexGuard, RwLock, RwLockReadGuard, RwLockWriteGuard};
 use async_trait::async_trait;
-use cedar_policy::{PolicySet, Schema, ValidationMode, ValidationResult, Validator};
-use log::{debug, info};
+use cedar_policy::{
+    Entities, PolicyId, PolicySet, Schema, ValidationMode, ValidationResult, Validator,
+};
+use log::{debug, error, info};
 
-use crate::common;
-use crate::schemas::policies::{Policy, PolicyUpdate};
+use crate::schemas::changes::{ObjectType, Operation};
+use crate::schemas::policies::{Policy, PolicyQuery, PolicyUpdate};
+use crate::services::changes::ChangeFeed;
+use crate::services::errors::StoreError;
 use crate::services::policies::errors::PolicyStoreError;
+use crate::services::policies::query::PolicyFilter;
 use crate::services::policies::PolicyStore;
+use crate::services::test_cases::TestGate;
 
-pub struct Policies(HashMap<String, cedar_policy::Policy>, PolicySet);
+/// Details of a stored policy given by clients, along with the ones kept by the store.
+#[derive(Clone)]
+struct Metadata {
+    description: Option<String>,
+    owner: Option<String>,
+    tags: BTreeSet<String>,
+    created_at: SystemTime,
+    updated_at: SystemTime,
+    claimed_author: Option<String>,
+}
+
+impl Metadata {
+    /// Metadata of a policy written now, with the details of the request.
+    fn new(policy: &Policy) -> Self {
+        let now = SystemTime::now();
+        Self {
+            description: policy.description.clone(),
+            owner: policy.owner.clone(),
+            tags: policy.tags.clone(),
+            created_at: now,
+            updated_at: now,
+            claimed_author: policy.claimed_author.clone(),
+        }
+    }
+
+    fn same_details(&self, other: &Metadata) -> bool {
+        self.description == other.description
+            && self.owner == other.owner
+            && self.tags == other.tags
+    }
+}
+
+/// What clients see of a stored policy, compared to find the policies a bulk write changed.
+#[derive(PartialEq)]
+struct PolicyState {
+    content: String,
+    description: Option<String>,
+    owner: Option<String>,
+    tags: BTreeSet<String>,
+    enabled: bool,
+}
+
+fn format_time(time: SystemTime) -> String {
+    humantime::format_rfc3339_seconds(time).to_string()
+}
+
+/// Policies by ID, along with the policy set built from the enabled ones, the IDs of
+/// the disabled ones with the time they are enabled again, if any, and the metadata
+/// of the policies.
+///
+/// The policy set is shared with the readers of the store, so a write copies it once
+/// and edits the copy, leaving the set used by in-flight requests untouched.
+#[derive(Clone)]
+pub struct Policies {
+    policies: HashMap<String, cedar_policy::Policy>,
+    policy_set: Arc<PolicySet>,
+    disabled: HashMap<String, Option<SystemTime>>,
+    metadata: HashMap<String, Metadata>,
+}
 
 impl Policies {
     fn new() -> Self {
         Self {
-            0: HashMap::new(),
-            1: PolicySet::new(),
+            policies: HashMap::new(),
+            policy_set: Arc::new(PolicySet::new()),
+            disabled: HashMap::new(),
+            metadata: HashMap::new(),
         }
     }
 
     #[allow(dead_code)]
     fn policy_map(&self) -> HashMap<String, cedar_policy::Policy> {
-        self.0.clone()
+        self.policies.clone()
+    }
+
+    /// Add or replace a policy, editing the policy set instead of rebuilding it.
+    /// A disabled policy stays disabled and out of the policy set.
+    fn insert(&mut self, policy: cedar_policy::Policy, metadata: Metadata) {
+        let id = policy.id().to_string();
+        self.metadata.insert(id.clone(), metadata);
+        let disabled = self.disabled.contains_key(&id);
+        if self.policies.contains_key(&id) && !disabled {
+            let _ = Arc::make_mut(&mut self.policy_set).remove_static(policy.id().clone());
+        }
+        if !disabled {
+            if let Err(err) = Arc::make_mut(&mut self.policy_set).add(policy.clone()) {
+                error!("Failed to add policy {} to the policy set: {}", id, err);
+            }
+        }
+        self.policies.insert(id, policy);
     }
 
-    fn policy_set(&self) -> PolicySet {
-        self.1.clone()
+    fn remove(&mut self, id: &str) -> Option<cedar_policy::Policy> {
+        let policy = self.policies.remove(id)?;
+        self.metadata.remove(id);
+        if self.disabled.remove(id).is_none() {
+            let _ = Arc::make_mut(&mut self.policy_set).remove_static(PolicyId::new(id));
+        }
+        Some(policy)
     }
 
-    fn update_policy_set(&mut self) {
+    /// Replace all policies, rebuilding the policy set.
+    /// The kept policies that were disabled stay disabled, and keep their metadata unless
+    /// their content or details changed.
+    fn replace(
+        &mut self,
+        policies: HashMap<String, cedar_policy::Policy>,
+        mut metadata: HashMap<String, Metadata>,
+    ) {
+        for (id, metadata) in metadata.iter_mut() {
+            let (Some(stored), Some(stored_policy)) =
+                (self.metadata.get(id), self.policies.get(id))
+            else {
+                continue;
+            };
+            let unchanged = policies
+                .get(id)
+                .is_some_and(|policy| policy.to_string() == stored_policy.to_string());
+            if unchanged && stored.same_details(metadata) {
+                *metadata = stored.clone();
+            } else {
+                metadata.created_at = stored.created_at;
+            }
+        }
+        self.metadata = metadata;
+        self.disabled.retain(|id, _| policies.contains_key(id));
         let mut policy_set = PolicySet::new();
-        for policy in self.0.values() {
-            policy_set.add(policy.clone()).unwrap();
+        for policy in policies.values() {
+            if !self.disabled.contains_key(&policy.id().to_string()) {
+                policy_set.add(policy.clone()).unwrap();
+            }
+        }
+        self.policies = policies;
+        self.policy_set = Arc::new(policy_set);
+    }
+
+    /// Remove a policy from the policy set, until the given time if any.
+    fn disable(&mut self, id: &str, until: Option<SystemTime>) -> Option<cedar_policy::Policy> {
+        let policy = self.policies.get(id)?.clone();
+        if self.disabled.insert(id.to_owned(), until).is_none() {
+            let _ = Arc::make_mut(&mut self.policy_set).remove_static(PolicyId::new(id));
+        }
+        Some(policy)
+    }
+
+    /// Add a disabled policy back to the policy set.
+    fn enable(&mut self, id: &str) -> Option<cedar_policy::Policy> {
+        let policy = self.policies.get(id)?.clone();
+        if self.disabled.remove(id).is_some() {
+            if let Err(err) = Arc::make_mut(&mut self.policy_set).add(policy.clone()) {
+                error!("Failed to add policy {} to the policy set: {}", id, err);
+            }
+        }
+        Some(policy)
+    }
+
+    /// The stored policy along with whether it is enabled and its metadata.
+    fn describe(&self, policy: &cedar_policy::Policy) -> Policy {
+        let mut described = Policy::from(policy.clone());
+        if let Some(until) = self.disabled.get(described.id.as_str()) {
+            described.enabled = false;
+            described.disabled_until = until.map(format_time);
         }
-        self.1 = policy_set;
+        if let Some(metadata) = self.metadata.get(described.id.as_str()) {
+            described.description = metadata.description.clone();
+            described.owner = metadata.owner.clone();
+            described.tags = metadata.tags.clone();
+            described.created_at = Some(format_time(metadata.created_at));
+            described.updated_at = Some(format_time(metadata.updated_at));
+            described.claimed_author = metadata.claimed_author.clone();
+        }
+        described
+    }
+
+    fn states(&self) -> HashMap<String, PolicyState> {
+        self.policies
+            .iter()
+            .map(|(id, policy)| {
+                let metadata = self.metadata.get(id);
+                let state = PolicyState {
+                    content: policy.to_string(),
+                    description: metadata.and_then(|metadata| metadata.description.clone()),
+                    owner: metadata.and_then(|metadata| metadata.owner.clone()),
+                    tags: metadata
+                        .map(|metadata| metadata.tags.clone())
+                        .unwrap_or_default(),
+                    enabled: !self.disabled.contains_key(id),
+                };
+                (id.clone(), state)
+            })
+            .collect()
+    }
+
+    fn describe_all(&self) -> Vec<Policy> {
+        self.policies
+            .values()
+            .map(|policy| self.describe(policy))
+            .collect()
     }
 
     fn validate_policy(
@@ -68,17 +249,195 @@ impl Policies {
     }
 }
 
+/// Parsed policies by ID, along with their metadata.
+type ParsedPolicies = (
+    HashMap<String, cedar_policy::Policy>,
+    HashMap<String, Metadata>,
+);
+
+/// Parse and validate a whole set of policies, rejecting duplicate IDs.
+fn parse_policies(
+    policies: Vec<Policy>,
+    schema: &Option<Schema>,
+) -> Result<ParsedPolicies, StoreError> {
+    let mut parsed: HashMap<String, cedar_policy::Policy> = HashMap::new();
+    let mut metadata: HashMap<String, Metadata> = HashMap::new();
+    for policy in policies {
+        match parsed.get(&policy.id) {
+            Some(_) => {
+                return Err(StoreError::Duplicate {
+                    object: "Policy",
+                    id: policy.id,
+                })
+            }
+            None => {
+                metadata.insert(policy.id.clone(), Metadata::new(&policy));
+                let policy: cedar_policy::Policy = policy.borrow().try_into()?;
+                Policies::validate_policy(&policy, schema)?;
+
+                parsed.insert(policy.id().to_string(), policy)
+            }
+        };
+    }
+    Ok((parsed, metadata))
+}
+
+/// Changes from the stored policies to the new ones, ordered by ID.
+fn changes_between(
+    stored: &HashMap<String, PolicyState>,
+    new_policies: &HashMap<String, PolicyState>,
+) -> Vec<(String, Operation)> {
+    let mut changes: Vec<(String, Operation)> = Vec::new();
+    for (id, policy) in new_policies {
+        match stored.get(id) {
+            None => changes.push((id.clone(), Operation::Create)),
+            Some(stored) if stored != policy => changes.push((id.clone(), Operation::Update)),
+            Some(_) => {}
+        }
+    }
+    for id in stored.keys() {
+        if !new_policies.contains_key(id) {
+            changes.push((id.clone(), Operation::Delete));
+        }
+    }
+    changes.sort_by(|(left, _), (right, _)| left.cmp(right));
+    changes
+}
+
+fn publish(
+    policy_set: &ArcSwap<PolicySet>,
+    feed: &ChangeFeed,
+    gate: Option<&TestGate>,
+    policies: &Policies,
+    changes: Vec<(String, Operation)>,
+) {
+    policy_set.store(policies.policy_set.clone());
+    if let Some(gate) = gate {
+        gate.publish_policies(policies.policy_set.clone());
+    }
+    feed.publish(
+        changes
+            .into_iter()
+            .map(|(id, operation)| (ObjectType::Policy, id, operation)),
+    );
+}
+
 pub struct MemoryPolicyStore {
-    policies: RwLock<Policies>,
+    policies: Arc<RwLock<Policies>>,
+    /// Snapshot of the policy set, read by authorization requests without locking
+    policy_set: Arc<ArcSwap<PolicySet>>,
+    /// Policies evaluated alongside the active ones without affecting decisions
+    shadow: RwLock<Option<Policies>>,
+    shadow_set: ArcSwapOption<PolicySet>,
+    changes: Arc<ChangeFeed>,
+    gate: Option<Arc<TestGate>>,
 }
 
 impl MemoryPolicyStore {
     pub fn new() -> Self {
+        let policies = Policies::new();
         Self {
-            policies: RwLock::new(Policies::new()),
+            policy_set: Arc::new(ArcSwap::new(policies.policy_set.clone())),
+            policies: Arc::new(RwLock::new(policies)),
+            shadow: RwLock::new(None),
+            shadow_set: ArcSwapOption::empty(),
+            changes: Arc::new(ChangeFeed::new()),
+            gate: None,
+        }
+    }
+
+    /// Publish the changes of the store to the given feed.
+    pub fn with_changes(mut self, changes: Arc<ChangeFeed>) -> Self {
+        self.changes = changes;
+        self
+    }
+
+    /// Check every write against the policy test cases before publishing it.
+    pub fn with_gate(mut self, gate: Arc<TestGate>) -> Self {
+        gate.publish_policies(self.policy_set.load_full());
+        self.gate = Some(gate);
+        self
+    }
+
+    /// Publish the changes and the new policy set, while the policies are still locked.
+    fn publish(&self, policies: &Policies, changes: Vec<(String, Operation)>) {
+        publish(
+            &self.policy_set,
+            &self.changes,
+            self.gate.as_deref(),
+            policies,
+            changes,
+        );
+    }
+
+    /// Lock the gate, if any, until the checked write is published.
+    async fn lock_gate(&self) -> Option<MutexGuard<'_, ()>> {
+        match &self.gate {
+            Some(gate) => Some(gate.lock().await),
+            None => None,
         }
     }
 
+    /// Run a write on the locked policies and publish it along with its changes, if it
+    /// changed the policies and passes the gate.
+    ///
+    /// With a gate, the policies are copied before the write to restore them if it is
+    /// rejected. Writes check their input before changing anything, so a failing write
+    /// leaves the policies as they were.
+    async fn commit<T>(
+        &self,
+        policies: &mut Policies,
+        write: impl FnOnce(&mut Policies) -> Result<(T, Vec<(String, Operation)>), StoreError>,
+    ) -> Result<T, StoreError> {
+        let previous = self.gate.as_ref().map(|_| policies.clone());
+        let policy_set = policies.policy_set.clone();
+        let (result, changes) = write(policies)?;
+        if changes.is_empty() && Arc::ptr_eq(&policy_set, &policies.policy_set) {
+            return Ok(result);
+        }
+        let _gate = self.lock_gate().await;
+        if let (Some(gate), Some(previous)) = (&self.gate, previous) {
+            if let Err(err) = gate.check_policies(&policies.policy_set).await {
+                *policies = previous;
+                return Err(err);
+            }
+        }
+        self.publish(policies, changes);
+        Ok(result)
+    }
+
+    /// Enable the policy again once the time it was disabled until has come,
+    /// unless it was enabled or disabled again in the meantime.
+    fn schedule_enable(&self, id: String, until: SystemTime) {
+        let policies = self.policies.clone();
+        let policy_set = self.policy_set.clone();
+        let changes = self.changes.clone();
+        let gate = self.gate.clone();
+        rocket::tokio::spawn(async move {
+            if let Ok(wait) = until.duration_since(SystemTime::now()) {
+                rocket::tokio::time::sleep(wait).await;
+            }
+            let mut lock = policies.write().await;
+            if lock.disabled.get(&id) != Some(&Some(until)) {
+                return;
+            }
+            // Not checked, as the expiry is not a request that could be rejected
+            let _gate = match &gate {
+                Some(gate) => Some(gate.lock().await),
+                None => None,
+            };
+            info!("Enabling policy {} again", id);
+            lock.enable(&id);
+            publish(
+                &policy_set,
+                &changes,
+                gate.as_deref(),
+                &lock,
+                vec![(id, Operation::Update)],
+            );
+        });
+    }
+
     async fn read(&self) -> RwLockReadGuard<Policies> {
         debug!("Trying to acquire read lock on policies");
         self.policies.read().await
@@ -92,23 +451,41 @@ impl MemoryPolicyStore {
 
 #[async_trait]
 impl PolicyStore for MemoryPolicyStore {
-    async fn policy_set(&self) -> PolicySet {
-        let lock = self.read().await;
-        lock.policy_set()
+    async fn policy_set(&self) -> Arc<PolicySet> {
+        self.policy_set.load_full()
     }
 
     async fn get_policies(&self) -> Vec<Policy> {
         info!("Getting policies");
         let lock = self.read().await;
-        Vec::from_iter(lock.0.values().cloned().map(|p| Policy::from(p)))
+        lock.describe_all()
+    }
+
+    async fn query_policies(
+        &self,
+        query: &PolicyQuery,
+        entities: &Entities,
+    ) -> Result<Vec<Policy>, StoreError> {
+        info!("Querying policies");
+        let filter = PolicyFilter::new(query, entities)?;
+        let lock = self.read().await;
+        let mut policies: Vec<Policy> = lock
+            .policies
+            .values()
+            .filter(|policy| filter.matches(policy))
+            .map(|policy| lock.describe(policy))
+            .filter(|policy| filter.matches_details(policy))
+            .collect();
+        policies.sort_by(|left, right| left.id.cmp(&right.id));
+        Ok(policies)
     }
 
-    async fn get_policy(&self, id: &str) -> Result<Policy, Box<dyn Error>> {
+    async fn get_policy(&self, id: &str) -> Result<Policy, StoreError> {
         info!("Getting policy {}", id);
         let lock = self.read().await;
-        let policy = lock.0.get(id);
+        let policy = lock.policies.get(id);
         match policy {
-            Some(p) => Ok(Policy::from(p.clone())),
+            Some(p) => Ok(lock.describe(p)),
             None => Err(PolicyStoreError::PolicyNotFoundError(id.to_owned()).into()),
         }
     }
@@ -117,16 +494,17 @@ impl PolicyStore for MemoryPolicyStore {
         &self,
         policy: &Policy,
         schema: Option<Schema>,
-    ) -> Result<Policy, Box<dyn Error>> {
+    ) -> Result<Policy, StoreError> {
         info!("Creating policy {}", policy.id);
         let mut lock = self.write().await;
-        let stored_policy = lock.0.get(&policy.id);
+        let stored_policy = lock.policies.get(&policy.id);
         match stored_policy {
-            Some(_) => Err(Box::new(std::io::Error::new(
-                std::io::ErrorKind::AlreadyExists,
-                format!("Policy with id {} already exists", policy.id),
-            ))),
+            Some(_) => Err(StoreError::Duplicate {
+                object: "Policy",
+                id: policy.id.clone(),
+            }),
             None => {
+                let metadata = Metadata::new(policy);
                 let policy: cedar_policy::Policy = match policy.try_into() {
                     Ok(p) => p,
                     Err(err) => return Err(PolicyStoreError::PolicyParseError(err).into()),
@@ -134,11 +512,12 @@ impl PolicyStore for MemoryPolicyStore {
                 Policies::validate_policy(&policy, &schema)?;
 
                 let policy_id = policy.id().to_string();
-                lock.0.insert(policy_id.clone(), policy);
-                lock.update_policy_set();
-                Ok(Policy::from(
-                    lock.0.get(policy_id.as_str()).unwrap().clone(),
-                ))
+                self.commit(&mut lock, |policies| {
+                    policies.insert(policy, metadata);
+                    Ok(((), vec![(policy_id.clone(), Operation::Create)]))
+                })
+                .await?;
+                Ok(lock.describe(lock.policies.get(policy_id.as_str()).unwrap()))
             }
         }
     }
@@ -147,34 +526,17 @@ impl PolicyStore for MemoryPolicyStore {
         &self,
         policies: Vec<Policy>,
         schema: Option<Schema>,
-    ) -> Result<Vec<Policy>, Box<dyn Error>> {
+    ) -> Result<Vec<Policy>, StoreError> {
         info!("Updating policies");
         let mut lock = self.write().await;
-        let mut new_policies: HashMap<String, cedar_policy::Policy> = HashMap::new();
-        for policy in policies {
-            match new_policies.get(&policy.id) {
-                Some(_) => {
-                    return Err(Box::new(std::io::Error::new(
-                        std::io::ErrorKind::AlreadyExists,
-                        format!("Policy with id {} already exists", policy.id),
-                    )))
-                }
-                None => {
-                    let policy: cedar_policy::Policy = match policy.borrow().try_into() {
-                        Ok(p) => p,
-                        Err(err) => return Err(err.into()),
-                    };
-                    Policies::validate_policy(&policy, &schema)?;
-
-                    new_policies.insert(policy.id().to_string(), policy)
-                }
-            };
-        }
-        lock.0 = new_policies;
-        lock.update_policy_set();
-        Ok(Vec::from_iter(
-            lock.0.values().cloned().map(|p| Policy::from(p)),
-        ))
+        let (new_policies, metadata) = parse_policies(policies, &schema)?;
+        let stored = lock.states();
+        self.commit(&mut lock, |policies| {
+            policies.replace(new_policies, metadata);
+            Ok(((), changes_between(&stored, &policies.states())))
+        })
+        .await?;
+        Ok(lock.describe_all())
     }
 
     async fn update_policy(
@@ -182,33 +544,166 @@ impl PolicyStore for MemoryPolicyStore {
         id: String,
         policy_update: PolicyUpdate,
         schema: Option<Schema>,
-    ) -> Result<Policy, Box<dyn Error>> {
+    ) -> Result<Policy, StoreError> {
         info!("Updating policy {}", id);
         let mut lock = self.write().await;
+        let keep_description = policy_update.description.is_none();
+        let keep_owner = policy_update.owner.is_none();
+        let keep_tags = policy_update.tags.is_none();
         let policy = Policy::from_policy_update(id.clone(), policy_update);
+        let mut metadata = Metadata::new(&policy);
         let policy: cedar_policy::Policy = match policy.borrow().try_into() {
             Ok(p) => p,
             Err(err) => return Err(err.into()),
         };
         Policies::validate_policy(&policy, &schema)?;
 
-        *lock
-            .0
-            .entry(String::from(id))
-            .or_insert_with(|| policy.clone()) = policy.clone();
-        lock.update_policy_set();
-        Ok(Policy::from(policy))
+        if let Some(stored) = lock.metadata.get(&id) {
+            metadata.created_at = stored.created_at;
+            if keep_description {
+                metadata.description = stored.description.clone();
+            }
+            if keep_owner {
+                metadata.owner = stored.owner.clone();
+            }
+            if keep_tags {
+                metadata.tags = stored.tags.clone();
+            }
+            // Rewriting a policy as it is keeps its metadata and is not a change
+            let unchanged = lock
+                .policies
+                .get(&id)
+                .is_some_and(|stored| stored.to_string() == policy.to_string());
+            if unchanged && stored.same_details(&metadata) {
+                return Ok(lock.describe(&policy));
+            }
+        }
+
+        let operation = match lock.policies.contains_key(&id) {
+            true => Operation::Update,
+            false => Operation::Create,
+        };
+        self.commit(&mut lock, |policies| {
+            policies.insert(policy.clone(), metadata);
+            Ok(((), vec![(id, operation)]))
+        })
+        .await?;
+        Ok(lock.describe(&policy))
     }
 
-    async fn delete_policy(&self, id: &str) -> Result<Policy, Box<dyn Error>> {
+    async fn delete_policy(&self, id: &str) -> Result<Policy, StoreError> {
         info!("Deleting policy {}", id);
         let mut lock = self.write().await;
-        match lock.0.remove(id) {
-            Some(policy) => {
-                lock.update_policy_set();
-                Ok(Policy::from(policy))
-            }
-            None => Err(common::EmptyError.into()),
+        self.commit(&mut lock, |policies| match policies.remove(id) {
+            Some(policy) => Ok((
+                Policy::from(policy),
+                vec![(id.to_owned(), Operation::Delete)],
+            )),
+            None => Err(PolicyStoreError::PolicyNotFoundError(id.to_owned()).into()),
+        })
+        .await
+    }
+
+    async fn shadow_policy_set(&self) -> Option<Arc<PolicySet>> {
+        self.shadow_set.load_full()
+    }
+
+    async fn get_shadow_policies(&self) -> Option<Vec<Policy>> {
+        info!("Getting shadow policies");
+        let lock = self.shadow.read().await;
+        lock.as_ref().map(|shadow| shadow.describe_all())
+    }
+
+    async fn update_shadow_policies(
+        &self,
+        policies: Vec<Policy>,
+        schema: Option<Schema>,
+    ) -> Result<Vec<Policy>, StoreError> {
+        info!("Updating shadow policies");
+        let (new_policies, metadata) = parse_policies(policies, &schema)?;
+        let mut shadow = Policies::new();
+        shadow.replace(new_policies, metadata);
+        let mut lock = self.shadow.write().await;
+        self.shadow_set.store(Some(shadow.policy_set.clone()));
+        let updated = shadow.describe_all();
+        *lock = Some(shadow);
+        Ok(updated)
+    }
+
+    async fn delete_shadow_policies(&self) -> Option<Vec<Policy>> {
+        info!("Deleting shadow policies");
+        let mut lock = self.shadow.write().await;
+        self.shadow_set.store(None);
+        lock.take().map(|shadow| shadow.describe_all())
+    }
+
+    async fn promote_shadow_policies(&self) -> Result<Option<Vec<Policy>>, StoreError> {
+        info!("Promoting shadow policies");
+        let mut lock = self.write().await;
+        let mut shadow_lock = self.shadow.write().await;
+        let Some(shadow) = shadow_lock.as_ref() else {
+            return Ok(None);
+        };
+        // The shadow policies are kept if the gate rejects them
+        let (shadow_policies, metadata) = (shadow.policies.clone(), shadow.metadata.clone());
+        let stored = lock.states();
+        self.commit(&mut lock, |policies| {
+            policies.replace(shadow_policies, metadata);
+            Ok(((), changes_between(&stored, &policies.states())))
+        })
+        .await?;
+        *shadow_lock = None;
+        self.shadow_set.store(None);
+        Ok(Some(lock.describe_all()))
+    }
+
+    async fn enable_policy(&self, id: &str) -> Result<Policy, StoreError> {
+        info!("Enabling policy {}", id);
+        let mut lock = self.write().await;
+        let policy = self
+            .commit(&mut lock, |policies| {
+                let disabled = policies.disabled.contains_key(id);
+                match policies.enable(id) {
+                    Some(policy) => Ok((
+                        policy,
+                        match disabled {
+                            true => vec![(id.to_owned(), Operation::Update)],
+                            false => Vec::new(),
+                        },
+                    )),
+                    None => Err(PolicyStoreError::PolicyNotFoundError(id.to_owned()).into()),
+                }
+            })
+            .await?;
+        Ok(lock.describe(&policy))
+    }
+
+    async fn disable_policy(
+        &self,
+        id: &str,
+        duration: Option<Duration>,
+    ) -> Result<Policy, StoreError> {
+        info!("Disabling policy {}", id);
+        let until = duration.map(|duration| SystemTime::now() + duration);
+        let mut lock = self.write().await;
+        let policy = self
+            .commit(&mut lock, |policies| {
+                let enabled = !policies.disabled.contains_key(id);
+                match policies.disable(id, until) {
+                    Some(policy) => Ok((
+                        policy,
+                        match enabled {
+                            true => vec![(id.to_owned(), Operation::Update)],
+                            false => Vec::new(),
+                        },
+                    )),
+                    None => Err(PolicyStoreError::PolicyNotFoundError(id.to_owned()).into()),
+                }
+            })
+            .await?;
+        if let Some(until) = until {
+            self.schedule_enable(id.to_owned(), until);
         }
+        Ok(lock.describe(&policy))
     }
 }
@@ -1,34 +1,68 @@
-use std::error::Error;
+use std::sync::Arc;
+use std::time::Duration;
 
 use async_trait::async_trait;
-use cedar_policy::{PolicySet, Schema};
+use cedar_policy::{Entities, PolicySet, Schema};
 
-use crate::schemas::policies::{Policy, PolicyUpdate};
+use crate::schemas::policies::{Policy, PolicyQuery, PolicyUpdate};
+use crate::services::errors::StoreError;
 
+pub mod analysis;
 pub(crate) mod errors;
 pub mod load_from_file;
 pub mod memory;
+pub mod query;
 
 #[async_trait]
 pub trait PolicyStore: Send + Sync {
-    async fn policy_set(&self) -> PolicySet;
+    /// Snapshot of the policy set, which later writes leave unchanged.
+    async fn policy_set(&self) -> Arc<PolicySet>;
     async fn get_policies(&self) -> Vec<Policy>;
-    async fn get_policy(&self, id: &str) -> Result<Policy, Box<dyn Error>>;
+    /// Get the policies matching the query ordered by ID, resolving scopes against the entities.
+    async fn query_policies(
+        &self,
+        query: &PolicyQuery,
+        entities: &Entities,
+    ) -> Result<Vec<Policy>, StoreError>;
+    async fn get_policy(&self, id: &str) -> Result<Policy, StoreError>;
     async fn create_policy(
         &self,
         policy: &Policy,
         schema: Option<Schema>,
-    ) -> Result<Policy, Box<dyn Error>>;
+    ) -> Result<Policy, StoreError>;
     async fn update_policies(
         &self,
         policies: Vec<Policy>,
         schema: Option<Schema>,
-    ) -> Result<Vec<Policy>, Box<dyn Error>>;
+    ) -> Result<Vec<Policy>, StoreError>;
     async fn update_policy(
         &self,
         id: String,
         policy: PolicyUpdate,
         schema: Option<Schema>,
-    ) -> Result<Policy, Box<dyn Error>>;
-    async fn delete_policy(&self, id: &str) -> Result<Policy, Box<dyn Error>>;
+    ) -> Result<Policy, StoreError>;
+    async fn delete_policy(&self, id: &str) -> Result<Policy, StoreError>;
+    /// Add a disabled policy back to the policy set used for authorization.
+    async fn enable_policy(&self, id: &str) -> Result<Policy, StoreError>;
+    /// Keep a policy out of the policy set used for authorization, while still storing and
+    /// validating it. With a duration, the policy is enabled again once it elapses.
+    async fn disable_policy(
+        &self,
+        id: &str,
+        duration: Option<Duration>,
+    ) -> Result<Policy, StoreError>;
+    /// Snapshot of the shadow policy set, if there is one.
+    async fn shadow_policy_set(&self) -> Option<Arc<PolicySet>>;
+    async fn get_shadow_policies(&self) -> Option<Vec<Policy>>;
+    /// Replace the shadow policy set, validating the policies like `update_policies`.
+    async fn update_shadow_policies(
+        &self,
+        policies: Vec<Policy>,
+        schema: Option<Schema>,
+    ) -> Result<Vec<Policy>, StoreError>;
+    /// Remove the shadow policy set, returning its policies.
+    async fn delete_shadow_policies(&self) -> Option<Vec<Policy>>;
+    /// Replace the active policies with the shadow ones, removing the shadow policy set.
+    /// The gate, if any, can reject the promotion, leaving the shadow policy set in place.
+    async fn promote_shadow_policies(&self) -> Result<Option<Vec<Policy>>, StoreError>;
 }
@@ -0,0 +1,93 @@
+use std::str::FromStr;
+
+use cedar_policy::{Effect, Entities, EntityUid, Policy};
+
+use crate::schemas::authorization::EffectRef;
+use crate::schemas::policies::{self, PolicyQuery};
+use crate::services::authorization::explain::{check_action, check_principal, check_resource};
+use crate::services::errors::StoreError;
+
+/// Matches policies against the filters of a policy query.
+///
+/// Scope filters match the policies that may apply to the entity, following the entity
+/// hierarchy for `in` constraints, so `Action::"SELECT"` also matches policies on its action groups.
+pub struct PolicyFilter<'a> {
+    effect: Option<EffectRef>,
+    principal: Option<EntityUid>,
+    action: Option<EntityUid>,
+    resource: Option<EntityUid>,
+    annotations: Vec<(String, Option<String>)>,
+    owner: Option<String>,
+    tags: Vec<String>,
+    /// Lowercase text searched for in the ID and description
+    search: Option<String>,
+    entities: &'a Entities,
+}
+
+fn parse_uid(uid: &Option<String>) -> Result<Option<EntityUid>, StoreError> {
+    uid.as_deref()
+        .map(EntityUid::from_str)
+        .transpose()
+        .map_err(StoreError::bad_request)
+}
+
+impl<'a> PolicyFilter<'a> {
+    pub fn new(query: &PolicyQuery, entities: &'a Entities) -> Result<Self, StoreError> {
+        Ok(Self {
+            effect: query.effect,
+            principal: parse_uid(&query.principal)?,
+            action: parse_uid(&query.action)?,
+            resource: parse_uid(&query.resource)?,
+            annotations: query
+                .annotation
+                .iter()
+                .map(|annotation| match annotation.split_once(':') {
+                    Some((key, value)) => (key.to_owned(), Some(value.to_owned())),
+                    None => (annotation.clone(), None),
+                })
+                .collect(),
+            owner: query.owner.clone(),
+            tags: query.tag.clone(),
+            search: query.search.as_ref().map(|search| search.to_lowercase()),
+            entities,
+        })
+    }
+
+    pub fn matches(&self, policy: &Policy) -> bool {
+        let effect = match policy.effect() {
+            Effect::Permit => EffectRef::Permit,
+            Effect::Forbid => EffectRef::Forbid,
+        };
+        self.effect.is_none_or(|expected| expected == effect)
+            && self.principal.as_ref().is_none_or(|uid| {
+                check_principal(policy.principal_constraint(), uid, self.entities).is_ok()
+            })
+            && self.action.as_ref().is_none_or(|uid| {
+                check_action(policy.action_constraint(), uid, self.entities).is_ok()
+            })
+            && self.resource.as_ref().is_none_or(|uid| {
+                check_resource(policy.resource_constraint(), uid, self.entities).is_ok()
+            })
+            && self.annotations.iter().all(|(key, expected)| {
+                match (policy.annotation(key), expected) {
+                    (Some(value), Some(expected)) => value == expected,
+                    (Some(_), None) => true,
+                    (None, _) => false,
+                }
+            })
+    }
+    /// Match the details of a stored policy, given by clients.
+    pub fn matches_details(&self, policy: &policies::Policy) -> bool {
+        self.owner
+            .as_ref()
+            .is_none_or(|owner| policy.owner.as_ref() == Some(owner))
+            && self.tags.iter().all(|tag| policy.tags.contains(tag))
+            && self.search.as_ref().is_none_or(|search| {
+                policy.id.to_lowercase().contains(search)
+                    || policy
+                        .description
+                        .as_ref()
+                        .is_some_and(|description| description.to_lowercase().contains(search))
+            })
+    }
+}
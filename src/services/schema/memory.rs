@@ -1,13 +1,20 @@
+use std::sync::Arc;
+
 use async_lock::{RwLock, RwLockReadGuard, RwLockWriteGuard};
 use async_trait::async_trait;
 use cedar_policy::Schema as CedarSchema;
-use cedar_policy::SchemaError;
 use log::{debug, error, info};
 use rocket::serde::json::serde_json;
 
+use crate::schemas::changes::{ObjectType, Operation};
 use crate::schemas::schema::Schema as InternalSchema;
+use crate::services::changes::ChangeFeed;
+use crate::services::errors::StoreError;
 use crate::services::schema::SchemaStore;
 
+/// ID of the schema in change events, as there is a single schema
+const SCHEMA_ID: &str = "schema";
+
 pub struct Schema(CedarSchema, InternalSchema);
 
 impl Schema {
@@ -42,15 +49,28 @@ impl Schema {
 
 pub struct MemorySchemaStore {
     schema: RwLock<Schema>,
+    changes: Arc<ChangeFeed>,
 }
 
 impl MemorySchemaStore {
     pub fn new() -> Self {
         Self {
             schema: RwLock::new(Schema::empty()),
+            changes: Arc::new(ChangeFeed::new()),
         }
     }
 
+    /// Publish the changes of the store to the given feed.
+    pub fn with_changes(mut self, changes: Arc<ChangeFeed>) -> Self {
+        self.changes = changes;
+        self
+    }
+
+    fn publish(&self, operation: Operation) {
+        self.changes
+            .publish([(ObjectType::Schema, SCHEMA_ID.to_owned(), operation)]);
+    }
+
     async fn read(&self) -> RwLockReadGuard<Schema> {
         debug!("Trying to acquire read lock on the schema");
         self.schema.read().await
@@ -60,6 +80,30 @@ impl MemorySchemaStore {
         debug!("Trying to acquire write lock on the schema");
         self.schema.write().await
     }
+
+    fn store(
+        &self,
+        lock: &mut Schema,
+        schema: InternalSchema,
+    ) -> Result<InternalSchema, StoreError> {
+        let internal_schema: InternalSchema = schema.clone();
+        let cedar_schema: CedarSchema = match schema.try_into() {
+            Ok(schema) => schema,
+            Err(err) => {
+                error!("Failed to parse schema");
+                return Err(err.into());
+            }
+        };
+        let operation = match lock.1.is_empty() {
+            true => Operation::Create,
+            false => Operation::Update,
+        };
+        if lock.1.get() != internal_schema.get() {
+            self.publish(operation);
+        }
+        *lock = Schema::new(cedar_schema, internal_schema.clone());
+        Ok(internal_schema)
+    }
 }
 
 #[async_trait]
@@ -79,24 +123,31 @@ impl SchemaStore for MemorySchemaStore {
         lock.internal_schema()
     }
 
-    async fn update_schema(&self, schema: InternalSchema) -> Result<InternalSchema, SchemaError> {
+    async fn update_schema(&self, schema: InternalSchema) -> Result<InternalSchema, StoreError> {
         info!("Updating stored schema");
         let mut lock = self.write().await;
-        let internal_schema: InternalSchema = schema.clone();
-        let cedar_schema: CedarSchema = match schema.try_into() {
-            Ok(schema) => schema,
-            Err(err) => {
-                error!("Failed to parse schema");
-                return Err(err);
-            }
-        };
-        *lock = Schema::new(cedar_schema, internal_schema.clone());
-        Ok(internal_schema)
+        self.store(&mut lock, schema)
+    }
+
+    async fn swap_schema(
+        &self,
+        current: &InternalSchema,
+        schema: InternalSchema,
+    ) -> Result<InternalSchema, StoreError> {
+        info!("Swapping stored schema");
+        let mut lock = self.write().await;
+        if lock.1.get() != current.get() {
+            return Err(StoreError::conflict("The schema was changed by another request"));
+        }
+        self.store(&mut lock, schema)
     }
 
     async fn delete_schema(&self) {
         info!("Deleting stored schema");
         let mut lock = self.write().await;
+        if !lock.1.is_empty() {
+            self.publish(Operation::Delete);
+        }
         *lock = Schema::empty();
     }
 }
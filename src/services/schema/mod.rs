@@ -1,8 +1,8 @@
 use async_trait::async_trait;
 use cedar_policy::Schema as CedarSchema;
-use cedar_policy::SchemaError;
 
 use crate::schemas::schema::Schema as InternalSchema;
+use crate::services::errors::StoreError;
 
 pub mod load_from_file;
 pub mod memory;
@@ -12,6 +12,13 @@ pub trait SchemaStore: Send + Sync {
     async fn get_cedar_schema(&self) -> Option<CedarSchema>;
 
     async fn get_internal_schema(&self) -> InternalSchema;
-    async fn update_schema(&self, schema: InternalSchema) -> Result<InternalSchema, SchemaError>;
+    async fn update_schema(&self, schema: InternalSchema) -> Result<InternalSchema, StoreError>;
+    /// Store a schema only if the stored one is still `current`, so edits derived from
+    /// `current` can't overwrite a concurrent update.
+    async fn swap_schema(
+        &self,
+        current: &InternalSchema,
+        schema: InternalSchema,
+    ) -> Result<InternalSchema, StoreError>;
     async fn delete_schema(&self);
 }
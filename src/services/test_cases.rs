@@ -0,0 +1,233 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::error::Error;
+use std::sync::Arc;
+
+use arc_swap::ArcSwap;
+use async_lock::{Mutex, MutexGuard, RwLock};
+use cedar_policy::{Authorizer, PolicySet, Response};
+use log::{debug, info, warn};
+
+use crate::schemas::authorization::{AuthorizationRequest, DecisionRef};
+use crate::schemas::test_cases::{TestCase, TestCaseResult, TestRunReport};
+use crate::services::data::EntitySnapshot;
+use crate::services::errors::StoreError;
+
+fn decide(
+    case: &TestCase,
+    authorizer: &Authorizer,
+    policies: &PolicySet,
+    entities: &EntitySnapshot,
+) -> Result<Response, Box<dyn Error>> {
+    let request: AuthorizationRequest = case.request.clone().try_into()?;
+    let (request, entities) = request.get_request_entities(entities.clone())?;
+    Ok(authorizer.is_authorized(&request, policies, &entities))
+}
+
+fn evaluate(
+    case: &TestCase,
+    authorizer: &Authorizer,
+    policies: &PolicySet,
+    entities: &EntitySnapshot,
+) -> TestCaseResult {
+    match decide(case, authorizer, policies, entities) {
+        Ok(response) => {
+            let decision = DecisionRef::from(response.decision());
+            let reasons: BTreeSet<String> = response
+                .diagnostics()
+                .reason()
+                .map(|id| id.to_string())
+                .collect();
+            let passed = decision == case.expected_decision
+                && case
+                    .expected_reasons
+                    .as_ref()
+                    .is_none_or(|expected| *expected == reasons);
+            TestCaseResult {
+                id: case.id.clone(),
+                passed,
+                decision: Some(decision),
+                reasons,
+                error: None,
+            }
+        }
+        Err(err) => TestCaseResult {
+            id: case.id.clone(),
+            passed: false,
+            decision: None,
+            reasons: BTreeSet::new(),
+            error: Some(err.to_string()),
+        },
+    }
+}
+
+/// Stored authorization requests along with their expected decisions.
+pub struct TestCaseStore {
+    cases: RwLock<BTreeMap<String, TestCase>>,
+}
+
+impl TestCaseStore {
+    pub fn new() -> Self {
+        Self {
+            cases: RwLock::new(BTreeMap::new()),
+        }
+    }
+
+    pub async fn get_test_cases(&self) -> Vec<TestCase> {
+        self.cases.read().await.values().cloned().collect()
+    }
+
+    pub async fn get_test_case(&self, id: &str) -> Option<TestCase> {
+        self.cases.read().await.get(id).cloned()
+    }
+
+    /// Store the test case under the given ID, replacing any previous one.
+    pub async fn put_test_case(
+        &self,
+        id: String,
+        mut case: TestCase,
+    ) -> Result<TestCase, Box<dyn Error>> {
+        // Reject requests that could never be evaluated
+        let _: AuthorizationRequest = case.request.clone().try_into()?;
+        case.id = id;
+        info!("Storing test case {}", case.id);
+        self.cases
+            .write()
+            .await
+            .insert(case.id.clone(), case.clone());
+        Ok(case)
+    }
+
+    pub async fn delete_test_case(&self, id: &str) -> Option<TestCase> {
+        info!("Deleting test case {}", id);
+        self.cases.write().await.remove(id)
+    }
+
+    /// Run every test case against the policies and entities.
+    pub async fn run(
+        &self,
+        authorizer: &Authorizer,
+        policies: &PolicySet,
+        entities: EntitySnapshot,
+    ) -> TestRunReport {
+        let cases = self.get_test_cases().await;
+        let results: Vec<TestCaseResult> = cases
+            .iter()
+            .map(|case| evaluate(case, authorizer, policies, &entities))
+            .collect();
+        let passed = results.iter().filter(|result| result.passed).count();
+        TestRunReport {
+            total: results.len(),
+            passed,
+            failed: results.len() - passed,
+            results,
+        }
+    }
+
+    /// IDs of the test cases that pass with the current policies and entities but fail
+    /// with the candidate ones.
+    pub async fn regressions(
+        &self,
+        authorizer: &Authorizer,
+        current: (&PolicySet, EntitySnapshot),
+        candidate: (&PolicySet, EntitySnapshot),
+    ) -> Vec<String> {
+        let cases = self.get_test_cases().await;
+        let regressions: Vec<String> = cases
+            .iter()
+            .filter(|case| {
+                evaluate(case, authorizer, current.0, &current.1).passed
+                    && !evaluate(case, authorizer, candidate.0, &candidate.1).passed
+            })
+            .map(|case| case.id.clone())
+            .collect();
+        debug!(
+            "{} of {} test cases regress with the change",
+            regressions.len(),
+            cases.len()
+        );
+        regressions
+    }
+}
+
+impl Default for TestCaseStore {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Rejects the changes of the policies or entities that make a passing test case fail.
+///
+/// The stores run the gate while they hold their write lock, on the result of a write
+/// before publishing it, so no other write of the same store comes in between. Each store
+/// also hands the gate what it publishes, so a change of one store is checked against
+/// what the other one currently publishes. The stores hold the lock of the gate from the
+/// check to the publishing, so a change of the other store does not come in between either.
+pub struct TestGate {
+    lock: Mutex<()>,
+    test_cases: Arc<TestCaseStore>,
+    authorizer: Authorizer,
+    policies: ArcSwap<PolicySet>,
+    entities: ArcSwap<EntitySnapshot>,
+}
+
+impl TestGate {
+    pub fn new(test_cases: Arc<TestCaseStore>) -> Self {
+        Self {
+            lock: Mutex::new(()),
+            test_cases,
+            authorizer: Authorizer::new(),
+            policies: ArcSwap::from_pointee(PolicySet::new()),
+            entities: ArcSwap::from_pointee(EntitySnapshot::empty()),
+        }
+    }
+
+    /// Run the gated writes one at a time, whichever store they are made to.
+    pub async fn lock(&self) -> MutexGuard<'_, ()> {
+        self.lock.lock().await
+    }
+
+    /// Record the policy set published by the policy store.
+    pub fn publish_policies(&self, policies: Arc<PolicySet>) {
+        self.policies.store(policies);
+    }
+
+    /// Record the entities published by the data store.
+    pub fn publish_entities(&self, entities: EntitySnapshot) {
+        self.entities.store(Arc::new(entities));
+    }
+
+    /// Check a policy set about to be published against the published entities.
+    pub async fn check_policies(&self, candidate: &PolicySet) -> Result<(), StoreError> {
+        let current = self.policies.load_full();
+        let entities = EntitySnapshot::clone(&self.entities.load());
+        self.check((&current, entities.clone()), (candidate, entities))
+            .await
+    }
+
+    /// Check entities about to be published against the published policy set.
+    pub async fn check_entities(&self, candidate: &EntitySnapshot) -> Result<(), StoreError> {
+        let policies = self.policies.load_full();
+        let current = EntitySnapshot::clone(&self.entities.load());
+        self.check((&policies, current), (&policies, candidate.clone()))
+            .await
+    }
+
+    async fn check(
+        &self,
+        current: (&PolicySet, EntitySnapshot),
+        candidate: (&PolicySet, EntitySnapshot),
+    ) -> Result<(), StoreError> {
+        let regressions = self
+            .test_cases
+            .regressions(&self.authorizer, current, candidate)
+            .await;
+        if regressions.is_empty() {
+            return Ok(());
+        }
+        warn!("Rejecting change failing the test cases {:?}", regressions);
+        Err(StoreError::conflict(format!(
+            "The change makes the policy test cases {} fail",
+            regressions.join(", ")
+        )))
+    }
+}
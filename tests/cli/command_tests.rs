@@ -0,0 +1,170 @@
+use std::fs;
+
+use rocket::serde::json::serde_json::{self, json, Value};
+
+use crate::cli::utils::*;
+
+const STORES: [&str; 4] = [
+    "--data",
+    "examples/data.json",
+    "--policies",
+    "examples/policies.json",
+];
+const REQUEST: &str = r#"{ "principal": "User::\"admin.1@domain.com\"", "action": "Action::\"get\"", "resource": "Document::\"cedar-agent.pdf\"" }"#;
+
+/// Authorize the requests of a file against the example stores
+fn authorize(path: &std::path::Path) -> std::process::Output {
+    run_agent(
+        &[&STORES[..], &["authorize", path.to_str().unwrap()]].concat(),
+        &[],
+    )
+}
+
+/// Test the exit code of validate with valid, invalid and missing files
+#[test]
+fn test_validate_exit_code() {
+    let output = run_agent(
+        &[
+            "--schema",
+            "examples/schema.json",
+            "--data",
+            "examples/data.json",
+            "--policies",
+            "examples/policies.json",
+            "validate",
+        ],
+        &[],
+    );
+    assert!(output.status.success(), "{}", stderr(&output));
+
+    let path = write_file(
+        "validate_invalid_policies.json",
+        r#"[{ "id": "invalid", "content": "permit(principal," }]"#,
+    );
+    let output = run_agent(&["--policies", path.to_str().unwrap(), "validate"], &[]);
+    fs::remove_file(&path).unwrap();
+    assert!(!output.status.success());
+    assert!(stderr(&output).contains("Policies"));
+
+    let output = run_agent(&["validate"], &[]);
+    assert!(!output.status.success());
+    assert!(stderr(&output).contains("Nothing to validate"));
+}
+
+/// Test that authorize fails when a request can not be answered
+#[test]
+fn test_authorize_exit_code() {
+    let path = write_file("authorize_requests.jsonl", REQUEST);
+    let output = authorize(&path);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(stdout(&output).contains(r#""decision":"Allow""#));
+
+    fs::write(
+        &path,
+        r#"{ "principal": "User", "action": "Action::\"get\"" }"#,
+    )
+    .unwrap();
+    let output = authorize(&path);
+    fs::remove_file(&path).unwrap();
+    assert!(!output.status.success());
+    assert!(stdout(&output).contains(r#""error""#));
+}
+
+/// Test reading requests from a JSON file of a request or an array, and from a JSONL file
+#[test]
+fn test_read_requests() {
+    let path = write_file("read_requests.json", REQUEST);
+    assert_eq!(stdout(&authorize(&path)).lines().count(), 1);
+    fs::write(&path, format!("[{}, {}]", REQUEST, REQUEST)).unwrap();
+    assert_eq!(stdout(&authorize(&path)).lines().count(), 2);
+    // JSON files hold a single document
+    fs::write(&path, format!("{}\n{}\n", REQUEST, REQUEST)).unwrap();
+    assert!(!authorize(&path).status.success());
+    fs::remove_file(&path).unwrap();
+
+    let path = write_file(
+        "read_requests.jsonl",
+        &format!("{}\n\n{}\n", REQUEST, REQUEST),
+    );
+    assert_eq!(stdout(&authorize(&path)).lines().count(), 2);
+    fs::write(&path, format!("{}\n[{}]\n", REQUEST, REQUEST)).unwrap();
+    let output = authorize(&path);
+    fs::remove_file(&path).unwrap();
+    assert!(!output.status.success());
+    assert!(stderr(&output).contains("Invalid request on line 2"));
+}
+
+/// Test that format --check reports the policies that are not in the canonical format
+#[test]
+fn test_format_check() {
+    let path = write_file(
+        "format_check.json",
+        r#"[
+            { "id": "formatted", "content": "permit (principal, action, resource);" },
+            { "id": "unformatted", "content": "permit(principal,action,resource);" }
+        ]"#,
+    );
+    let check = run_agent(
+        &["--policies", path.to_str().unwrap(), "format", "--check"],
+        &[],
+    );
+    let format = run_agent(&["--policies", path.to_str().unwrap(), "format"], &[]);
+    fs::remove_file(&path).unwrap();
+
+    assert!(!check.status.success());
+    assert_eq!(
+        stderr(&check).trim_end(),
+        "Policies not in the canonical format: unformatted"
+    );
+    assert!(format.status.success());
+}
+
+/// Test that format only changes the content of the policies, and that its output is
+/// in the canonical format
+#[test]
+fn test_format_keeps_metadata() {
+    let path = write_file(
+        "format_metadata.json",
+        r#"[{
+            "id": "owned",
+            "content": "@reason(\"default\")\npermit(principal,action,resource);",
+            "description": "Allow everything",
+            "owner": "security",
+            "tags": ["default"],
+            "created_at": "2024-05-01T12:00:00Z"
+        }]"#,
+    );
+    let output = run_agent(&["--policies", path.to_str().unwrap(), "format"], &[]);
+    fs::remove_file(&path).unwrap();
+    assert!(output.status.success(), "{}", stderr(&output));
+
+    let printed: Value = serde_json::from_str(&stdout(&output)).unwrap();
+    let keys: Vec<&String> = printed[0].as_object().unwrap().keys().collect();
+    assert_eq!(
+        keys,
+        [
+            "id",
+            "content",
+            "description",
+            "owner",
+            "tags",
+            "created_at"
+        ]
+    );
+    assert_eq!(
+        printed[0]["content"],
+        json!("@reason(\"default\")\npermit (principal, action, resource);")
+    );
+    assert_eq!(printed[0]["description"], json!("Allow everything"));
+    assert_eq!(printed[0]["owner"], json!("security"));
+    assert_eq!(printed[0]["tags"], json!(["default"]));
+    assert_eq!(printed[0]["created_at"], json!("2024-05-01T12:00:00Z"));
+
+    let path = write_file("format_metadata_formatted.json", &stdout(&output));
+    let check = run_agent(
+        &["--policies", path.to_str().unwrap(), "format", "--check"],
+        &[],
+    );
+    fs::remove_file(&path).unwrap();
+    assert!(check.status.success(), "{}", stderr(&check));
+}
@@ -0,0 +1,157 @@
+use std::fs;
+
+use crate::cli::utils::*;
+
+/// Test loading every section of a TOML config file
+#[test]
+fn test_load_toml_file() {
+    let path = write_file(
+        "load.toml",
+        r#"
+        [server]
+        port = 8280
+
+        [server.cors]
+        allowed_origins = ["http://localhost:3000"]
+
+        [logging]
+        level = "debug"
+        format = "json"
+
+        [loaders]
+        policies = "policies.json"
+        "#,
+    );
+    let printed = print_config(&["--config", path.to_str().unwrap()], &[]);
+    fs::remove_file(&path).unwrap();
+
+    assert!(printed.contains("port = 8280"));
+    assert!(printed.contains(r#"allowed_origins = ["http://localhost:3000"]"#));
+    assert!(printed.contains(r#"level = "DEBUG""#));
+    assert!(printed.contains(r#"format = "json""#));
+    assert!(printed.contains(r#"policies = "policies.json""#));
+    assert!(!printed.contains("data ="));
+}
+
+/// Test loading a YAML config file
+#[test]
+fn test_load_yaml_file() {
+    let path = write_file(
+        "load.yaml",
+        "server:\n  port: 8280\n  cors:\n    disabled: true\ndecision_cache:\n  size: 100\n",
+    );
+    let printed = print_config(&["--config", path.to_str().unwrap()], &[]);
+    fs::remove_file(&path).unwrap();
+
+    assert!(printed.contains("port = 8280"));
+    assert!(printed.contains("disabled = true"));
+    assert!(printed.contains("size = 100"));
+}
+
+/// Test that invalid, unsupported and missing config files fail the startup
+#[test]
+fn test_load_invalid_file() {
+    let path = write_file("unknown.toml", "[server]\nunknown = 1\n");
+    let output = run_agent(&["--config", path.to_str().unwrap(), "--print-config"], &[]);
+    fs::remove_file(&path).unwrap();
+    assert!(!output.status.success());
+    assert!(stderr(&output).contains("Failed to parse TOML config file"));
+
+    let path = write_file("config.json", "{}");
+    let output = run_agent(&["--config", path.to_str().unwrap(), "--print-config"], &[]);
+    fs::remove_file(&path).unwrap();
+    assert!(!output.status.success());
+    assert!(stderr(&output).contains("Unsupported config file"));
+
+    let output = run_agent(&["--config", "missing.toml", "--print-config"], &[]);
+    assert!(!output.status.success());
+    assert!(stderr(&output).contains("Failed to read config file"));
+}
+
+/// Test boolean settings given as command line flags, with or without a value
+#[test]
+fn test_boolean_flags() {
+    let printed = print_config(&["--cors-disabled", "--policy-tests-gate"], &[]);
+    assert!(printed.contains("disabled = true"));
+    assert!(printed.contains("gate = true"));
+
+    let printed = print_config(
+        &["--cors-disabled=false", "--cors-allow-credentials=false"],
+        &[
+            ("CEDAR_AGENT_CORS_DISABLED", "true"),
+            ("CEDAR_AGENT_CORS_ALLOW_CREDENTIALS", "true"),
+        ],
+    );
+    assert!(printed.contains("disabled = false"));
+    assert!(printed.contains("allow_credentials = false"));
+
+    let printed = print_config(
+        &["--policy-tests-gate=false"],
+        &[("CEDAR_AGENT_POLICY_TESTS_GATE", "true")],
+    );
+    assert!(printed.contains("gate = false"));
+}
+
+/// Test that the environment overrides the config file and the command line overrides both
+#[test]
+fn test_precedence() {
+    let path = write_file(
+        "precedence.toml",
+        "[server]\naddr = \"0.0.0.0\"\nport = 1\n\n[logging]\nlevel = \"warn\"\nformat = \"json\"\n",
+    );
+    let printed = print_config(
+        &["--log-level", "debug"],
+        &[
+            ("CEDAR_AGENT_CONFIG", path.to_str().unwrap()),
+            ("CEDAR_AGENT_PORT", "2"),
+            ("CEDAR_AGENT_LOG_LEVEL", "error"),
+        ],
+    );
+    fs::remove_file(&path).unwrap();
+
+    assert!(printed.contains(r#"addr = "0.0.0.0""#));
+    assert!(printed.contains(r#"format = "json""#));
+    assert!(printed.contains("port = 2"));
+    assert!(printed.contains(r#"level = "DEBUG""#));
+}
+
+/// Test that a config file given on the command line replaces the one in the environment
+#[test]
+fn test_cli_config_file() {
+    let env_path = write_file("env.toml", "[server]\nport = 1\n");
+    let args_path = write_file("args.toml", "[server]\nport = 2\n");
+    let printed = print_config(
+        &["--config", args_path.to_str().unwrap()],
+        &[("CEDAR_AGENT_CONFIG", env_path.to_str().unwrap())],
+    );
+    fs::remove_file(&env_path).unwrap();
+    fs::remove_file(&args_path).unwrap();
+
+    assert!(printed.contains("port = 2"));
+}
+
+/// Test that the config file and print switch are only read from prefixed variables
+#[test]
+fn test_unprefixed_env() {
+    let output = run_agent(
+        &["--policies", "examples/policies.json", "validate"],
+        &[("CONFIG", "missing.toml"), ("PRINT_CONFIG", "true")],
+    );
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(stdout(&output).contains("is valid"));
+
+    // Other settings are still read without the prefix
+    let printed = print_config(&[], &[("PORT", "9100")]);
+    assert!(printed.contains("port = 9100"));
+}
+
+/// Test that the printed configuration redacts the API key
+#[test]
+fn test_redacted_config() {
+    let printed = print_config(&["--authentication", "secret", "--port", "8280"], &[]);
+    assert!(!printed.contains("secret"));
+    assert!(printed.contains(r#"authentication = "<redacted>""#));
+    assert!(printed.contains("port = 8280"));
+
+    assert!(!print_config(&[], &[]).contains("<redacted>"));
+}
@@ -0,0 +1,3 @@
+pub mod command_tests;
+pub mod config_tests;
+pub mod utils;
@@ -0,0 +1,117 @@
+use crate::routes::utils::rocket;
+use cedar_agent::config::Config;
+use cedar_agent::cors::{self, CorsConfigError};
+use clap::Parser;
+use rocket::http::{Header, Status};
+use rocket::local::asynchronous::Client;
+use rocket::{get, routes};
+
+#[get("/")]
+fn index() -> &'static str {
+    "ok"
+}
+
+fn config(args: &[&str]) -> Config {
+    Config::parse_from(["cedar-agent"].iter().chain(args))
+}
+
+async fn client(conf: &Config) -> Client {
+    let mut rocket = rocket().mount("/", routes![index]);
+    if let Some(cors) = cors::init(conf).unwrap() {
+        rocket = rocket.attach(cors);
+    }
+    Client::tracked(rocket).await.unwrap()
+}
+
+/// Test that credentials can not be allowed together with a wildcard origin
+#[test]
+fn test_cors_wildcard_origin_with_credentials() {
+    let result = cors::init(&config(&[
+        "--cors-allowed-origins=*",
+        "--cors-allow-credentials",
+    ]));
+    assert!(matches!(
+        result,
+        Err(CorsConfigError::CredentialsWithWildcardOrigin)
+    ));
+
+    let result = cors::init(&config(&[
+        "--cors-allowed-origins=http://localhost:3000",
+        "--cors-allow-credentials",
+    ]));
+    assert!(matches!(result, Ok(Some(_))));
+
+    let result = cors::init(&config(&[
+        "--cors-allowed-origins=*",
+        "--cors-allow-credentials=false",
+    ]));
+    assert!(matches!(result, Ok(Some(_))));
+}
+
+/// Test that no cross-origin request is allowed by default
+#[tokio::test]
+async fn test_cors_default_allows_no_origin() {
+    let client = client(&config(&[])).await;
+
+    let response = client
+        .get("/")
+        .header(Header::new("Origin", "http://localhost:3000"))
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Forbidden);
+    assert!(response
+        .headers()
+        .get_one("Access-Control-Allow-Origin")
+        .is_none());
+
+    let response = client.get("/").dispatch().await;
+    assert_eq!(response.status(), Status::Ok);
+}
+
+/// Test that the configured origins are allowed and echoed back
+#[tokio::test]
+async fn test_cors_allowed_origin() {
+    let client = client(&config(&["--cors-allowed-origins=http://localhost:3000"])).await;
+
+    let response = client
+        .get("/")
+        .header(Header::new("Origin", "http://localhost:3000"))
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(
+        response.headers().get_one("Access-Control-Allow-Origin"),
+        Some("http://localhost:3000")
+    );
+
+    let response = client
+        .get("/")
+        .header(Header::new("Origin", "http://example.com"))
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Forbidden);
+}
+
+/// Test that disabling CORS attaches no fairing, and that it can be turned back off
+#[tokio::test]
+async fn test_cors_disabled() {
+    assert!(cors::init(&config(&["--cors-disabled"])).unwrap().is_none());
+    assert!(cors::init(&config(&["--cors-disabled=true"]))
+        .unwrap()
+        .is_none());
+    assert!(cors::init(&config(&["--cors-disabled=false"]))
+        .unwrap()
+        .is_some());
+
+    let client = client(&config(&["--cors-disabled"])).await;
+    let response = client
+        .get("/")
+        .header(Header::new("Origin", "http://localhost:3000"))
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Ok);
+    assert!(response
+        .headers()
+        .get_one("Access-Control-Allow-Origin")
+        .is_none());
+}
@@ -1,7 +1,7 @@
 use crate::routes::utils::*;
 use cedar_agent::data::memory::MemoryDataStore;
 use cedar_agent::schema::memory::MemorySchemaStore;
-use cedar_agent::{DataStore, SchemaStore};
+use cedar_agent::{DataStore, SchemaStore, StoreError};
 
 /// Test adding a new entity successfully
 #[tokio::test]
@@ -288,7 +288,7 @@ async fn test_delete_all_entities() {
     assert_eq!(data_store.get_entities().await.len(), 3);
 
     // Delete all
-    data_store.delete_entities().await;
+    data_store.delete_entities().await.unwrap();
 
     assert_eq!(data_store.get_entities().await.len(), 0);
 }
@@ -313,7 +313,7 @@ async fn test_entity_validation_with_schema() {
     let result = data_store
         .update_entities(invalid, schema_store.get_cedar_schema().await)
         .await;
-    assert!(result.is_err());
+    assert!(matches!(result, Err(StoreError::Validation { .. })));
 }
 
 /// Test updating single entity by id
@@ -567,3 +567,287 @@ async fn test_entity_matching_with_namespaces() {
         .collect();
     assert_eq!(simple_entities.len(), 1);
 }
+
+/// Test JSON and merge patches of an entity with schema validation
+#[tokio::test]
+async fn test_patch_entity() {
+    use cedar_agent::schemas::patch::{Patch, PatchOperation};
+    use cedar_policy::EntityUid;
+    use rocket::serde::json::json;
+    use std::str::FromStr;
+
+    let data_store = MemoryDataStore::new();
+    let schema_store = MemorySchemaStore::new();
+    schema_store.update_schema(sample_schema()).await.unwrap();
+    let schema = schema_store.get_cedar_schema().await;
+    data_store
+        .update_entities(sample_entities(), schema.clone())
+        .await
+        .unwrap();
+    let alice = EntityUid::from_str("User::\"alice\"").unwrap();
+
+    let patch = Patch::Json(vec![
+        PatchOperation::Test {
+            path: "/attrs/level".to_string(),
+            value: json!(5),
+        },
+        PatchOperation::Replace {
+            path: "/attrs/level".to_string(),
+            value: json!(6),
+        },
+        PatchOperation::Remove {
+            path: "/parents/0".to_string(),
+        },
+    ]);
+    let patched = data_store
+        .patch_entity(&alice, &patch, schema.clone())
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(patched.get()["attrs"]["level"], json!(6));
+    assert_eq!(patched.get()["parents"], json!([]));
+
+    let patch = Patch::Merge(json!({ "attrs": { "department": "Sales", "level": null } }));
+    let patched = data_store
+        .patch_entity(&alice, &patch, None)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(patched.get()["attrs"], json!({ "department": "Sales" }));
+
+    // Patches that fail a test or the schema leave the entity unchanged
+    let failing = Patch::Json(vec![
+        PatchOperation::Replace {
+            path: "/attrs/department".to_string(),
+            value: json!("Support"),
+        },
+        PatchOperation::Test {
+            path: "/attrs/department".to_string(),
+            value: json!("Sales"),
+        },
+    ]);
+    assert!(data_store
+        .patch_entity(&alice, &failing, None)
+        .await
+        .is_err());
+    let invalid = Patch::Merge(json!({ "attrs": { "wrongField": "value" } }));
+    assert!(data_store
+        .patch_entity(&alice, &invalid, schema.clone())
+        .await
+        .is_err());
+    let renamed = Patch::Merge(json!({ "uid": { "id": "bob" } }));
+    assert!(data_store
+        .patch_entity(&alice, &renamed, None)
+        .await
+        .is_err());
+    let stored = data_store.get_entity(&alice).await.unwrap();
+    assert_eq!(stored.get()["attrs"], json!({ "department": "Sales" }));
+
+    let missing = EntityUid::from_str("User::\"missing\"").unwrap();
+    assert!(data_store
+        .patch_entity(&missing, &patch, None)
+        .await
+        .unwrap()
+        .is_none());
+}
+
+/// Test the HTTP status of the data store errors
+#[tokio::test]
+async fn test_error_status() {
+    use cedar_agent::schemas::data::IntegrityMode;
+    use cedar_agent::AgentError;
+    use cedar_policy::EntityUid;
+    use rocket::http::Status;
+    use rocket::serde::json::json;
+    use std::str::FromStr;
+
+    let data_store = MemoryDataStore::with_integrity(IntegrityMode::Reject);
+    let entities = |value| rocket::serde::json::serde_json::from_value(value).unwrap();
+    data_store
+        .update_entities(
+            entities(json!([
+                { "uid": { "type": "Group", "id": "dev" }, "attrs": {}, "parents": [] },
+                { "uid": { "type": "User", "id": "alice" }, "attrs": {}, "parents": [{ "type": "Group", "id": "dev" }] }
+            ])),
+            None,
+        )
+        .await
+        .unwrap();
+
+    // Removing an entity that is still a parent conflicts with the stored entities
+    let group = EntityUid::from_str("Group::\"dev\"").unwrap();
+    let err = data_store.remove_entity(&group).await.unwrap_err();
+    assert!(matches!(err, StoreError::Conflict { .. }));
+    assert_eq!(AgentError::from(err).status(), Status::Conflict);
+
+    let err = data_store
+        .upsert_entities(
+            entities(json!([
+                { "uid": { "type": "User", "id": "bob" }, "attrs": {}, "parents": [] },
+                { "uid": { "type": "User", "id": "bob" }, "attrs": { "a": 1 }, "parents": [] }
+            ])),
+            None,
+        )
+        .await
+        .unwrap_err();
+    assert!(matches!(
+        err,
+        StoreError::Duplicate {
+            object: "Entity",
+            ..
+        }
+    ));
+    assert_eq!(AgentError::from(err).status(), Status::Conflict);
+}
+
+/// Test deleting a single entity by its id, which must match only one entity
+#[tokio::test]
+async fn test_delete_single_data_entry() {
+    use cedar_agent::routes::data::delete_single_data_entry;
+    use rocket::http::{ContentType, Status};
+    use rocket::local::asynchronous::Client;
+    use rocket::serde::json::{json, Value};
+
+    let data_store = MemoryDataStore::new();
+    data_store
+        .update_entities(
+            rocket::serde::json::serde_json::from_value(json!([
+                { "uid": { "type": "User", "id": "alice" }, "attrs": {}, "parents": [] },
+                { "uid": { "type": "Group", "id": "alice" }, "attrs": {}, "parents": [] },
+                { "uid": { "type": "User", "id": "bob" }, "attrs": {}, "parents": [] }
+            ]))
+            .unwrap(),
+            None,
+        )
+        .await
+        .unwrap();
+    let rocket = rocket()
+        .manage(Box::new(data_store) as Box<dyn DataStore>)
+        .mount("/", rocket::routes![delete_single_data_entry]);
+    let client = Client::tracked(rocket).await.unwrap();
+
+    // An id shared by entities of several types lists them instead of deleting them all
+    let response = client
+        .delete("/data/single/alice")
+        .header(ContentType::JSON)
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Conflict);
+    let error: Value = response.into_json().await.unwrap();
+    let description = error["description"].as_str().unwrap();
+    assert!(description.contains(r#"User::"alice""#));
+    assert!(description.contains(r#"Group::"alice""#));
+
+    let response = client
+        .delete("/data/single/User::%22alice%22")
+        .header(ContentType::JSON)
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::NoContent);
+
+    let response = client
+        .delete("/data/single/bob")
+        .header(ContentType::JSON)
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::NoContent);
+
+    let response = client
+        .delete("/data/single/bob")
+        .header(ContentType::JSON)
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::NotFound);
+}
+
+/// Test that the attribute routes edit the stored entity and return what was stored
+#[tokio::test]
+async fn test_attribute_routes() {
+    use cedar_agent::routes::data::{
+        delete_entity_attribute, patch_entity_attributes, update_entity_attribute,
+    };
+    use rocket::http::{ContentType, Status};
+    use rocket::local::asynchronous::Client;
+    use rocket::serde::json::{json, Value};
+
+    let schema_store = MemorySchemaStore::new();
+    schema_store.update_schema(sample_schema()).await.unwrap();
+    let data_store = MemoryDataStore::new();
+    data_store
+        .update_entities(sample_entities(), schema_store.get_cedar_schema().await)
+        .await
+        .unwrap();
+    let rocket = rocket()
+        .manage(Box::new(data_store) as Box<dyn DataStore>)
+        .manage(Box::new(schema_store) as Box<dyn SchemaStore>)
+        .mount(
+            "/",
+            rocket::routes![
+                update_entity_attribute,
+                delete_entity_attribute,
+                patch_entity_attributes
+            ],
+        );
+    let client = Client::tracked(rocket).await.unwrap();
+    let data_store = client.rocket().state::<Box<dyn DataStore>>().unwrap();
+    let alice: cedar_policy::EntityUid = r#"User::"alice""#.parse().unwrap();
+    let attribute = |name: &str, value: Value| {
+        json!({ "entity_type": "User", "entity_id": "alice", "attribute_name": name, "attribute_value": value })
+            .to_string()
+    };
+
+    let response = client
+        .put("/data/attribute")
+        .header(ContentType::JSON)
+        .body(attribute("level", json!(7)))
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Ok);
+    let updated: Value = response.into_json().await.unwrap();
+    let stored = data_store.get_entity(&alice).await.unwrap();
+    assert_eq!(&updated, stored.get());
+    assert_eq!(updated["attrs"]["level"], json!(7));
+    assert_eq!(updated["attrs"]["department"], json!("Engineering"));
+
+    let response = client
+        .patch("/data/entity/attributes")
+        .header(ContentType::JSON)
+        .body(
+            json!({
+                "entity_type": "User",
+                "entity_id": "alice",
+                "attributes": { "department": "Sales" },
+                "parents": []
+            })
+            .to_string(),
+        )
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Ok);
+    let patched: Value = response.into_json().await.unwrap();
+    let stored = data_store.get_entity(&alice).await.unwrap();
+    assert_eq!(&patched, stored.get());
+    assert_eq!(patched["attrs"]["department"], json!("Sales"));
+    assert_eq!(patched["parents"], json!([]));
+
+    // A missing attribute leaves the entity as it was
+    let response = client
+        .delete("/data/attribute")
+        .header(ContentType::JSON)
+        .body(attribute("missing", Value::Null))
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::NotFound);
+    assert_eq!(data_store.get_entity(&alice).await.unwrap().get(), &patched);
+
+    // Editing a removed entity does not bring it back
+    data_store.remove_entity(&alice).await.unwrap();
+    let response = client
+        .put("/data/attribute")
+        .header(ContentType::JSON)
+        .body(attribute("level", json!(8)))
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::NotFound);
+    assert!(data_store.get_entity(&alice).await.is_none());
+}
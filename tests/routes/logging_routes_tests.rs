@@ -0,0 +1,209 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::routes::utils::{log_handle, rocket};
+use cedar_agent::config::Config;
+use cedar_agent::logger::LogController;
+use cedar_agent::routes::logging::{get_log_levels, reset_log_levels, update_log_level};
+use clap::Parser;
+use log::LevelFilter;
+use rocket::http::{ContentType, Status};
+use rocket::local::asynchronous::Client;
+use rocket::routes;
+use rocket::serde::json::serde_json::{json, Value};
+
+fn controller() -> Arc<LogController> {
+    let conf = Config::parse_from(["cedar-agent", "--log-level", "off"]);
+    Arc::new(LogController::with_handle(&conf, log_handle()).unwrap())
+}
+
+async fn sleep(millis: u64) {
+    tokio::time::sleep(Duration::from_millis(millis)).await;
+}
+
+/// Test that a level set with a TTL is reverted once it elapses
+#[tokio::test]
+async fn test_log_level_ttl_expires() {
+    let controller = controller();
+    let module = Some("ttl_module".to_string());
+
+    let levels = controller
+        .set_level(
+            module.clone(),
+            LevelFilter::Debug,
+            Some(Duration::from_millis(50)),
+        )
+        .unwrap();
+    assert_eq!(levels.modules.get("ttl_module"), Some(&LevelFilter::Debug));
+
+    sleep(300).await;
+    assert!(controller.levels().modules.get("ttl_module").is_none());
+
+    controller
+        .set_level(None, LevelFilter::Error, Some(Duration::from_millis(50)))
+        .unwrap();
+    assert_eq!(controller.levels().root, LevelFilter::Error);
+
+    sleep(300).await;
+    assert_eq!(controller.levels().root, LevelFilter::Off);
+}
+
+/// Test that overlapping TTLs revert to the level from before the first one
+#[tokio::test]
+async fn test_log_level_overlapping_ttls() {
+    let controller = controller();
+    let module = Some("overlapping_module".to_string());
+
+    controller
+        .set_level(module.clone(), LevelFilter::Warn, None)
+        .unwrap();
+    controller
+        .set_level(
+            module.clone(),
+            LevelFilter::Debug,
+            Some(Duration::from_millis(100)),
+        )
+        .unwrap();
+    controller
+        .set_level(
+            module.clone(),
+            LevelFilter::Trace,
+            Some(Duration::from_millis(400)),
+        )
+        .unwrap();
+
+    // The first TTL elapsing does not undo the newer level
+    sleep(250).await;
+    assert_eq!(
+        controller.levels().modules.get("overlapping_module"),
+        Some(&LevelFilter::Trace)
+    );
+
+    sleep(450).await;
+    assert_eq!(
+        controller.levels().modules.get("overlapping_module"),
+        Some(&LevelFilter::Warn)
+    );
+}
+
+/// Test that a level set without a TTL cancels the pending revert
+#[tokio::test]
+async fn test_log_level_without_ttl_cancels_revert() {
+    let controller = controller();
+    let module = Some("kept_module".to_string());
+
+    controller
+        .set_level(
+            module.clone(),
+            LevelFilter::Debug,
+            Some(Duration::from_millis(50)),
+        )
+        .unwrap();
+    controller
+        .set_level(module.clone(), LevelFilter::Info, None)
+        .unwrap();
+
+    sleep(300).await;
+    assert_eq!(
+        controller.levels().modules.get("kept_module"),
+        Some(&LevelFilter::Info)
+    );
+}
+
+/// Test that resetting restores the initial levels and drops the pending reverts
+#[tokio::test]
+async fn test_log_level_reset() {
+    let controller = controller();
+
+    controller
+        .set_level(None, LevelFilter::Error, None)
+        .unwrap();
+    controller
+        .set_level(
+            Some("reset_module".to_string()),
+            LevelFilter::Debug,
+            Some(Duration::from_millis(50)),
+        )
+        .unwrap();
+
+    let levels = controller.reset().unwrap();
+    assert_eq!(levels.root, LevelFilter::Off);
+    assert!(levels.modules.is_empty());
+
+    controller
+        .set_level(Some("reset_module".to_string()), LevelFilter::Trace, None)
+        .unwrap();
+    sleep(300).await;
+    assert_eq!(
+        controller.levels().modules.get("reset_module"),
+        Some(&LevelFilter::Trace)
+    );
+}
+
+async fn client() -> Client {
+    let rocket = rocket().manage(controller()).mount(
+        "/",
+        routes![get_log_levels, update_log_level, reset_log_levels],
+    );
+    Client::tracked(rocket).await.unwrap()
+}
+
+/// Test updating, reading and resetting the levels through the routes
+#[tokio::test]
+async fn test_log_level_routes() {
+    let client = client().await;
+
+    let response = client
+        .put("/admin/logging")
+        .header(ContentType::JSON)
+        .body(json!({"level": "debug", "module": "route_module"}).to_string())
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Ok);
+    let levels: Value = response.into_json().await.unwrap();
+    assert_eq!(levels["level"], "off");
+    assert_eq!(levels["modules"]["route_module"], "debug");
+
+    let response = client.get("/admin/logging").dispatch().await;
+    let levels: Value = response.into_json().await.unwrap();
+    assert_eq!(levels["modules"]["route_module"], "debug");
+
+    let response = client.delete("/admin/logging").dispatch().await;
+    assert_eq!(response.status(), Status::Ok);
+    let levels: Value = response.into_json().await.unwrap();
+    assert_eq!(levels, json!({"level": "off", "modules": {}}));
+}
+
+/// Test that an unknown level is rejected
+#[tokio::test]
+async fn test_log_level_route_bad_level() {
+    let client = client().await;
+
+    let response = client
+        .put("/admin/logging")
+        .header(ContentType::JSON)
+        .body(json!({"level": "verbose"}).to_string())
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::UnprocessableEntity);
+}
+
+/// Test that an empty module is rejected
+#[tokio::test]
+async fn test_log_level_route_bad_target() {
+    let client = client().await;
+
+    for module in ["", "  "] {
+        let response = client
+            .put("/admin/logging")
+            .header(ContentType::JSON)
+            .body(json!({"level": "debug", "module": module}).to_string())
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::BadRequest);
+    }
+
+    let response = client.get("/admin/logging").dispatch().await;
+    let levels: Value = response.into_json().await.unwrap();
+    assert_eq!(levels["modules"], json!({}));
+}
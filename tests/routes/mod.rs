@@ -1,4 +1,8 @@
+pub mod cors_tests;
 pub mod data_routes_tests;
+pub mod logging_routes_tests;
 pub mod policy_routes_tests;
+pub mod replay_routes_tests;
+pub mod request_id_tests;
 pub mod schema_routes_tests;
 pub mod utils;
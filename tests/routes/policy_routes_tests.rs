@@ -1,8 +1,9 @@
 use crate::routes::utils::*;
 use cedar_agent::policies::memory::MemoryPolicyStore;
 use cedar_agent::schema::memory::MemorySchemaStore;
-use cedar_agent::schemas::policies::PolicyUpdate;
-use cedar_agent::{PolicyStore, SchemaStore};
+use cedar_agent::schemas::policies::{Policy, PolicyUpdate};
+use cedar_agent::{AgentError, PolicyStore, SchemaStore, StoreError};
+use rocket::http::Status;
 
 /// Test creating a policy successfully
 #[tokio::test]
@@ -31,6 +32,7 @@ async fn test_create_duplicate_policy() {
     // Verify error indicates already exists
     let err = result.unwrap_err();
     assert!(err.to_string().contains("already exists"));
+    assert!(matches!(err, StoreError::Duplicate { .. }));
 }
 
 /// Test creating policy with parse error
@@ -83,6 +85,7 @@ async fn test_bulk_update_with_duplicates() {
     // Verify error indicates duplicate
     let err = result.unwrap_err();
     assert!(err.to_string().contains("already exists"));
+    assert!(matches!(err, StoreError::Duplicate { .. }));
 }
 
 /// Test bulk update with parse error
@@ -109,6 +112,7 @@ async fn test_update_single_policy() {
     let new_content = "permit(principal == User::\"admin\", action, resource);";
     let update = PolicyUpdate {
         content: new_content.to_string(),
+        ..Default::default()
     };
 
     let result = policy_store
@@ -132,6 +136,7 @@ async fn test_update_policy_with_invalid_content() {
     // Try to update with invalid content
     let update = PolicyUpdate {
         content: "this is not valid cedar".to_string(),
+        ..Default::default()
     };
 
     let result = policy_store
@@ -159,7 +164,7 @@ async fn test_get_nonexistent_policy() {
     let policy_store = MemoryPolicyStore::new();
 
     let result = policy_store.get_policy("does_not_exist").await;
-    assert!(result.is_err());
+    assert!(matches!(result, Err(StoreError::NotFound { .. })));
 }
 
 /// Test deleting policy
@@ -185,7 +190,7 @@ async fn test_delete_nonexistent_policy() {
     let policy_store = MemoryPolicyStore::new();
 
     let result = policy_store.delete_policy("not_there").await;
-    assert!(result.is_err());
+    assert!(matches!(result, Err(StoreError::NotFound { .. })));
 }
 
 /// Test policy validation against schema
@@ -283,6 +288,7 @@ async fn test_create_policy_invalid_for_schema() {
             || err_msg.contains("NonExistent")
             || err_msg.contains("unrecognized")
     );
+    assert!(matches!(err, StoreError::Validation { .. }));
 }
 
 /// Test getting all policies
@@ -350,3 +356,74 @@ async fn test_policy_set_consistency() {
         .policy(&cedar_policy::PolicyId::from_str("p2").unwrap())
         .is_some());
 }
+
+/// Test that creating an existing policy from a policy update, as `PUT /policies/<id>?mode=create`
+/// does, conflicts while replacing it succeeds
+#[tokio::test]
+async fn test_create_mode_conflict() {
+    let policy_store = MemoryPolicyStore::new();
+    policy_store
+        .create_policy(&sample_policy("existing"), None)
+        .await
+        .unwrap();
+    let update = || PolicyUpdate {
+        content: "forbid(principal,action,resource);".to_string(),
+        ..Default::default()
+    };
+
+    let policy = Policy::from_policy_update("existing".to_string(), update());
+    let err = policy_store.create_policy(&policy, None).await.unwrap_err();
+    assert_eq!(AgentError::from(err).status(), Status::Conflict);
+
+    // Without the create mode the policy is replaced
+    let result = policy_store
+        .update_policy("existing".to_string(), update(), None)
+        .await;
+    assert!(result.is_ok());
+}
+
+/// Test the HTTP status of the errors of the policy store
+#[tokio::test]
+async fn test_error_status() {
+    let policy_store = MemoryPolicyStore::new();
+    let schema_store = MemorySchemaStore::new();
+    schema_store.update_schema(sample_schema()).await.unwrap();
+    let schema = schema_store.get_cedar_schema().await;
+
+    let err = policy_store.get_policy("missing").await.unwrap_err();
+    assert!(matches!(
+        err,
+        StoreError::NotFound {
+            object: "Policy",
+            ..
+        }
+    ));
+    assert_eq!(AgentError::from(err).status(), Status::NotFound);
+
+    let err = policy_store
+        .create_policy(&invalid_policy("invalid"), schema.clone())
+        .await
+        .unwrap_err();
+    assert_eq!(AgentError::from(err).status(), Status::BadRequest);
+
+    let unknown = Policy {
+        id: "unknown".to_string(),
+        content: r#"permit(principal == Unknown::"alice", action, resource);"#.to_string(),
+        ..Default::default()
+    };
+    let err = policy_store
+        .create_policy(&unknown, schema.clone())
+        .await
+        .unwrap_err();
+    assert_eq!(AgentError::from(err).status(), Status::UnprocessableEntity);
+
+    policy_store
+        .create_policy(&sample_policy("duplicate"), schema.clone())
+        .await
+        .unwrap();
+    let err = policy_store
+        .create_policy(&sample_policy("duplicate"), schema)
+        .await
+        .unwrap_err();
+    assert_eq!(AgentError::from(err).status(), Status::Conflict);
+}
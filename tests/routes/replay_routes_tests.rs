@@ -0,0 +1,89 @@
+use crate::routes::utils::*;
+use cedar_agent::config::Config;
+use cedar_agent::data::memory::MemoryDataStore;
+use cedar_agent::policies::memory::MemoryPolicyStore;
+use cedar_agent::routes::replay::replay_requests;
+use cedar_agent::schema::memory::MemorySchemaStore;
+use cedar_agent::{DataStore, PolicyStore, SchemaStore};
+use clap::Parser;
+use rocket::http::{ContentType, Status};
+use rocket::local::asynchronous::Client;
+use rocket::routes;
+use rocket::serde::json::serde_json::{json, Value};
+
+async fn client() -> Client {
+    let schema_store = MemorySchemaStore::new();
+    schema_store.update_schema(sample_schema()).await.unwrap();
+    let schema = schema_store.get_cedar_schema().await;
+    let data_store = MemoryDataStore::new();
+    data_store
+        .update_entities(sample_entities(), schema.clone())
+        .await
+        .unwrap();
+    let policy_store = MemoryPolicyStore::new();
+    policy_store
+        .update_policies(vec![sample_policy("all")], schema)
+        .await
+        .unwrap();
+    let rocket = rocket()
+        .manage(Config::parse_from(["cedar-agent"]))
+        .manage(Box::new(policy_store) as Box<dyn PolicyStore>)
+        .manage(Box::new(data_store) as Box<dyn DataStore>)
+        .manage(Box::new(schema_store) as Box<dyn SchemaStore>)
+        .manage(cedar_policy::Authorizer::new())
+        .mount("/", routes![replay_requests]);
+    Client::tracked(rocket).await.unwrap()
+}
+
+async fn replay(client: &Client, candidate: Value) -> (Status, Value) {
+    let response = client
+        .post("/admin/replay")
+        .header(ContentType::JSON)
+        .body(
+            json!({
+                "requests": [{
+                    "principal": "User::\"alice\"",
+                    "action": "Action::\"view\"",
+                    "resource": "Document::\"doc1\""
+                }],
+                "candidate": candidate
+            })
+            .to_string(),
+        )
+        .dispatch()
+        .await;
+    (response.status(), response.into_json().await.unwrap())
+}
+
+/// Test that a replay rejects invalid candidates with 400
+#[tokio::test]
+async fn test_replay_invalid_candidate() {
+    let client = client().await;
+
+    let (status, report) = replay(
+        &client,
+        json!({ "policies": [{ "id": "none", "content": "forbid(principal, action, resource);" }] }),
+    )
+    .await;
+    assert_eq!(status, Status::Ok);
+    assert_eq!(report["diffs"].as_array().unwrap().len(), 1);
+
+    let (status, _) = replay(&client, json!({ "policies": [invalid_policy("invalid")] })).await;
+    assert_eq!(status, Status::BadRequest);
+
+    // The stored entities have attributes the candidate schema does not declare
+    let (status, error) = replay(
+        &client,
+        json!({
+            "schema": {
+                "": {
+                    "entityTypes": { "User": { "memberOfTypes": ["Role"] }, "Role": {}, "Document": {} },
+                    "actions": {}
+                }
+            }
+        }),
+    )
+    .await;
+    assert_eq!(status, Status::BadRequest);
+    assert!(error.to_string().contains("candidate schema"));
+}
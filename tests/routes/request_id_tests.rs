@@ -0,0 +1,158 @@
+use crate::routes::utils::rocket;
+use cedar_agent::request_id::{
+    catchers_with_request_id, with_request_id, RequestIdFairing, REQUEST_ID_HEADER, REQUEST_ID_KEY,
+};
+use rocket::http::{Header, Status};
+use rocket::local::asynchronous::Client;
+use rocket::{catch, catchers, get, routes, Request};
+
+fn logged_request_id() -> String {
+    log_mdc::get(REQUEST_ID_KEY, |id| id.unwrap_or_default().to_owned())
+}
+
+#[get("/mdc")]
+fn mdc() -> String {
+    logged_request_id()
+}
+
+#[catch(404)]
+fn not_found(_req: &Request) -> String {
+    logged_request_id()
+}
+
+async fn client() -> Client {
+    let rocket = rocket()
+        .attach(RequestIdFairing)
+        .register("/", catchers_with_request_id(catchers![not_found]))
+        .mount("/", with_request_id(routes![mdc]));
+    Client::tracked(rocket).await.unwrap()
+}
+
+/// Test that the ID supplied by the client is echoed back and logged
+#[tokio::test]
+async fn test_request_id_from_header() {
+    let client = client().await;
+
+    let response = client
+        .get("/mdc")
+        .header(Header::new(REQUEST_ID_HEADER, "client-id-1"))
+        .dispatch()
+        .await;
+
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(
+        response.headers().get_one(REQUEST_ID_HEADER),
+        Some("client-id-1")
+    );
+    assert_eq!(response.into_string().await.unwrap(), "client-id-1");
+}
+
+/// Test that an ID is generated when the request has none
+#[tokio::test]
+async fn test_request_id_generated() {
+    let client = client().await;
+
+    let response = client.get("/mdc").dispatch().await;
+
+    let request_id = response
+        .headers()
+        .get_one(REQUEST_ID_HEADER)
+        .unwrap()
+        .to_owned();
+    assert_eq!(request_id.len(), 32);
+    assert!(request_id.chars().all(|c| c.is_ascii_hexdigit()));
+    assert_eq!(response.into_string().await.unwrap(), request_id);
+}
+
+/// Test that IDs which could corrupt the logs are replaced
+#[tokio::test]
+async fn test_request_id_invalid_header_replaced() {
+    let client = client().await;
+
+    for invalid in ["", "two words", "line\u{7f}break", &"x".repeat(129)] {
+        let response = client
+            .get("/mdc")
+            .header(Header::new(REQUEST_ID_HEADER, invalid.to_owned()))
+            .dispatch()
+            .await;
+
+        let request_id = response
+            .headers()
+            .get_one(REQUEST_ID_HEADER)
+            .unwrap()
+            .to_owned();
+        assert_ne!(request_id, invalid);
+        assert_eq!(request_id.len(), 32);
+        assert_eq!(response.into_string().await.unwrap(), request_id);
+    }
+
+    let longest = "x".repeat(128);
+    let response = client
+        .get("/mdc")
+        .header(Header::new(REQUEST_ID_HEADER, longest.clone()))
+        .dispatch()
+        .await;
+    assert_eq!(
+        response.headers().get_one(REQUEST_ID_HEADER),
+        Some(longest.as_str())
+    );
+}
+
+/// Test that every request logs its own ID
+#[tokio::test]
+async fn test_request_id_per_request() {
+    let client = client().await;
+
+    let first = client.get("/mdc").dispatch().await;
+    let first_id = first
+        .headers()
+        .get_one(REQUEST_ID_HEADER)
+        .unwrap()
+        .to_owned();
+    assert_eq!(first.into_string().await.unwrap(), first_id);
+
+    let second = client.get("/mdc").dispatch().await;
+    let second_id = second
+        .headers()
+        .get_one(REQUEST_ID_HEADER)
+        .unwrap()
+        .to_owned();
+    assert_eq!(second.into_string().await.unwrap(), second_id);
+
+    assert_ne!(first_id, second_id);
+}
+
+/// Test that catchers log the ID and it is echoed on error responses
+#[tokio::test]
+async fn test_request_id_in_catcher() {
+    let client = client().await;
+
+    let response = client
+        .get("/missing")
+        .header(Header::new(REQUEST_ID_HEADER, "client-id-2"))
+        .dispatch()
+        .await;
+
+    assert_eq!(response.status(), Status::NotFound);
+    assert_eq!(
+        response.headers().get_one(REQUEST_ID_HEADER),
+        Some("client-id-2")
+    );
+    assert_eq!(response.into_string().await.unwrap(), "client-id-2");
+}
+
+/// Test that the ID is not left behind for the log lines emitted after the request
+#[tokio::test]
+async fn test_request_id_cleared_after_request() {
+    let client = client().await;
+
+    for path in ["/mdc", "/missing"] {
+        let response = client
+            .get(path)
+            .header(Header::new(REQUEST_ID_HEADER, "client-id-3"))
+            .dispatch()
+            .await;
+        assert_eq!(response.into_string().await.unwrap(), "client-id-3");
+        assert_eq!(logged_request_id(), "");
+    }
+}
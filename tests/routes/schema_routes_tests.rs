@@ -2,7 +2,7 @@ use crate::routes::utils::*;
 use cedar_agent::data::memory::MemoryDataStore;
 use cedar_agent::policies::memory::MemoryPolicyStore;
 use cedar_agent::schema::memory::MemorySchemaStore;
-use cedar_agent::{DataStore, PolicyStore, SchemaStore};
+use cedar_agent::{DataStore, PolicyStore, SchemaStore, StoreError};
 use rocket::serde::json::serde_json::json;
 
 /// Test updating schema successfully
@@ -562,3 +562,82 @@ async fn test_add_action_to_schema() {
     assert!(actions.is_some());
     assert!(actions.unwrap().get("read").is_some());
 }
+
+/// Test patching the schema document with JSON patch and merge patch
+#[tokio::test]
+async fn test_patch_schema_document() {
+    use cedar_agent::schemas::patch::{Patch, PatchOperation};
+
+    let schema = sample_schema();
+    let patch = Patch::Json(vec![
+        PatchOperation::Add {
+            path: "/~1entityTypes".to_string(),
+            value: json!("escaped"),
+        },
+        PatchOperation::Copy {
+            from: "/entityTypes/User/shape/attributes/level".to_string(),
+            path: "/entityTypes/Document/shape/attributes/version".to_string(),
+        },
+        PatchOperation::Move {
+            from: "/entityTypes/Document/shape/attributes/owner".to_string(),
+            path: "/entityTypes/Document/shape/attributes/author".to_string(),
+        },
+        PatchOperation::Remove {
+            path: "/~1entityTypes".to_string(),
+        },
+    ]);
+    let nested = Patch::Json(vec![PatchOperation::Add {
+        path: "/missing/child".to_string(),
+        value: json!(1),
+    }]);
+
+    let document = schema.get()[""].clone();
+    let patched = patch.apply(&document).unwrap();
+    assert_eq!(
+        patched["entityTypes"]["Document"]["shape"]["attributes"],
+        json!({ "author": { "type": "String" }, "version": { "type": "Long" } })
+    );
+    assert!(patched.get("/entityTypes").is_none());
+    assert!(nested.apply(&document).is_err());
+
+    let merge = Patch::Merge(json!({ "actions": { "edit": null } }));
+    let merged = merge.apply(&document).unwrap();
+    assert_eq!(merged["actions"].as_object().unwrap().len(), 1);
+    assert_eq!(merged["entityTypes"], document["entityTypes"]);
+
+    // The patched schema is still validated before it is stored
+    let schema_store = MemorySchemaStore::new();
+    let invalid = Patch::Merge(
+        json!({ "": { "entityTypes": { "User": { "memberOfTypes": ["Missing"] } } } }),
+    );
+    let patched = invalid.apply(schema.get()).unwrap();
+    assert!(schema_store
+        .update_schema(cedar_agent::schemas::schema::Schema::from(patched))
+        .await
+        .is_err());
+}
+
+/// Test that a schema derived from a replaced one isn't stored
+#[tokio::test]
+async fn test_swap_schema_conflict() {
+    use cedar_agent::schemas::patch::Patch;
+
+    let schema_store = MemorySchemaStore::new();
+    let base = schema_store.update_schema(sample_schema()).await.unwrap();
+
+    // Two patches read the same base, only the first one is stored
+    let first = Patch::Merge(json!({ "": { "actions": { "first": {} } } }));
+    let second = Patch::Merge(json!({ "": { "actions": { "second": {} } } }));
+    let first = cedar_agent::schemas::schema::Schema::from(first.apply(base.get()).unwrap());
+    let second = cedar_agent::schemas::schema::Schema::from(second.apply(base.get()).unwrap());
+
+    let stored = schema_store.swap_schema(&base, first).await.unwrap();
+    assert!(matches!(
+        schema_store.swap_schema(&base, second.clone()).await,
+        Err(StoreError::Conflict { .. })
+    ));
+    assert_eq!(schema_store.get_internal_schema().await.get(), stored.get());
+
+    // Unchanged since it was read, the schema is swapped
+    assert!(schema_store.swap_schema(&stored, second).await.is_ok());
+}
@@ -3,7 +3,31 @@ use cedar_agent::schemas::data::{
 };
 use cedar_agent::schemas::policies::Policy;
 use cedar_agent::schemas::schema::Schema;
+use log::LevelFilter;
+use log4rs::config::Root;
+use log4rs::Handle;
 use rocket::serde::json::serde_json::{from_str, json, Value};
+use rocket::{Build, Rocket};
+use std::sync::OnceLock;
+
+/// Handle of the global logger, which can only be initialized once so every test shares it
+pub fn log_handle() -> Handle {
+    static HANDLE: OnceLock<Handle> = OnceLock::new();
+    HANDLE
+        .get_or_init(|| {
+            let config = log4rs::Config::builder()
+                .build(Root::builder().build(LevelFilter::Off))
+                .unwrap();
+            log4rs::init_config(config).unwrap()
+        })
+        .clone()
+}
+
+/// Helper to build a server, after the logger so Rocket does not install its own
+pub fn rocket() -> Rocket<Build> {
+    log_handle();
+    rocket::build()
+}
 
 /// Helper to create a simple entity
 pub fn create_simple_entity(entity_type: &str, entity_id: &str) -> Entity {
@@ -50,7 +74,7 @@ pub fn entity_attr_with_value(
         namespace: "".to_string(),
         entity_id: entity_id.to_string(),
         attribute_name: attr_name.to_string(),
-        attribute_value: attr_value.to_string(),
+        attribute_value: Value::from(attr_value),
     }
 }
 
@@ -86,7 +110,7 @@ pub fn entity_attr_with_value_with_namespace(
         namespace: namespace.to_string(),
         entity_id: entity_id.to_string(),
         attribute_name: attr_name.to_string(),
-        attribute_value: attr_value.to_string(),
+        attribute_value: Value::from(attr_value),
     }
 }
 
@@ -133,6 +157,7 @@ pub fn sample_policy(id: &str) -> Policy {
     Policy {
         id: id.to_string(),
         content: "permit(principal,action,resource);".to_string(),
+        ..Default::default()
     }
 }
 
@@ -141,6 +166,7 @@ pub fn invalid_policy(id: &str) -> Policy {
     Policy {
         id: id.to_string(),
         content: "this is not valid cedar syntax".to_string(),
+        ..Default::default()
     }
 }
 
@@ -0,0 +1,303 @@
+use std::collections::HashSet;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use cedar_agent::authorization::cache::DecisionCache;
+use cedar_agent::authorization::explain::explain;
+use cedar_agent::authorization::replay::{replay, ReplayTarget};
+use cedar_agent::authorization::shadow::ShadowMonitor;
+use cedar_agent::changes::ChangeFeed;
+use cedar_agent::data::EntitySnapshot;
+use cedar_agent::schemas::authorization::DecisionRef;
+use cedar_agent::schemas::authorization::{
+    AuthorizationAnswer, AuthorizationCall, PolicyEvaluation, PolicyOutcome,
+};
+use cedar_agent::schemas::changes::{ObjectType, Operation};
+use cedar_agent::schemas::policies::Policy;
+use cedar_agent::schemas::replay::ReplayCandidate;
+use cedar_agent::schemas::schema::Schema;
+use cedar_policy::{
+    Authorizer, Context, Decision, Entities, EntityUid, PolicySet, Request, Response,
+};
+use rocket::serde::json::serde_json::{from_value, json};
+
+fn policies() -> PolicySet {
+    // Parsed policies are named policy0, policy1, ... in order
+    PolicySet::from_str(
+        r#"
+        permit(principal in Role::"Editor", action == Action::"edit", resource);
+        permit(principal in Role::"Admin", action, resource);
+        permit(principal, action == Action::"edit", resource) when { principal.level > 5 };
+        forbid(principal, action, resource) when { principal.level + 9223372036854775807 > 0 };
+        "#,
+    )
+    .unwrap()
+}
+
+fn entities() -> Entities {
+    Entities::from_json_value(
+        json!([
+            {
+                "uid": { "type": "User", "id": "alice" },
+                "attrs": { "level": 3 },
+                "parents": [{ "type": "Role", "id": "Editor" }]
+            },
+            {
+                "uid": { "type": "Role", "id": "Editor" },
+                "attrs": {},
+                "parents": []
+            }
+        ]),
+        None,
+    )
+    .unwrap()
+}
+
+fn evaluation<'a>(evaluations: &'a [PolicyEvaluation], id: &str) -> &'a PolicyEvaluation {
+    evaluations.iter().find(|e| e.id == id).unwrap()
+}
+
+#[test]
+fn test_explain_policy_outcomes() {
+    let request = Request::new(
+        EntityUid::from_str("User::\"alice\"").unwrap(),
+        EntityUid::from_str("Action::\"edit\"").unwrap(),
+        EntityUid::from_str("Document::\"doc\"").unwrap(),
+        Context::empty(),
+        None,
+    )
+    .unwrap();
+
+    let explanation = explain(&Authorizer::new(), &request, &policies(), &entities());
+    assert_eq!(explanation.policies.len(), 4);
+
+    let policies = &explanation.policies;
+    assert_eq!(
+        evaluation(policies, "policy0").outcome,
+        PolicyOutcome::Satisfied
+    );
+    let admins = evaluation(policies, "policy1");
+    assert_eq!(admins.outcome, PolicyOutcome::ScopeMismatch);
+    assert!(admins.reason.as_ref().unwrap().contains("Role::\"Admin\""));
+    assert_eq!(
+        evaluation(policies, "policy2").outcome,
+        PolicyOutcome::ConditionFalse
+    );
+    assert_eq!(
+        evaluation(policies, "policy3").outcome,
+        PolicyOutcome::Error
+    );
+
+    let principal = &explanation.entities[0];
+    assert_eq!(principal.uid, "User::\"alice\"");
+    assert!(principal.found);
+    assert_eq!(principal.attrs, json!({ "level": 3 }));
+    assert_eq!(principal.ancestors, vec!["Role::\"Editor\"".to_string()]);
+    assert!(!explanation.entities[2].found);
+}
+
+fn call(principal: &str) -> AuthorizationCall {
+    AuthorizationCall::new(
+        Some(format!("User::\"{}\"", principal)),
+        Some("Action::\"edit\"".to_owned()),
+        Some("Document::\"doc\"".to_owned()),
+        None,
+        None,
+        None,
+        None,
+    )
+}
+
+fn answer(decision: Decision) -> AuthorizationAnswer {
+    AuthorizationAnswer::from(Response::new(decision, HashSet::new(), vec![]))
+}
+
+#[test]
+fn test_decision_cache() {
+    let changes = Arc::new(ChangeFeed::new());
+    let cache = DecisionCache::new(2, None, changes.clone());
+
+    let key = cache.key(&call("alice")).unwrap();
+    assert!(cache.get(&key).is_none());
+    cache.insert(key, answer(Decision::Allow));
+    cache.insert(cache.key(&call("bob")).unwrap(), answer(Decision::Deny));
+    assert!(cache.get(&cache.key(&call("alice")).unwrap()).is_some());
+
+    // bob is the least recently used decision
+    cache.insert(cache.key(&call("carol")).unwrap(), answer(Decision::Allow));
+    assert!(cache.get(&cache.key(&call("bob")).unwrap()).is_none());
+    assert!(cache.get(&cache.key(&call("carol")).unwrap()).is_some());
+
+    // A decision computed before a change is not cached
+    let stale = cache.key(&call("dave")).unwrap();
+    changes.publish([(ObjectType::Policy, "policy0".to_owned(), Operation::Update)]);
+    cache.insert(stale, answer(Decision::Allow));
+    assert!(cache.get(&cache.key(&call("alice")).unwrap()).is_none());
+    assert!(cache.get(&cache.key(&call("dave")).unwrap()).is_none());
+
+    let metrics = cache.metrics();
+    assert_eq!(metrics.entries, 0);
+    assert_eq!((metrics.hits, metrics.misses), (2, 4));
+    assert_eq!((metrics.evictions, metrics.invalidations), (1, 1));
+
+    let expiring = DecisionCache::new(2, Some(Duration::ZERO), changes.clone());
+    expiring.insert(
+        expiring.key(&call("alice")).unwrap(),
+        answer(Decision::Allow),
+    );
+    assert!(expiring
+        .get(&expiring.key(&call("alice")).unwrap())
+        .is_none());
+
+    assert!(DecisionCache::disabled(changes)
+        .key(&call("alice"))
+        .is_none());
+}
+
+#[tokio::test]
+async fn test_replay() {
+    let baseline = ReplayTarget {
+        policies: Arc::new(policies()),
+        entities: EntitySnapshot::from(entities()),
+    };
+    let invalid = AuthorizationCall::new(
+        Some("alice".to_owned()),
+        Some("Action::\"edit\"".to_owned()),
+        Some("Document::\"doc\"".to_owned()),
+        None,
+        None,
+        None,
+        None,
+    );
+    let requests = vec![call("alice"), call("bob"), invalid];
+
+    let report = replay(&Authorizer::new(), &requests, &baseline, None);
+    assert_eq!(report.requests, 3);
+    let decisions = &report.baseline.decisions;
+    assert_eq!(
+        (decisions.allow, decisions.deny, decisions.errors),
+        (1, 1, 1)
+    );
+    assert!(report.baseline.latency_us.p50 <= report.baseline.latency_us.max);
+    assert!(report.candidate.is_none());
+
+    let candidate = ReplayCandidate {
+        policies: Some(vec![Policy {
+            id: "everyone".to_owned(),
+            content: "permit(principal, action, resource);".to_owned(),
+            ..Default::default()
+        }]),
+        ..Default::default()
+    };
+    let candidate = baseline
+        .with_candidate(candidate, None, None)
+        .await
+        .unwrap();
+    let report = replay(&Authorizer::new(), &requests, &baseline, Some(&candidate));
+    assert_eq!(report.candidate.unwrap().decisions.allow, 2);
+    assert_eq!(report.diffs.len(), 1);
+    let diff = &report.diffs[0];
+    assert_eq!(diff.index, 1);
+    assert_eq!(diff.baseline.decision, Some(DecisionRef::Deny));
+    assert_eq!(diff.candidate.decision, Some(DecisionRef::Allow));
+    assert!(diff.candidate.reasons.contains("everyone"));
+
+    let invalid_candidate = ReplayCandidate {
+        policies: Some(vec![Policy {
+            id: "invalid".to_owned(),
+            content: "permit(".to_owned(),
+            ..Default::default()
+        }]),
+        ..Default::default()
+    };
+    assert!(baseline
+        .with_candidate(invalid_candidate, None, None)
+        .await
+        .is_err());
+}
+
+/// Test that a candidate with only a schema checks the stored policies and entities against it
+#[tokio::test]
+async fn test_replay_candidate_schema() {
+    let baseline = ReplayTarget {
+        policies: Arc::new(policies()),
+        entities: EntitySnapshot::from(entities()),
+    };
+    let schema = |level: &str| {
+        from_value::<Schema>(json!({
+            "": {
+                "entityTypes": {
+                    "User": {
+                        "shape": {
+                            "type": "Record",
+                            "attributes": { "level": { "type": level } }
+                        },
+                        "memberOfTypes": ["Role"]
+                    },
+                    "Role": {},
+                    "Document": {}
+                },
+                "actions": {
+                    "edit": {
+                        "appliesTo": {
+                            "principalTypes": ["User"],
+                            "resourceTypes": ["Document"]
+                        }
+                    }
+                }
+            }
+        }))
+        .unwrap()
+    };
+    let candidate = |schema: Schema| ReplayCandidate {
+        schema: Some(schema),
+        ..Default::default()
+    };
+
+    let target = baseline
+        .with_candidate(candidate(schema("Long")), None, None)
+        .await
+        .unwrap();
+    assert_eq!(target.policies.policies().count(), 4);
+    assert!(target
+        .entities
+        .get(&EntityUid::from_str("User::\"alice\"").unwrap())
+        .is_some());
+
+    // The level of alice is not a string, and the policies add a number to it
+    let err = baseline
+        .with_candidate(candidate(schema("String")), None, None)
+        .await
+        .err()
+        .unwrap();
+    assert!(err.contains("candidate schema"));
+}
+
+#[test]
+fn test_shadow_monitor() {
+    let monitor = ShadowMonitor::new();
+    let shadow = |decision: Decision| Response::new(decision, HashSet::new(), vec![]);
+
+    monitor.record(
+        call("alice"),
+        &answer(Decision::Allow),
+        shadow(Decision::Allow),
+    );
+    monitor.record(
+        call("bob"),
+        &answer(Decision::Deny),
+        shadow(Decision::Allow),
+    );
+    let metrics = monitor.metrics();
+    assert_eq!((metrics.evaluations, metrics.disagreements), (2, 1));
+
+    let samples = monitor.samples();
+    assert_eq!(samples.len(), 1);
+    assert_eq!(samples[0].active_decision, DecisionRef::Deny);
+    assert_eq!(samples[0].shadow_decision, DecisionRef::Allow);
+
+    monitor.reset();
+    assert_eq!(monitor.metrics().evaluations, 0);
+    assert!(monitor.samples().is_empty());
+}
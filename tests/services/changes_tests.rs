@@ -0,0 +1,107 @@
+use std::str::FromStr;
+use std::sync::Arc;
+
+use cedar_policy::EntityUid;
+
+use crate::services::utils::*;
+
+use cedar_agent::changes::ChangeFeed;
+use cedar_agent::data::memory::MemoryDataStore;
+use cedar_agent::policies::memory::MemoryPolicyStore;
+use cedar_agent::schema::memory::MemorySchemaStore;
+use cedar_agent::schemas::changes::{ChangeEvent, ObjectType, Operation};
+use cedar_agent::schemas::data::IntegrityMode;
+use cedar_agent::{DataStore, PolicyStore, SchemaStore};
+
+fn summary(events: &[ChangeEvent]) -> Vec<(ObjectType, String, Operation)> {
+    events
+        .iter()
+        .map(|event| (event.object, event.id.clone(), event.operation))
+        .collect()
+}
+
+#[tokio::test]
+async fn test_store_changes() {
+    let changes = Arc::new(ChangeFeed::new());
+    let policy_store = MemoryPolicyStore::new().with_changes(changes.clone());
+    let data_store =
+        MemoryDataStore::with_integrity(IntegrityMode::Cascade).with_changes(changes.clone());
+    let schema_store = MemorySchemaStore::new().with_changes(changes.clone());
+
+    policy_store
+        .update_policies(vec![approve_all_policy(None)], None)
+        .await
+        .unwrap();
+    schema_store.update_schema(schema()).await.unwrap();
+    data_store.update_entities(entities(), None).await.unwrap();
+    let loaded = changes.revision();
+    assert_eq!(loaded, 10);
+
+    // Reloading unchanged content is not a change
+    schema_store.update_schema(schema()).await.unwrap();
+    data_store.update_entities(entities(), None).await.unwrap();
+    assert_eq!(changes.revision(), loaded);
+
+    let (missed, _) = changes.subscribe(Some(loaded)).unwrap();
+    assert!(missed.is_empty());
+    let update = EntityUid::from_str("Action::\"document:update\"").unwrap();
+    data_store.remove_entity(&update).await.unwrap();
+    policy_store.delete_policy("test").await.unwrap();
+    schema_store.delete_schema().await;
+
+    // Cascading the removal updates the children of the removed entity
+    let (missed, _) = changes.subscribe(Some(loaded)).unwrap();
+    assert_eq!(
+        missed
+            .iter()
+            .map(|event| event.revision)
+            .collect::<Vec<_>>(),
+        (loaded + 1..=loaded + 4).collect::<Vec<_>>()
+    );
+    assert_eq!(
+        summary(&missed),
+        [
+            (
+                ObjectType::Entity,
+                "Action::\"document:update\"".to_string(),
+                Operation::Delete
+            ),
+            (
+                ObjectType::Entity,
+                "Action::\"document:create\"".to_string(),
+                Operation::Update
+            ),
+            (ObjectType::Policy, "test".to_string(), Operation::Delete),
+            (ObjectType::Schema, "schema".to_string(), Operation::Delete),
+        ]
+    );
+    assert!(changes.subscribe(Some(loaded + 100)).is_err());
+}
+
+#[tokio::test]
+async fn test_policy_detail_changes() {
+    let changes = Arc::new(ChangeFeed::new());
+    let policy_store = MemoryPolicyStore::new().with_changes(changes.clone());
+    let mut policy = approve_all_policy(None);
+    policy_store
+        .update_policies(vec![policy.clone()], None)
+        .await
+        .unwrap();
+    let loaded = changes.revision();
+
+    // Changing only the details of a policy is a change
+    policy.description = Some("Approve everything".to_string());
+    policy_store
+        .update_policies(vec![policy.clone()], None)
+        .await
+        .unwrap();
+    policy_store
+        .update_policies(vec![policy], None)
+        .await
+        .unwrap();
+    let (missed, _) = changes.subscribe(Some(loaded)).unwrap();
+    assert_eq!(
+        summary(&missed),
+        [(ObjectType::Policy, "test".to_string(), Operation::Update)]
+    );
+}
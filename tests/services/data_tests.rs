@@ -1,14 +1,22 @@
 use std::path::PathBuf;
+use std::sync::Arc;
 
 use crate::services::utils;
+use crate::services::utils::test_case;
 use std::error::Error;
 
 use cedar_agent::data::load_from_file::load_entities_from_file;
 use cedar_agent::data::memory::MemoryDataStore;
+use cedar_agent::data::{EntitySnapshot, Relation};
 use cedar_agent::schemas::authorization::AuthorizationCall;
-use cedar_agent::schemas::authorization::AuthorizationRequest;
-use cedar_agent::DataStore;
-use cedar_policy::Entities;
+use cedar_agent::schemas::authorization::{AuthorizationRequest, DecisionRef};
+use cedar_agent::schemas::data as schemas;
+use cedar_agent::test_cases::{TestCaseStore, TestGate};
+use cedar_agent::{DataStore, StoreError};
+use cedar_policy::{Entities, EntityUid, PolicySet};
+use rocket::serde::json::serde_json::{from_value, json};
+use rocket::serde::json::Value;
+use std::str::FromStr;
 
 #[tokio::test]
 async fn memory_tests() {
@@ -26,11 +34,565 @@ async fn memory_tests() {
         .update_entities(utils::parse_error_entities(), None)
         .await;
     assert!(error_entities.is_err());
-    store.delete_entities().await;
+    store.delete_entities().await.unwrap();
     let entities = store.get_entities().await;
     assert_eq!(entities.len(), 0);
 }
 
+#[tokio::test]
+async fn test_incremental_hierarchy() {
+    let store = MemoryDataStore::new();
+    let entity = |typ: &str, id: &str, parents: Vec<(&str, &str)>| {
+        json!({
+            "uid": { "type": typ, "id": id },
+            "attrs": {},
+            "parents": parents
+                .into_iter()
+                .map(|(typ, id)| json!({ "type": typ, "id": id }))
+                .collect::<Vec<_>>()
+        })
+    };
+    let uid = |typ: &str, id: &str| EntityUid::from_str(&format!("{}::\"{}\"", typ, id)).unwrap();
+    let entities = |values: Vec<Value>| from_value::<schemas::Entities>(json!(values)).unwrap();
+
+    store
+        .update_entities(
+            entities(vec![
+                entity("User", "alice", vec![("Group", "dev")]),
+                entity("Group", "dev", vec![]),
+            ]),
+            None,
+        )
+        .await
+        .unwrap();
+
+    // A new parent of the group becomes an ancestor of its members
+    store
+        .upsert_entities(
+            entities(vec![
+                entity("Group", "dev", vec![("Group", "eng")]),
+                entity("Group", "eng", vec![]),
+            ]),
+            None,
+        )
+        .await
+        .unwrap();
+    let stored = store.entities().await;
+    assert!(stored.is_ancestor_of(&uid("Group", "eng"), &uid("User", "alice")));
+    assert!(store.get_entity(&uid("Group", "eng")).await.is_some());
+
+    // Cycles are rejected and leave the store unchanged
+    let cycle = store
+        .upsert_entities(
+            entities(vec![entity("Group", "eng", vec![("User", "alice")])]),
+            None,
+        )
+        .await;
+    assert!(cycle.is_err());
+    assert!(store
+        .entities()
+        .await
+        .is_ancestor_of(&uid("Group", "eng"), &uid("User", "alice")));
+
+    // Removing an entity drops it from the ancestors of its descendants
+    let removed = store
+        .remove_entities(vec![uid("Group", "dev")])
+        .await
+        .unwrap();
+    assert_eq!(removed.len(), 1);
+    let stored = store.entities().await;
+    assert!(!stored.is_ancestor_of(&uid("Group", "eng"), &uid("User", "alice")));
+    assert!(stored.is_ancestor_of(&uid("Group", "dev"), &uid("User", "alice")));
+    assert!(store.get_entity(&uid("Group", "dev")).await.is_none());
+    assert_eq!(store.get_entities().await.len(), 2);
+}
+
+/// Test that snapshots recycled across accepted, rejected and failed writes match the
+/// stored entities, while the snapshots held by readers stay unchanged
+#[tokio::test]
+async fn test_incremental_snapshots() {
+    let test_cases = Arc::new(TestCaseStore::new());
+    let gate = Arc::new(TestGate::new(test_cases.clone()));
+    let store =
+        MemoryDataStore::with_integrity(schemas::IntegrityMode::Cascade).with_gate(gate.clone());
+    gate.publish_policies(Arc::new(
+        PolicySet::from_str(r#"permit(principal in Group::"dev", action, resource);"#).unwrap(),
+    ));
+    test_cases
+        .put_test_case(
+            "carol".to_string(),
+            test_case("User::\"carol\"", DecisionRef::Deny),
+        )
+        .await
+        .unwrap();
+    let entity = |typ: &str, id: &str, parents: Vec<(&str, &str)>| {
+        json!({
+            "uid": { "type": typ, "id": id },
+            "attrs": {},
+            "parents": parents
+                .into_iter()
+                .map(|(typ, id)| json!({ "type": typ, "id": id }))
+                .collect::<Vec<_>>()
+        })
+    };
+    let uid = |typ: &str, id: &str| EntityUid::from_str(&format!("{}::\"{}\"", typ, id)).unwrap();
+    let entities = |values: Vec<Value>| from_value::<schemas::Entities>(json!(values)).unwrap();
+
+    store
+        .update_entities(
+            entities(vec![
+                entity("User", "alice", vec![("Group", "dev")]),
+                entity("Group", "dev", vec![]),
+            ]),
+            None,
+        )
+        .await
+        .unwrap();
+    let held = store.entities().await;
+
+    // Every write publishes a new snapshot, recycling the previous one once it is released
+    store
+        .upsert_entities(
+            entities(vec![
+                entity("Group", "dev", vec![("Group", "eng")]),
+                entity("Group", "eng", vec![]),
+            ]),
+            None,
+        )
+        .await
+        .unwrap();
+    store
+        .upsert_entities(
+            entities(vec![entity("User", "bob", vec![("Group", "dev")])]),
+            None,
+        )
+        .await
+        .unwrap();
+    let stored = store.entities().await;
+    assert!(stored.is_ancestor_of(&uid("Group", "eng"), &uid("User", "alice")));
+    assert!(stored.is_ancestor_of(&uid("Group", "eng"), &uid("User", "bob")));
+    drop(stored);
+
+    // Cascading the removal updates the children of the removed entity too
+    store
+        .remove_entities(vec![uid("Group", "eng")])
+        .await
+        .unwrap();
+    let stored = store.entities().await;
+    assert!(stored.get(&uid("Group", "eng")).is_none());
+    assert!(!stored.is_ancestor_of(&uid("Group", "eng"), &uid("User", "alice")));
+    assert!(!stored.is_ancestor_of(&uid("Group", "eng"), &uid("User", "bob")));
+    assert!(stored.is_ancestor_of(&uid("Group", "dev"), &uid("User", "bob")));
+    drop(stored);
+
+    // A write rejected by the gate does not leak into the snapshots published after it
+    assert!(matches!(
+        store
+            .upsert_entities(
+                entities(vec![entity("User", "carol", vec![("Group", "dev")])]),
+                None,
+            )
+            .await,
+        Err(StoreError::Conflict { .. })
+    ));
+    for id in ["dave", "erin"] {
+        store
+            .upsert_entities(entities(vec![entity("User", id, vec![])]), None)
+            .await
+            .unwrap();
+    }
+    // A failed write does not either
+    assert!(store
+        .upsert_entities(
+            entities(vec![
+                entity("User", "frank", vec![]),
+                entity("Group", "dev", vec![("User", "bob")]),
+            ]),
+            None,
+        )
+        .await
+        .is_err());
+    store
+        .remove_entities(vec![uid("User", "dave")])
+        .await
+        .unwrap();
+
+    let stored = store.entities().await;
+    let mut published: Vec<String> = stored
+        .iter()
+        .map(|entity| entity.uid().to_string())
+        .collect();
+    published.sort();
+    let mut expected: Vec<String> = store
+        .get_entities()
+        .await
+        .into_iter()
+        .map(|entity| {
+            EntityUid::from_json(entity.get()["uid"].clone())
+                .unwrap()
+                .to_string()
+        })
+        .collect();
+    expected.sort();
+    assert_eq!(published, expected);
+    assert_eq!(
+        published,
+        [
+            "Group::\"dev\"",
+            "User::\"alice\"",
+            "User::\"bob\"",
+            "User::\"erin\""
+        ]
+    );
+
+    // The snapshot taken before the writes is left as it was
+    assert_eq!(held.iter().count(), 2);
+    assert!(held.get(&uid("Group", "eng")).is_none());
+    assert!(!held.is_ancestor_of(&uid("Group", "eng"), &uid("User", "alice")));
+}
+
+#[tokio::test]
+async fn test_single_entity_by_uid() {
+    let store = MemoryDataStore::new();
+    let user = EntityUid::from_str(r#"User::"x""#).unwrap();
+    let table = EntityUid::from_str(r#"Table::"x""#).unwrap();
+    for uid in [&user, &table] {
+        let entity = from_value::<schemas::Entity>(json!({
+            "uid": { "type": uid.type_name().to_string(), "id": "x" },
+            "attrs": {},
+            "parents": []
+        }))
+        .unwrap();
+        store.upsert_entity(entity, None).await.unwrap();
+    }
+
+    // Entities with the same id but different types don't collide
+    assert_eq!(store.get_entities().await.len(), 2);
+    let removed = store.remove_entity(&user).await.unwrap();
+    assert!(removed.is_some());
+    assert!(store.get_entity(&user).await.is_none());
+    assert!(store.get_entity(&table).await.is_some());
+    assert!(store.remove_entity(&user).await.unwrap().is_none());
+}
+
+#[tokio::test]
+async fn test_query_entities() {
+    let store = MemoryDataStore::new();
+    store
+        .update_entities(utils::entities(), None)
+        .await
+        .unwrap();
+    let query = |query: Value| async {
+        let query: schemas::EntityQuery = from_value(query).unwrap();
+        store.query_entities(&query).await.unwrap()
+    };
+
+    let actions = query(json!({ "entity_type": "Action", "id_prefix": "document:" })).await;
+    assert_eq!(actions.total, 5);
+
+    let page = query(json!({ "entity_type": "Action", "offset": 1, "limit": 2 })).await;
+    assert_eq!(page.total, 5);
+    assert_eq!(page.entities.len(), 2);
+    assert_eq!(page.offset, 1);
+
+    let direct = query(json!({ "parent": { "type": "Action", "id": "document:delete" } })).await;
+    assert_eq!(direct.total, 1);
+    let transitive = query(json!({
+        "parent": { "type": "Action", "id": "document:delete" },
+        "transitive": true
+    }))
+    .await;
+    assert_eq!(transitive.total, 2);
+
+    let parents = query(json!({ "entity_type": "Action", "has_children": true })).await;
+    assert_eq!(parents.total, 3);
+
+    let users = query(json!({
+        "attributes": [
+            { "attribute": "jobLevel", "op": "gte", "value": 5 },
+            { "attribute": "department", "value": "HardwareEngineering" }
+        ],
+        "fields": ["attrs.jobLevel"]
+    }))
+    .await;
+    assert_eq!(users.total, 1);
+    let user = users.entities.iter().next().unwrap().get();
+    assert_eq!(user["attrs"], json!({ "jobLevel": 5 }));
+    assert!(user.get("parents").is_none());
+
+    let none = query(json!({
+        "attributes": [{ "attribute": "jobLevel", "op": "lt", "value": 5 }]
+    }))
+    .await;
+    assert_eq!(none.total, 0);
+}
+
+#[tokio::test]
+async fn test_entity_relations() {
+    let store = MemoryDataStore::new();
+    store
+        .update_entities(utils::entities(), None)
+        .await
+        .unwrap();
+    let uid = |id: &str| EntityUid::from_str(&format!("Action::\"{}\"", id)).unwrap();
+    let related = |id: &'static str, relation: Relation| {
+        let store = &store;
+        async move {
+            store
+                .related_entities(&uid(id), relation)
+                .await
+                .unwrap()
+                .iter()
+                .map(|uid| uid.id().unescaped().to_string())
+                .collect::<Vec<_>>()
+        }
+    };
+
+    assert_eq!(
+        related("document:create", Relation::Parents).await,
+        ["document:update"]
+    );
+    assert_eq!(
+        related("document:create", Relation::Ancestors).await,
+        ["document:delete", "document:update"]
+    );
+    assert_eq!(
+        related("document:delete", Relation::Children).await,
+        ["document:update"]
+    );
+    assert_eq!(
+        related("document:delete", Relation::Descendants).await,
+        ["document:create", "document:update"]
+    );
+    assert!(store
+        .related_entities(&uid("missing"), Relation::Parents)
+        .await
+        .is_none());
+
+    // Edges are added and removed without rewriting the entity
+    store
+        .add_parent(&uid("document:list"), &uid("document:delete"), None)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(
+        related("document:list", Relation::Ancestors).await,
+        ["document:delete", "document:get"]
+    );
+    store
+        .remove_parent(&uid("document:create"), &uid("document:update"), None)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(
+        related("document:delete", Relation::Descendants).await,
+        ["document:list", "document:update"]
+    );
+
+    // Edges that would close a cycle are rejected
+    let cycle = store
+        .add_parent(&uid("document:delete"), &uid("document:list"), None)
+        .await;
+    assert!(cycle.is_err());
+}
+
+#[tokio::test]
+async fn test_referential_integrity() {
+    let entities = |values: Value| from_value::<schemas::Entities>(values).unwrap();
+    let hierarchy = || {
+        entities(json!([
+            { "uid": { "type": "User", "id": "alice" }, "attrs": {}, "parents": [{ "type": "Group", "id": "dev" }] },
+            { "uid": { "type": "Group", "id": "dev" }, "attrs": {}, "parents": [] }
+        ]))
+    };
+    let dangling = || {
+        entities(json!([
+            { "uid": { "type": "User", "id": "bob" }, "attrs": {}, "parents": [{ "type": "Group", "id": "ops" }] }
+        ]))
+    };
+    let group = EntityUid::from_str("Group::\"dev\"").unwrap();
+
+    // Missing parents and deletions of referenced entities are rejected
+    let store = MemoryDataStore::with_integrity(schemas::IntegrityMode::Reject);
+    store.update_entities(hierarchy(), None).await.unwrap();
+    assert!(store.upsert_entities(dangling(), None).await.is_err());
+    assert!(store.remove_entity(&group).await.is_err());
+    assert_eq!(store.get_entities().await.len(), 2);
+
+    // Deletions cascade to the parents of the children
+    let store = MemoryDataStore::with_integrity(schemas::IntegrityMode::Cascade);
+    store.update_entities(hierarchy(), None).await.unwrap();
+    store.remove_entity(&group).await.unwrap().unwrap();
+    let alice = store
+        .get_entity(&EntityUid::from_str("User::\"alice\"").unwrap())
+        .await
+        .unwrap();
+    assert_eq!(alice.get()["parents"], json!([]));
+    assert!(store.integrity_report().await.dangling.is_empty());
+
+    // A cascading deletion is undone with the rest of a failing batch
+    store.update_entities(hierarchy(), None).await.unwrap();
+    let failing = from_value::<schemas::DeltaBatch>(json!({
+        "sequence": 1,
+        "operations": [
+            { "op": "delete", "uid": { "type": "Group", "id": "dev" } },
+            { "op": "upsert", "entity": { "uid": { "type": "User", "id": "bob" }, "attrs": {}, "parents": [{ "type": "Group" }] } }
+        ]
+    }))
+    .unwrap();
+    assert!(store.apply_delta(failing, None).await.is_err());
+    assert!(store.get_entity(&group).await.is_some());
+    let alice = store
+        .get_entity(&EntityUid::from_str("User::\"alice\"").unwrap())
+        .await
+        .unwrap();
+    assert_eq!(
+        alice.get()["parents"],
+        json!([{ "type": "Group", "id": "dev" }])
+    );
+    assert!(store
+        .entities()
+        .await
+        .is_ancestor_of(&group, &EntityUid::from_str("User::\"alice\"").unwrap()));
+    assert_eq!(store.sync_status().await.sequence, None);
+
+    // Dangling references are kept and reported
+    let store = MemoryDataStore::new();
+    store.update_entities(hierarchy(), None).await.unwrap();
+    store.upsert_entities(dangling(), None).await.unwrap();
+    store.remove_entity(&group).await.unwrap().unwrap();
+    let report = store.integrity_report().await;
+    assert_eq!(report.mode, schemas::IntegrityMode::Warn);
+    let missing: Vec<(String, String)> = report
+        .dangling
+        .into_iter()
+        .map(|reference| (reference.entity.id, reference.parent.id))
+        .collect();
+    assert_eq!(
+        missing,
+        [
+            ("alice".to_string(), "dev".to_string()),
+            ("bob".to_string(), "ops".to_string())
+        ]
+    );
+    assert!(report.cycles.is_empty());
+}
+
+#[tokio::test]
+async fn test_delta_sync() {
+    let store = MemoryDataStore::new();
+    let batch = |value: Value| from_value::<schemas::DeltaBatch>(value).unwrap();
+    assert_eq!(store.sync_status().await.sequence, None);
+
+    let first = batch(json!({
+        "sequence": 10,
+        "operations": [
+            { "op": "upsert", "entity": { "uid": { "type": "User", "id": "alice" }, "attrs": { "level": 1 }, "parents": [{ "type": "Group", "id": "dev" }] } },
+            { "op": "upsert", "entity": { "uid": { "type": "Group", "id": "dev" }, "attrs": {}, "parents": [] } },
+            { "op": "upsert", "entity": { "uid": { "type": "User", "id": "alice" }, "attrs": { "level": 2 }, "parents": [{ "type": "Group", "id": "dev" }] } }
+        ]
+    }));
+    let result = store.apply_delta(first.clone(), None).await.unwrap();
+    assert!(result.applied);
+    assert_eq!((result.sequence, result.upserted), (10, 3));
+    let alice = EntityUid::from_str("User::\"alice\"").unwrap();
+    assert_eq!(
+        store.get_entity(&alice).await.unwrap().get()["attrs"]["level"],
+        json!(2)
+    );
+
+    // Replaying a batch is a no-op
+    let replay = store.apply_delta(first, None).await.unwrap();
+    assert!(!replay.applied);
+    assert_eq!(replay.sequence, 10);
+
+    // A failing operation rejects the whole batch
+    let failing = batch(json!({
+        "sequence": 11,
+        "operations": [
+            { "op": "delete", "uid": { "type": "User", "id": "alice" } },
+            { "op": "upsert", "entity": { "uid": { "type": "Group", "id": "dev" }, "attrs": {}, "parents": [{ "type": "User", "id": "alice" }] } },
+            { "op": "upsert", "entity": { "uid": { "type": "User", "id": "alice" }, "attrs": {}, "parents": [{ "type": "Group", "id": "dev" }] } }
+        ]
+    }));
+    assert!(store.apply_delta(failing, None).await.is_err());
+    assert_eq!(store.get_entities().await.len(), 2);
+    assert_eq!(store.sync_status().await.sequence, Some(10));
+
+    let second = batch(json!({
+        "sequence": 12,
+        "operations": [
+            { "op": "delete", "uid": { "type": "User", "id": "alice" } },
+            { "op": "delete", "uid": { "type": "User", "id": "missing" } }
+        ]
+    }));
+    let result = store.apply_delta(second, None).await.unwrap();
+    assert_eq!(result.deleted, 1);
+    assert!(store.get_entity(&alice).await.is_none());
+
+    // Full reloads keep the sequence
+    store
+        .update_entities(utils::entities(), None)
+        .await
+        .unwrap();
+    assert_eq!(store.sync_status().await.sequence, Some(12));
+}
+
+/// Test that the writes making a policy test case fail are rejected, leaving the store
+/// unchanged
+#[tokio::test]
+async fn test_gated_writes() {
+    let test_cases = Arc::new(TestCaseStore::new());
+    let gate = Arc::new(TestGate::new(test_cases.clone()));
+    let store = MemoryDataStore::new().with_gate(gate.clone());
+    store
+        .update_entities(utils::entities(), None)
+        .await
+        .unwrap();
+    gate.publish_policies(Arc::new(
+        PolicySet::from_str(r#"permit(principal in Group::"dev", action, resource);"#).unwrap(),
+    ));
+    test_cases
+        .put_test_case(
+            "alice".to_string(),
+            test_case("User::\"alice\"", DecisionRef::Deny),
+        )
+        .await
+        .unwrap();
+    let alice = EntityUid::from_str("User::\"alice\"").unwrap();
+    let dev = EntityUid::from_str("Group::\"dev\"").unwrap();
+    let entities = |value: Value| from_value::<schemas::Entities>(value).unwrap();
+
+    let rejected = store
+        .upsert_entities(
+            entities(json!([
+                { "uid": { "type": "Group", "id": "dev" }, "attrs": {}, "parents": [] },
+                { "uid": { "type": "User", "id": "alice" }, "attrs": {}, "parents": [{ "type": "Group", "id": "dev" }] }
+            ])),
+            None,
+        )
+        .await;
+    assert!(matches!(rejected, Err(StoreError::Conflict { .. })));
+    assert!(store.get_entity(&alice).await.is_none());
+    assert!(store.get_entity(&dev).await.is_none());
+    assert_eq!(store.get_entities().await.len(), 8);
+    assert_eq!(store.entities().await.iter().count(), 8);
+
+    // Writes keeping the test cases passing go through
+    store
+        .upsert_entities(
+            entities(json!([
+                { "uid": { "type": "User", "id": "alice" }, "attrs": {}, "parents": [] }
+            ])),
+            None,
+        )
+        .await
+        .unwrap();
+    assert!(store.entities().await.get(&alice).is_some());
+    store.delete_entities().await.unwrap();
+    assert_eq!(store.entities().await.iter().count(), 0);
+}
+
 #[tokio::test]
 async fn test_load_entities_from_file() {
     let entities = load_entities_from_file(PathBuf::from("./examples/data.json"))
@@ -182,11 +744,12 @@ async fn test_combine_entities_with_additional_entities() {
 
     match query {
         Ok(req) => {
-            match req.get_request_entities(Entities::from_json_str(&stored_entities, None).unwrap())
-            {
+            match req.get_request_entities(EntitySnapshot::from(
+                Entities::from_json_str(&stored_entities, None).unwrap(),
+            )) {
                 Ok((_request, entities)) => {
                     assert_eq!(
-                        entities,
+                        *entities,
                         Entities::from_json_str(&expected_result, None).unwrap()
                     )
                 }
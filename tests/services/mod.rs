@@ -1,4 +1,7 @@
+mod authorization_tests;
+mod changes_tests;
 mod data_tests;
 mod policies_tests;
 mod schema_tests;
+mod test_cases_tests;
 mod utils;
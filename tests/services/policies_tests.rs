@@ -1,14 +1,22 @@
+use std::collections::BTreeSet;
 use std::path::PathBuf;
 use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
 
-use cedar_policy::PolicyId;
+use cedar_policy::{Entities, PolicyId, PolicySet, PrincipalConstraint, Schema};
+use rocket::serde::json::serde_json::json;
 
 use crate::services::utils::*;
 
+use cedar_agent::policies::analysis::analyze;
 use cedar_agent::policies::load_from_file::load_policies_from_file;
 use cedar_agent::policies::memory::MemoryPolicyStore;
-use cedar_agent::schemas::policies::PolicyUpdate;
-use cedar_agent::PolicyStore;
+use cedar_agent::schemas::analysis::FindingKind;
+use cedar_agent::schemas::authorization::{DecisionRef, EffectRef};
+use cedar_agent::schemas::policies::{Policy, PolicyQuery, PolicyUpdate, ScopeOperator};
+use cedar_agent::test_cases::{TestCaseStore, TestGate};
+use cedar_agent::{PolicyStore, StoreError};
 
 #[tokio::test]
 async fn memory_tests() {
@@ -55,6 +63,7 @@ async fn memory_tests() {
             "test".to_string(),
             PolicyUpdate {
                 content: approve_admin_policy(None).content,
+                ..Default::default()
             },
             None,
         )
@@ -71,6 +80,7 @@ async fn memory_tests() {
             "test".to_string(),
             PolicyUpdate {
                 content: parse_error_policy().content,
+                ..Default::default()
             },
             None,
         )
@@ -93,6 +103,51 @@ async fn memory_tests() {
         .is_none());
 }
 
+#[tokio::test]
+async fn test_policy_set_snapshots() {
+    let store = MemoryPolicyStore::new();
+    store
+        .update_policies(vec![approve_all_policy(None)], None)
+        .await
+        .unwrap();
+    let loaded = store.policy_set().await;
+
+    store
+        .update_policy(
+            "test".to_string(),
+            PolicyUpdate {
+                content: approve_admin_policy(None).content,
+                ..Default::default()
+            },
+            None,
+        )
+        .await
+        .unwrap();
+    store
+        .create_policy(&approve_all_policy(Some("other".to_string())), None)
+        .await
+        .unwrap();
+
+    // Snapshots taken before a write are left unchanged
+    let id = PolicyId::from_str("test").unwrap();
+    assert_eq!(loaded.policies().count(), 1);
+    assert!(matches!(
+        loaded.policy(&id).unwrap().principal_constraint(),
+        PrincipalConstraint::Any
+    ));
+
+    let updated = store.policy_set().await;
+    assert_eq!(updated.policies().count(), 2);
+    assert!(matches!(
+        updated.policy(&id).unwrap().principal_constraint(),
+        PrincipalConstraint::Eq(_)
+    ));
+
+    store.delete_policy("other").await.unwrap();
+    assert_eq!(store.policy_set().await.policies().count(), 1);
+    assert_eq!(updated.policies().count(), 2);
+}
+
 #[tokio::test]
 async fn test_load_policies_from_file() {
     let policies = load_policies_from_file(PathBuf::from("./examples/policies.json"))
@@ -101,3 +156,438 @@ async fn test_load_policies_from_file() {
     assert_eq!(policies.len(), 3);
     assert_eq!(policies[0].id, "admins-policy".to_string());
 }
+
+#[tokio::test]
+async fn test_query_policies() {
+    let store = MemoryPolicyStore::new();
+    let policy = |id: &str, content: &str| Policy {
+        id: id.to_string(),
+        content: content.to_string(),
+        ..Default::default()
+    };
+    store
+        .update_policies(
+            vec![
+                policy(
+                    "select",
+                    r#"@owner("team-x") permit(principal, action == Action::"SELECT", resource);"#,
+                ),
+                policy(
+                    "read",
+                    r#"@owner("team-y") permit(principal in Role::"Reader", action in [Action::"read"], resource);"#,
+                ),
+                policy(
+                    "deny",
+                    r#"@reviewed("") forbid(principal == User::"mallory", action, resource);"#,
+                ),
+            ],
+            None,
+        )
+        .await
+        .unwrap();
+    let entities = Entities::from_json_value(
+        json!([
+            { "uid": { "type": "Action", "id": "SELECT" }, "attrs": {}, "parents": [{ "type": "Action", "id": "read" }] },
+            { "uid": { "type": "Action", "id": "read" }, "attrs": {}, "parents": [] }
+        ]),
+        None,
+    )
+    .unwrap();
+    let ids = |policies: Vec<Policy>| -> Vec<String> {
+        policies.into_iter().map(|policy| policy.id).collect()
+    };
+
+    let all = store
+        .query_policies(&PolicyQuery::default(), &entities)
+        .await
+        .unwrap();
+    assert_eq!(ids(all.clone()), vec!["deny", "read", "select"]);
+    let select = all.iter().find(|policy| policy.id == "select").unwrap();
+    assert_eq!(select.effect, Some(EffectRef::Permit));
+    assert_eq!(select.annotations.get("owner").unwrap(), "team-x");
+    let scope = select.scope.as_ref().unwrap();
+    assert_eq!(scope.principal.operator, ScopeOperator::Any);
+    assert_eq!(scope.action.operator, ScopeOperator::Eq);
+    assert_eq!(scope.action.entities, vec![r#"Action::"SELECT""#]);
+
+    // Action groups are followed through the entity hierarchy
+    let query = PolicyQuery {
+        action: Some(r#"Action::"SELECT""#.to_string()),
+        ..Default::default()
+    };
+    let matched = store.query_policies(&query, &entities).await.unwrap();
+    assert_eq!(ids(matched), vec!["deny", "read", "select"]);
+
+    let query = PolicyQuery {
+        effect: Some(EffectRef::Forbid),
+        ..Default::default()
+    };
+    let matched = store.query_policies(&query, &entities).await.unwrap();
+    assert_eq!(ids(matched), vec!["deny"]);
+
+    let query = PolicyQuery {
+        principal: Some(r#"User::"alice""#.to_string()),
+        annotation: vec!["owner".to_string()],
+        ..Default::default()
+    };
+    let matched = store.query_policies(&query, &entities).await.unwrap();
+    assert_eq!(ids(matched), vec!["select"]);
+
+    let query = PolicyQuery {
+        annotation: vec!["owner:team-y".to_string()],
+        ..Default::default()
+    };
+    let matched = store.query_policies(&query, &entities).await.unwrap();
+    assert_eq!(ids(matched), vec!["read"]);
+
+    let query = PolicyQuery {
+        principal: Some("not a uid".to_string()),
+        ..Default::default()
+    };
+    assert!(store.query_policies(&query, &entities).await.is_err());
+}
+
+#[test]
+fn test_analyze_policies() {
+    let policy_set = PolicySet::from_policies(
+        [
+            ("p-admin", r#"permit(principal in Role::"Admin", action in [Action::"view", Action::"edit"], resource);"#),
+            ("p-alice", r#"permit(principal == User::"alice", action == Action::"view", resource);"#),
+            ("p-bob", r#"permit(principal == User::"bob", action == Action::"view", resource) unless { resource == Document::"public" };"#),
+            ("f-bob", r#"forbid(principal == User::"bob", action, resource);"#),
+            ("p-dup1", r#"permit(principal, action == Action::"edit", resource is Document);"#),
+            ("p-dup2", r#"permit(principal, action == Action::"edit", resource is Document);"#),
+            ("p-never", r#"permit(principal, action == Action::"view", resource) when { false };"#),
+        ]
+        .into_iter()
+        .map(|(id, content)| {
+            cedar_policy::Policy::parse(Some(PolicyId::from_str(id).unwrap()), content).unwrap()
+        }),
+    )
+    .unwrap();
+    let (schema, _) = Schema::from_cedarschema_str(
+        r#"
+        entity Role;
+        entity User in [Role];
+        entity Document;
+        entity Folder;
+        action view, edit appliesTo { principal: User, resource: Document };
+        action archive appliesTo { principal: User, resource: Folder };
+        "#,
+    )
+    .unwrap();
+    let entities = Entities::from_json_value(
+        json!([
+            { "uid": { "type": "User", "id": "alice" }, "attrs": {}, "parents": [{ "type": "Role", "id": "Admin" }] }
+        ]),
+        None,
+    )
+    .unwrap();
+
+    let report = analyze(&policy_set, Some(&schema), &entities);
+    assert_eq!(report.policies, 7);
+    assert!(report.schema);
+    let findings = |kind: FindingKind| -> Vec<(Option<&str>, Option<&str>, Option<&str>)> {
+        report
+            .findings
+            .iter()
+            .filter(|finding| finding.kind == kind)
+            .map(|finding| {
+                (
+                    finding.policy.as_deref(),
+                    finding.related_policy.as_deref(),
+                    finding.subject.as_deref(),
+                )
+            })
+            .collect()
+    };
+    assert_eq!(
+        findings(FindingKind::Redundant),
+        vec![
+            (Some("p-alice"), Some("p-admin"), None),
+            (Some("p-dup2"), Some("p-dup1"), None)
+        ]
+    );
+    assert_eq!(
+        findings(FindingKind::Overridden),
+        vec![(Some("p-bob"), Some("f-bob"), None)]
+    );
+    assert_eq!(
+        findings(FindingKind::Unsatisfiable),
+        vec![(Some("p-never"), None, None)]
+    );
+    assert!(
+        findings(FindingKind::Invalid).is_empty(),
+        "{:?}",
+        report.findings
+    );
+    assert_eq!(
+        findings(FindingKind::UnusedAction),
+        vec![(None, None, Some(r#"Action::"archive""#))]
+    );
+    assert_eq!(
+        findings(FindingKind::UnusedEntityType),
+        vec![(None, None, Some("Folder"))]
+    );
+    assert!(report.findings.iter().all(|f| !f.explanation.is_empty()));
+
+    // Without a schema only overlapping policies are reported
+    let report = analyze(&policy_set, None, &entities);
+    assert!(!report.schema);
+    assert_eq!(report.findings.len(), 3);
+}
+
+#[tokio::test]
+async fn test_shadow_policies() {
+    let store = MemoryPolicyStore::new();
+    store
+        .update_policies(vec![approve_admin_policy(Some("admin".to_string()))], None)
+        .await
+        .unwrap();
+    assert!(store.shadow_policy_set().await.is_none());
+    assert!(store.promote_shadow_policies().await.unwrap().is_none());
+
+    assert!(store
+        .update_shadow_policies(vec![parse_error_policy()], None)
+        .await
+        .is_err());
+    store
+        .update_shadow_policies(vec![approve_all_policy(Some("all".to_string()))], None)
+        .await
+        .unwrap();
+    let shadow = store.shadow_policy_set().await.unwrap();
+    assert!(shadow.policy(&PolicyId::from_str("all").unwrap()).is_some());
+    // The active policies are left untouched
+    assert_eq!(store.get_policies().await[0].id, "admin");
+
+    let promoted = store.promote_shadow_policies().await.unwrap().unwrap();
+    assert_eq!(promoted.len(), 1);
+    assert_eq!(promoted[0].id, "all");
+    assert!(store.get_policy("admin").await.is_err());
+    assert!(store.shadow_policy_set().await.is_none());
+    assert!(store.get_shadow_policies().await.is_none());
+
+    store
+        .update_shadow_policies(Vec::new(), None)
+        .await
+        .unwrap();
+    assert!(store.delete_shadow_policies().await.unwrap().is_empty());
+    assert!(store.delete_shadow_policies().await.is_none());
+}
+
+#[tokio::test]
+async fn test_disable_policies() {
+    let store = MemoryPolicyStore::new();
+    store
+        .update_policies(
+            vec![
+                approve_all_policy(Some("all".to_string())),
+                approve_admin_policy(Some("admin".to_string())),
+            ],
+            None,
+        )
+        .await
+        .unwrap();
+    let all = PolicyId::from_str("all").unwrap();
+
+    assert!(store.disable_policy("missing", None).await.is_err());
+    let disabled = store.disable_policy("all", None).await.unwrap();
+    assert!(!disabled.enabled);
+    assert!(disabled.disabled_until.is_none());
+    // Disabled policies are still stored, but out of the policy set
+    assert_eq!(store.get_policies().await.len(), 2);
+    assert!(store.policy_set().await.policy(&all).is_none());
+
+    // Updating a disabled policy keeps it disabled
+    store
+        .update_policy(
+            "all".to_string(),
+            PolicyUpdate {
+                content: "forbid(principal,action,resource);".to_string(),
+                ..Default::default()
+            },
+            None,
+        )
+        .await
+        .unwrap();
+    assert!(!store.get_policy("all").await.unwrap().enabled);
+    assert!(store.policy_set().await.policy(&all).is_none());
+
+    let enabled = store.enable_policy("all").await.unwrap();
+    assert!(enabled.enabled);
+    assert!(store.policy_set().await.policy(&all).is_some());
+
+    let disabled = store
+        .disable_policy("all", Some(Duration::from_millis(100)))
+        .await
+        .unwrap();
+    assert!(disabled.disabled_until.is_some());
+    assert!(store.policy_set().await.policy(&all).is_none());
+    tokio::time::sleep(Duration::from_millis(300)).await;
+    assert!(store.get_policy("all").await.unwrap().enabled);
+    assert!(store.policy_set().await.policy(&all).is_some());
+}
+
+/// Test that the policy writes making a policy test case fail are rejected, leaving the
+/// policies unchanged
+#[tokio::test]
+async fn test_gated_policies() {
+    let test_cases = Arc::new(TestCaseStore::new());
+    let store = MemoryPolicyStore::new().with_gate(Arc::new(TestGate::new(test_cases.clone())));
+    store
+        .update_policies(vec![approve_admin_policy(Some("admin".to_string()))], None)
+        .await
+        .unwrap();
+    test_cases
+        .put_test_case(
+            "admin".to_string(),
+            test_case("User::\"admin@domain.com\"", DecisionRef::Allow),
+        )
+        .await
+        .unwrap();
+    test_cases
+        .put_test_case(
+            "guest".to_string(),
+            test_case("User::\"guest\"", DecisionRef::Deny),
+        )
+        .await
+        .unwrap();
+    let all = PolicyId::from_str("all").unwrap();
+    let admin = PolicyId::from_str("admin").unwrap();
+
+    let created = store
+        .create_policy(&approve_all_policy(Some("all".to_string())), None)
+        .await;
+    assert!(matches!(created, Err(StoreError::Conflict { .. })));
+    assert_eq!(store.get_policies().await.len(), 1);
+    assert!(store.policy_set().await.policy(&all).is_none());
+
+    let disabled = store.disable_policy("admin", None).await;
+    assert!(matches!(disabled, Err(StoreError::Conflict { .. })));
+    assert!(store.get_policy("admin").await.unwrap().enabled);
+    assert!(store.policy_set().await.policy(&admin).is_some());
+
+    // A rejected promotion keeps the shadow policies
+    store
+        .update_shadow_policies(vec![approve_all_policy(Some("all".to_string()))], None)
+        .await
+        .unwrap();
+    let promoted = store.promote_shadow_policies().await;
+    assert!(matches!(promoted, Err(StoreError::Conflict { .. })));
+    assert!(store.shadow_policy_set().await.is_some());
+    assert!(store.policy_set().await.policy(&admin).is_some());
+
+    // Changes keeping the test cases passing go through
+    store
+        .update_policies(
+            vec![
+                approve_admin_policy(Some("admin".to_string())),
+                schema_valid_policy(Some("editor".to_string())),
+            ],
+            None,
+        )
+        .await
+        .unwrap();
+    assert_eq!(store.policy_set().await.policies().count(), 2);
+}
+
+#[tokio::test]
+async fn test_policy_metadata() {
+    let store = MemoryPolicyStore::new();
+    let policy = Policy {
+        description: Some("Lets the admins do anything".to_string()),
+        owner: Some("team-x".to_string()),
+        tags: BTreeSet::from(["admin".to_string(), "prod".to_string()]),
+        claimed_author: Some("alice".to_string()),
+        ..approve_admin_policy(Some("admin".to_string()))
+    };
+    let created = store.create_policy(&policy, None).await.unwrap();
+    assert_eq!(created.owner.as_deref(), Some("team-x"));
+    assert_eq!(created.claimed_author.as_deref(), Some("alice"));
+    assert!(created.created_at.is_some());
+    assert_eq!(created.created_at, created.updated_at);
+
+    // Missing details are kept by single policy updates
+    let updated = store
+        .update_policy(
+            "admin".to_string(),
+            PolicyUpdate {
+                content: approve_all_policy(None).content,
+                owner: Some("team-y".to_string()),
+                claimed_author: Some("bob".to_string()),
+                ..Default::default()
+            },
+            None,
+        )
+        .await
+        .unwrap();
+    assert_eq!(updated.owner.as_deref(), Some("team-y"));
+    assert_eq!(updated.description, policy.description);
+    assert_eq!(updated.tags.len(), 2);
+    assert_eq!(updated.created_at, created.created_at);
+    assert_eq!(updated.claimed_author.as_deref(), Some("bob"));
+
+    // Rewriting the policy as it is keeps its metadata
+    let rewritten = store
+        .update_policy(
+            "admin".to_string(),
+            PolicyUpdate {
+                content: approve_all_policy(None).content,
+                claimed_author: Some("carol".to_string()),
+                ..Default::default()
+            },
+            None,
+        )
+        .await
+        .unwrap();
+    assert_eq!(rewritten.updated_at, updated.updated_at);
+    assert_eq!(rewritten.claimed_author.as_deref(), Some("bob"));
+
+    // Bulk updates keep the metadata of unchanged policies
+    let mut unchanged = store.get_policy("admin").await.unwrap();
+    unchanged.claimed_author = Some("carol".to_string());
+    let policies = store
+        .update_policies(
+            vec![unchanged, approve_all_policy(Some("all".to_string()))],
+            None,
+        )
+        .await
+        .unwrap();
+    let admin = policies.iter().find(|policy| policy.id == "admin").unwrap();
+    assert_eq!(admin.claimed_author.as_deref(), Some("bob"));
+
+    let entities = Entities::empty();
+    let query = |query: PolicyQuery| {
+        let store = &store;
+        let entities = &entities;
+        async move {
+            store
+                .query_policies(&query, entities)
+                .await
+                .unwrap()
+                .into_iter()
+                .map(|policy| policy.id)
+                .collect::<Vec<String>>()
+        }
+    };
+    let by_owner = PolicyQuery {
+        owner: Some("team-y".to_string()),
+        ..Default::default()
+    };
+    assert_eq!(query(by_owner).await, vec!["admin"]);
+    let by_tags = PolicyQuery {
+        tag: vec!["prod".to_string(), "admin".to_string()],
+        ..Default::default()
+    };
+    assert_eq!(query(by_tags).await, vec!["admin"]);
+    let by_text = PolicyQuery {
+        search: Some("ADMINS".to_string()),
+        ..Default::default()
+    };
+    assert_eq!(query(by_text).await, vec!["admin"]);
+    let by_id = PolicyQuery {
+        search: Some("al".to_string()),
+        ..Default::default()
+    };
+    assert_eq!(query(by_id).await, vec!["all"]);
+}
@@ -4,7 +4,9 @@ use cedar_agent::data::memory::MemoryDataStore;
 use cedar_agent::policies::memory::MemoryPolicyStore;
 use cedar_agent::schema::load_from_file::load_schema_from_file;
 use cedar_agent::schema::memory::MemorySchemaStore;
+use cedar_agent::schemas::schema::Schema;
 use cedar_agent::{DataStore, PolicyStore, SchemaStore};
+use rocket::serde::json::serde_json::{from_str, json};
 
 use crate::services::utils;
 
@@ -80,3 +82,125 @@ async fn test_validate_entities() {
         .await;
     assert!(invalid_entities.is_err());
 }
+
+#[tokio::test]
+async fn test_coerce_attribute() {
+    let schema: Schema = from_str(
+        r#"{
+          "App": {
+            "commonTypes": {
+              "Tags": { "type": "Set", "element": { "type": "String" } }
+            },
+            "entityTypes": {
+              "User": {
+                "shape": {
+                  "type": "Record",
+                  "attributes": {
+                    "level": { "type": "Long" },
+                    "active": { "type": "Boolean" },
+                    "ip": { "type": "Extension", "name": "ipaddr" },
+                    "manager": { "type": "Entity", "name": "User" },
+                    "tags": { "type": "Tags" },
+                    "profile": {
+                      "type": "Record",
+                      "attributes": { "age": { "type": "Long" } }
+                    }
+                  }
+                }
+              }
+            }
+          }
+        }"#,
+    )
+    .unwrap();
+
+    assert_eq!(
+        schema.coerce_attribute("App::User", "level", json!("5")),
+        json!(5)
+    );
+    assert_eq!(
+        schema.coerce_attribute("App::User", "active", json!("true")),
+        json!(true)
+    );
+    assert_eq!(
+        schema.coerce_attribute("App::User", "ip", json!("10.0.0.1")),
+        json!({ "__extn": { "fn": "ip", "arg": "10.0.0.1" } })
+    );
+    assert_eq!(
+        schema.coerce_attribute("App::User", "manager", json!("alice")),
+        json!({ "__entity": { "type": "App::User", "id": "alice" } })
+    );
+    assert_eq!(
+        schema.coerce_attribute("App::User", "tags", json!([1, "a"])),
+        json!(["1", "a"])
+    );
+    assert_eq!(
+        schema.coerce_attribute("App::User", "profile", json!({ "age": "42" })),
+        json!({ "age": 42 })
+    );
+    // Values that can't be coerced are left to the validation
+    assert_eq!(
+        schema.coerce_attribute("App::User", "level", json!("high")),
+        json!("high")
+    );
+    assert_eq!(
+        schema.coerce_attribute("App::User", "unknown", json!("5")),
+        json!("5")
+    );
+}
+
+/// Test that extension types referenced by name resolve to extension types, not entities
+#[tokio::test]
+async fn test_resolve_extension_types() {
+    let schema: Schema = from_str(
+        r#"{
+          "App": {
+            "entityTypes": {
+              "User": {
+                "shape": {
+                  "type": "Record",
+                  "attributes": {
+                    "ip": { "type": "EntityOrCommon", "name": "ipaddr" },
+                    "limit": { "type": "EntityOrCommon", "name": "decimal" },
+                    "since": { "type": "EntityOrCommon", "name": "__cedar::datetime" },
+                    "timeout": { "type": "duration" },
+                    "owner": { "type": "EntityOrCommon", "name": "User" }
+                  }
+                }
+              }
+            }
+          }
+        }"#,
+    )
+    .unwrap();
+
+    assert_eq!(
+        schema.attribute_type("App::User", "ip"),
+        Some(json!({ "type": "Extension", "name": "ipaddr" }))
+    );
+    assert_eq!(
+        schema.attribute_type("App::User", "limit"),
+        Some(json!({ "type": "Extension", "name": "decimal" }))
+    );
+    assert_eq!(
+        schema.attribute_type("App::User", "since"),
+        Some(json!({ "type": "Extension", "name": "datetime" }))
+    );
+    assert_eq!(
+        schema.attribute_type("App::User", "timeout"),
+        Some(json!({ "type": "Extension", "name": "duration" }))
+    );
+    assert_eq!(
+        schema.attribute_type("App::User", "owner"),
+        Some(json!({ "type": "Entity", "name": "App::User" }))
+    );
+
+    assert_eq!(
+        schema.coerce_attribute("App::User", "ip", json!("10.0.0.1")),
+        json!({ "__extn": { "fn": "ip", "arg": "10.0.0.1" } })
+    );
+    assert_eq!(
+        schema.coerce_attribute("App::User", "limit", json!("1.5")),
+        json!({ "__extn": { "fn": "decimal", "arg": "1.5" } })
+    );
+}
@@ -0,0 +1,147 @@
+use std::collections::BTreeSet;
+use std::str::FromStr;
+use std::sync::Arc;
+
+use cedar_agent::data::memory::MemoryDataStore;
+use cedar_agent::data::EntitySnapshot;
+use cedar_agent::policies::memory::MemoryPolicyStore;
+use cedar_agent::schemas::authorization::DecisionRef;
+use cedar_agent::schemas::policies::Policy;
+use cedar_agent::test_cases::{TestCaseStore, TestGate};
+use cedar_agent::{DataStore, PolicyStore, StoreError};
+use cedar_policy::{Authorizer, PolicySet};
+use rocket::serde::json::serde_json::{from_value, json};
+
+use crate::services::utils::test_case;
+
+#[tokio::test]
+async fn test_policy_test_cases() {
+    let store = TestCaseStore::new();
+    let authorizer = Authorizer::new();
+    let entities = EntitySnapshot::empty();
+    let policies =
+        PolicySet::from_str(r#"permit(principal == User::"admin", action, resource);"#).unwrap();
+
+    let mut admin = test_case("User::\"admin\"", DecisionRef::Allow);
+    admin.expected_reasons = Some(BTreeSet::from(["policy0".to_string()]));
+    let admin = store
+        .put_test_case("admin".to_string(), admin)
+        .await
+        .unwrap();
+    assert_eq!(admin.id, "admin");
+    store
+        .put_test_case(
+            "guest".to_string(),
+            test_case("User::\"guest\"", DecisionRef::Deny),
+        )
+        .await
+        .unwrap();
+    assert!(store
+        .put_test_case("invalid".to_string(), test_case("guest", DecisionRef::Deny))
+        .await
+        .is_err());
+    assert_eq!(store.get_test_cases().await.len(), 2);
+
+    let report = store.run(&authorizer, &policies, entities.clone()).await;
+    assert_eq!((report.total, report.passed, report.failed), (2, 2, 0));
+
+    // Denying the admin makes its test case fail, allowing everyone fails the guest's
+    let empty = PolicySet::new();
+    let open = PolicySet::from_str("permit(principal, action, resource);").unwrap();
+    let regressions = store
+        .regressions(
+            &authorizer,
+            (&policies, entities.clone()),
+            (&empty, entities.clone()),
+        )
+        .await;
+    assert_eq!(regressions, vec!["admin".to_string()]);
+    let regressions = store
+        .regressions(
+            &authorizer,
+            (&policies, entities.clone()),
+            (&open, entities.clone()),
+        )
+        .await;
+    assert_eq!(regressions, vec!["guest".to_string()]);
+
+    // Test cases that already fail are not regressions
+    let report = store.run(&authorizer, &empty, entities.clone()).await;
+    assert!(!report.results[0].passed);
+    assert_eq!(report.results[0].decision, Some(DecisionRef::Deny));
+    assert!(store
+        .regressions(
+            &authorizer,
+            (&empty, entities.clone()),
+            (&empty, entities.clone()),
+        )
+        .await
+        .is_empty());
+
+    assert!(store.delete_test_case("admin").await.is_some());
+    assert!(store.get_test_case("admin").await.is_none());
+}
+
+/// Test that a policy write and an entity write that each keep the test cases passing
+/// are not both accepted when they fail a test case together, even when made at once
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn test_gate_across_stores() {
+    for _ in 0..50 {
+        let test_cases = Arc::new(TestCaseStore::new());
+        test_cases
+            .put_test_case(
+                "alice".to_string(),
+                test_case("User::\"alice\"", DecisionRef::Deny),
+            )
+            .await
+            .unwrap();
+        let gate = Arc::new(TestGate::new(test_cases));
+        let policy_store = Arc::new(MemoryPolicyStore::new().with_gate(gate.clone()));
+        let data_store = Arc::new(MemoryDataStore::new().with_gate(gate));
+
+        let policies = policy_store.clone();
+        let policy_write = tokio::spawn(async move {
+            policies
+                .create_policy(
+                    &Policy {
+                        id: "dev".to_string(),
+                        content: r#"permit(principal in Group::"dev", action, resource);"#
+                            .to_string(),
+                        ..Default::default()
+                    },
+                    None,
+                )
+                .await
+                .map(|_| ())
+        });
+        let data = data_store.clone();
+        let entity_write = tokio::spawn(async move {
+            data.upsert_entities(
+                from_value(json!([{
+                    "uid": { "type": "User", "id": "alice" },
+                    "attrs": {},
+                    "parents": [{ "type": "Group", "id": "dev" }]
+                }]))
+                .unwrap(),
+                None,
+            )
+            .await
+            .map(|_| ())
+        });
+        let results = [policy_write.await.unwrap(), entity_write.await.unwrap()];
+
+        let rejected = results
+            .iter()
+            .filter(|result| matches!(result, Err(StoreError::Conflict { .. })))
+            .count();
+        assert_eq!(rejected, 1, "{:?}", results);
+        assert_eq!(
+            policy_store.get_policies().await.is_empty(),
+            results[0].is_err()
+        );
+        assert_eq!(
+            data_store.get_entities().await.is_empty(),
+            results[1].is_err()
+        );
+    }
+}
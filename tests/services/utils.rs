@@ -1,8 +1,10 @@
 use rocket::serde::json::serde_json::from_str;
 
+use cedar_agent::schemas::authorization::{AuthorizationCall, DecisionRef};
 use cedar_agent::schemas::data::Entities;
 use cedar_agent::schemas::policies::Policy;
 use cedar_agent::schemas::schema::Schema;
+use cedar_agent::schemas::test_cases::TestCase;
 
 pub(crate) fn split_content(in_string: &str) -> (&str, &str) {
     let mut splitter = in_string.splitn(2, ':');
@@ -15,6 +17,7 @@ pub(crate) fn parse_error_policy() -> Policy {
     Policy {
         id: "error".to_string(),
         content: "error".to_string(),
+        ..Default::default()
     }
 }
 
@@ -23,6 +26,7 @@ pub(crate) fn approve_all_policy(id: Option<String>) -> Policy {
     Policy {
         id,
         content: "permit(principal,action,resource);".to_string(),
+        ..Default::default()
     }
 }
 
@@ -31,6 +35,7 @@ pub(crate) fn approve_admin_policy(id: Option<String>) -> Policy {
     Policy {
         id,
         content: "permit(principal == User::\"admin@domain.com\",action,resource);".to_string(),
+        ..Default::default()
     }
 }
 
@@ -41,6 +46,7 @@ pub(crate) fn schema_valid_policy(id: Option<String>) -> Policy {
         content:
             "permit(principal in Role::\"Editor\",action,resource == ResourceType::\"document\");"
                 .to_string(),
+        ..Default::default()
     }
 }
 
@@ -50,6 +56,7 @@ pub(crate) fn schema_invalid_policy(id: Option<String>) -> Policy {
         id,
         content: "permit(principal in Role::\"Editor\",action,resource == Document::\"document\");"
             .to_string(),
+        ..Default::default()
     }
 }
 
@@ -299,3 +306,22 @@ pub(crate) fn parse_error_schema() -> Schema {
     "#;
     from_str(schema_json).unwrap()
 }
+
+/// A test case of viewing the report by the principal
+pub(crate) fn test_case(principal: &str, expected_decision: DecisionRef) -> TestCase {
+    TestCase {
+        id: String::new(),
+        description: None,
+        request: AuthorizationCall::new(
+            Some(principal.to_string()),
+            Some("Action::\"view\"".to_string()),
+            Some("Document::\"report\"".to_string()),
+            None,
+            None,
+            None,
+            None,
+        ),
+        expected_decision,
+        expected_reasons: None,
+    }
+}